@@ -3,16 +3,30 @@ use soroban_sdk::{*};
 use soroban_sdk::xdr::*;
 // region global state defs
 pub type LobbyId = u32;
+pub type TournamentId = u32;
 pub type PawnId = u32;
-pub type HiddenRankHash = BytesN<16>; // always the hash of HiddenRank struct
-pub type HiddenMoveHash = BytesN<16>; // always the hash of HiddenMove struct
+pub type HiddenRankHash = Bytes; // always the hash of HiddenRank struct, 16 or 32 bytes depending on the lobby's HashScheme
+pub type HiddenMoveHash = Bytes; // always the hash of HiddenMove struct, 16 or 32 bytes depending on the lobby's HashScheme
 pub type SetupHash = BytesN<16>; // always the hash of Setup struct
 pub type BoardHash = BytesN<16>; // not used at the moment
-pub type MerkleHash = BytesN<16>;
+pub type MerkleHash = Bytes;
 pub type Rank = u32;
 pub type PackedTile = u32;
 pub type PackedPawn = u32;
 pub type PackedMove = u32;
+pub const DEFAULT_RATING: u32 = 1200;
+pub const RATING_K_FACTOR: i64 = 32;
+pub const MAX_QUEUE_SIZE: u32 = 32;
+pub const MAX_INVITED_GUESTS: u32 = 8;
+// domain tags prefixed to every hashed commitment outside of HashScheme::Legacy
+pub const HASH_TAG_LEAF: u8 = 0;
+pub const HASH_TAG_NODE: u8 = 1;
+pub const HASH_TAG_MOVE: u8 = 2;
+pub const HASH_TAG_ROOT: u8 = 3;
+pub const MAX_OPEN_LOBBIES: u32 = 64;
+pub const MAX_SERIES_TARGET_WINS: u32 = 4;
+pub const MAX_TOURNAMENT_PLAYERS: u32 = 16;
+pub const QUEUE_ENTRY_TTL: u32 = 720; // ledgers a queued player waits before their entry expires
 // endregion
 // region enums & errors
 #[contracterror]
@@ -43,6 +57,7 @@ pub enum Phase {
     RankProve = 4,
     Finished = 5,
     Aborted = 6,
+    SetupReveal = 7, // the game is over but its result waits on both players revealing their setup
 }
 #[contracttype]#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Subphase {
@@ -51,6 +66,51 @@ pub enum Subphase {
     Both = 2, // both must do something
     None = 3, // either nothing needs to be done, or a flag where both players have done something
 }
+#[contracttype]#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EndReason {
+    None = 0, // game has not ended
+    ThroneCaptured = 1,
+    NoMovablePawns = 2,
+    Blocked = 3,
+    Timeout = 4,
+    MoveHashMismatch = 5,
+    IllegalMove = 6,
+    InvalidRankProof = 7,
+    RankCountExceeded = 8,
+    LeftLobby = 9,
+    Surrender = 10,
+    InviteDeclined = 11,
+    DrawAgreed = 12,
+    TurnLimit = 13,
+    NoCaptureLimit = 14,
+    RepeatedMove = 15,
+    MutualBlock = 16,
+    SetupNotRevealed = 17,
+    FraudProven = 18,
+}
+#[contracttype]#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CombatOutcome {
+    Neither = 0, // both pawns survive
+    Attacker = 1, // the attacking pawn dies
+    Defender = 2, // the defending pawn dies
+    Both = 3, // both pawns die
+}
+#[contracttype]#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Adjudication {
+    Draw = 0, // the game is drawn
+    Material = 1, // the player with more surviving pawns wins, equal counts draw
+    DeadMovables = 2, // the player with fewer dead movable pawns wins, equal counts draw
+}
+#[contracttype]#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HashScheme {
+    Legacy = 0, // untagged sha256 truncated to 16 bytes and a bare tree root, the hashes clients computed before tagging.
+                // only the hashing is unchanged: proofs must still sit at the pawn's slot, and lobbies in flight when the
+                // contract is upgraded don't survive it since their records aren't versioned
+    Tagged = 1, // sha256 over the scheme version and a domain tag, truncated to 16 bytes
+    Tagged32 = 2, // like Tagged but keeps the full 32 byte digest
+    Bound = 3, // like Tagged, and move hashes are also bound to their lobby, turn and player
+    Bound32 = 4, // like Bound but keeps the full 32 byte digest
+}
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum UserIndex {
     Host = 0,
@@ -84,6 +144,17 @@ pub struct Pos {
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct User {
+    pub aborts: u32,
+    pub current_lobby: LobbyId,
+    pub draws: u32,
+    pub frauds: u32, // fraud proofs upheld against this player
+    pub games_completed: u32,
+    pub losses: u32,
+    pub rating: u32, // elo, only updated by finished games outside of dev_mode
+    pub wins: u32,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UserV0 {         // User as stored before ratings and results were tracked, upgraded on read
     pub current_lobby: LobbyId,
     pub games_completed: u32,
 }
@@ -129,6 +200,13 @@ pub struct PawnState {
     pub rank: Vec<Rank>,
     pub zz_revealed: bool,
 }
+#[contracttype]#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MovementProfile {
+    pub can_move: bool,
+    pub diagonal: bool, // may also step diagonally, ignored on hex boards
+    pub jump: bool, // may pass over pawns of either team while traveling
+    pub max_range: u32, // tiles traveled in a straight line, 1 to 16 if can_move
+}
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct UserMove {
     pub move_hashes: Vec<HiddenMoveHash>,
@@ -141,6 +219,7 @@ pub struct GameState {
     pub pawns: Vec<PackedPawn>,
     pub rank_roots: Vec<MerkleHash>,
     pub turn: u32,
+    pub turns_without_capture: u32, // resolved turns since a collision last killed a pawn
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LobbyParameters {
@@ -148,23 +227,101 @@ pub struct LobbyParameters {
     pub blitz_max_simultaneous_moves: u32,
     pub board: Board,
     pub board_hash: BoardHash, //deprecated
+    pub clock_budget: u32, // ledgers each player may spend on their own subphases, 0 for the fixed per-phase timeouts
+    pub clock_increment: u32, // ledgers added to a player's clock for each move they commit
+    pub combat_outcomes: Vec<CombatOutcome>, // indexed by attacker rank * 13 + defender rank, empty for the default rules
     pub dev_mode: bool,
+    pub disconnect_window: u32, // ledgers a disconnected player has to rejoin before the opponent can claim the win, 0 for the phase timeout
+    pub dispute_window: u32, // ledgers after a game finishes during which either player can submit a fraud proof, 0 to settle immediately
+    pub hash_scheme: HashScheme, // how move hashes, rank hashes and merkle nodes are computed
     pub host_team: u32,
+    pub invited_guests: Vec<Address>, // only these addresses may join, empty for an open lobby
     pub max_ranks: Vec<u32>,
+    pub max_repeated_moves: u32, // consecutive moves allowed for one pawn between the same two tiles, 0 for no limit
+    pub max_turns: u32, // resolved turns before the game is adjudicated, 0 for no limit
+    pub max_turns_without_capture: u32, // resolved turns without a capture before the game is adjudicated, 0 for no limit
+    pub movement_profiles: Vec<MovementProfile>, // indexed by rank, empty for the default rules
+    pub mutual_block_adjudication: Adjudication, // how a game ends when neither player has a legal move
     pub must_fill_all_tiles: bool, //deprecated
+    pub reap_grace_period: u32, // ledgers past the phase timeout before anyone may reap a lobby stuck in Subphase::Both
     pub security_mode: bool,
+    pub series_target_wins: u32, // game wins needed to take a series, 0 for a single game
+    pub setup_reveal: bool, // hold the result of a security_mode game until both players reveal their whole setup
+    pub stake_amount: i128, // paid into escrow by each player, 0 if stake_token is empty. covers the whole series
+    pub stake_token: Vec<Address>, // stellar asset contract for the stake, empty for no stake
+    pub turn_limit_adjudication: Adjudication, // how a game that hits max_turns or max_turns_without_capture ends
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LobbyInfo {
+    pub disconnected: Subphase, // the player who disconnected and hasn't rejoined, None if both are connected
+    pub disconnected_ledger_seq: u32, // when disconnected was set
+    pub dispute_deadline: u32, // first ledger a finished game can be settled on, 0 once settled or without a dispute_window
+    pub draw_offer: Subphase, // the player offering a draw until their next commit_move, None if no offer
+    pub end_player: Subphase, // the player responsible for end_reason, None if it applies to both or neither
+    pub end_reason: EndReason,
     pub guest_address: Vec<Address>,
+    pub guest_clock: u32, // ledgers left on the guest's clock as of last_edited_ledger_seq
     pub host_address: Vec<Address>,
+    pub host_clock: u32, // ledgers left on the host's clock as of last_edited_ledger_seq
     pub index: LobbyId,
     pub last_edited_ledger_seq: u32,
+    pub pending_winner: Subphase, // the result held during Phase::SetupReveal, None for a draw
     pub phase: Phase,
+    pub previous_lobby: LobbyId, // the finished lobby this one is a rematch of, 0 if none
+    pub rematch_offer: Subphase, // the player offering a rematch after the game finished, None if no offer
+    pub series: LobbyId, // id of the series this game belongs to, 0 if none
     pub subphase: Subphase,
+    pub tournament: TournamentId, // id of the tournament this game belongs to, 0 if none
 }
 // legacy collision summary removed
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenLobby {
+    pub blitz_interval: u32,
+    pub board_name: String,
+    pub board_size: Pos,
+    pub dev_mode: bool,
+    pub hex: bool,
+    pub host_team: u32,
+    pub lobby_info: LobbyInfo,
+    pub security_mode: bool,
+    pub stake_amount: i128,
+    pub stake_token: Vec<Address>,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Series {
+    pub draws: u32,
+    pub finished: bool,
+    pub index: LobbyId, // same as the id of the first lobby
+    pub lobbies: Vec<LobbyId>,
+    pub players: Vec<Address>, // host then guest of the first lobby
+    pub scores: Vec<u32>, // game wins in the same order as players
+    pub target_wins: u32, // the series also stops after target_wins * 3 games to bound draws
+    pub winner: Vec<Address>, // empty while running or if the series was drawn
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tournament {
+    pub entrants: Vec<Vec<Address>>, // current round in bracket order, slots 2i and 2i+1 play each other, empty for a bye
+    pub finished: bool,
+    pub index: TournamentId,
+    pub lobby_parameters: LobbyParameters,
+    pub matches: Vec<LobbyId>, // lobby for each pair of entrants, 0 if the pair needed no game
+    pub max_players: u32,
+    pub organizer: Address,
+    pub pending_matches: u32, // games in the current round that haven't ended yet
+    pub players: Vec<Address>, // registration order, sorted by seed once the bracket is generated
+    pub results: Vec<Vec<Address>>, // who advanced from each pair, empty if undecided or nobody advanced
+    pub round: u32, // 0 while registration is open
+    pub winner: Vec<Address>,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueueEntry {
+    pub address: Address,
+    pub enqueued_ledger_seq: u32,
+    pub lobby_parameters: LobbyParameters,
+    pub rating: u32,
+    pub rating_window: u32, // max rating difference this player accepts
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Collision {
     pub g_pawn_id: PawnId,
     pub h_pawn_id: PawnId,
@@ -183,6 +340,7 @@ pub struct History {
     pub final_game_state: Vec<GameState>,
     pub final_lobby_info: Vec<LobbyInfo>,
     pub lobby_parameters: LobbyParameters,
+    pub setup_reveals: Vec<Vec<HiddenRank>>, // every rank each player revealed after the game, host then guest
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PackedTurn {
@@ -196,7 +354,6 @@ pub struct HistoryTurns {
 // // region requests
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MakeLobbyReq {
-    pub lobby_id: LobbyId,
     pub parameters: LobbyParameters,
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
@@ -204,9 +361,35 @@ pub struct JoinLobbyReq {
     pub lobby_id: LobbyId,
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeclineInviteReq {
+    pub lobby_id: LobbyId,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MakeTournamentReq {
+    pub max_players: u32,
+    pub parameters: LobbyParameters,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TournamentReq {
+    pub tournament_id: TournamentId,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SessionReq {
+    pub lobby_id: LobbyId,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReapStaleLobbyReq {
+    pub lobby_id: LobbyId,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EnqueueReq {
+    pub parameters: LobbyParameters,
+    pub rating_window: u32,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CommitSetupReq {
     pub lobby_id: LobbyId,
-    pub rank_commitment_root: MerkleHash,
+    pub rank_commitment_root: MerkleHash, // rank_commitment of a tree with one leaf per pawn in ascending pawn_id order
     pub zz_hidden_ranks: Vec<HiddenRank>,
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
@@ -226,6 +409,22 @@ pub struct ProveRankReq {
     pub merkle_proofs: Vec<MerkleProof>,
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RevealSetupReq {
+    pub hidden_ranks: Vec<HiddenRank>, // one per pawn the player set up
+    pub lobby_id: LobbyId,
+    pub merkle_proofs: Vec<MerkleProof>,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DisputeReq {
+    pub hidden_ranks: Vec<HiddenRank>, // one or two openings of the opponent's rank commitment
+    pub lobby_id: LobbyId,
+    pub merkle_proofs: Vec<MerkleProof>,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettleGameReq {
+    pub lobby_id: LobbyId,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RedeemWinReq {
     pub lobby_id: LobbyId,
 }
@@ -233,6 +432,14 @@ pub struct RedeemWinReq {
 pub struct SurrenderReq {
     pub lobby_id: LobbyId,
 }
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RematchReq {
+    pub lobby_id: LobbyId,
+}
+#[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DrawReq {
+    pub lobby_id: LobbyId,
+}
 // // endregion
 // // region keys
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
@@ -244,6 +451,14 @@ pub enum DataKey {
     GameState(LobbyId), // game state
     History(LobbyId),
     HistoryTurns(LobbyId),
+    MoveHashes(LobbyId), // every move hash committed so far in a lobby whose HashScheme doesn't bind hashes to their turn
+    NextLobbyId, // counter for contract assigned lobby ids
+    OpenLobbies, // ids of lobbies waiting for a guest, oldest first
+    MatchmakingQueue, // addresses waiting for a match, oldest first
+    QueueEntry(Address),
+    Series(LobbyId),
+    NextTournamentId,
+    Tournament(TournamentId),
 }
 #[contracttype]#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum AnyValue {
@@ -281,6 +496,15 @@ impl Contract {
         temporary.extend_ttl(&DataKey::History(lobby_id), THRESHOLD, EXTEND);
         let persistent = e.storage().persistent();
         persistent.extend_ttl(&DataKey::HistoryTurns(lobby_id), THRESHOLD, EXTEND);
+        if persistent.has(&DataKey::MoveHashes(lobby_id)) {
+            persistent.extend_ttl(&DataKey::MoveHashes(lobby_id), THRESHOLD, EXTEND);
+        }
+        let lobby_info_opt: Option<LobbyInfo> = temporary.get(&DataKey::LobbyInfo(lobby_id));
+        if let Some(lobby_info) = lobby_info_opt {
+            if lobby_info.series != 0 {
+                persistent.extend_ttl(&DataKey::Series(lobby_info.series), THRESHOLD, EXTEND);
+            }
+        }
         Ok(())
     }
     pub fn create_entry(e: &Env, address: Address, key: DataKey, value: AnyValue) -> Result<(), Error> {
@@ -320,56 +544,43 @@ impl Contract {
         e.deployer().update_current_contract_wasm(new_wasm_hash);
         Ok(())
     }
-    pub fn make_lobby(e: &Env, address: Address, req: MakeLobbyReq) -> Result<(), Error> {
+    pub fn make_lobby(e: &Env, address: Address, req: MakeLobbyReq) -> Result<LobbyId, Error> {
         address.require_auth();
         let persistent = e.storage().persistent();
         let temporary = e.storage().temporary();
         let user_key = DataKey::User(address.clone());
-        let mut user =  persistent.get(&user_key).unwrap_or_else(|| {
-            User {
-                current_lobby: 0,
-                games_completed: 0,
-            }
-        });
-        let lobby_info_key = DataKey::LobbyInfo(req.lobby_id);
-        if temporary.has(&lobby_info_key) {
-            return Err(Error::AlreadyExists)
-        }
-        // Reject lobby id if a persistent history already exists for this key
-        if persistent.has(&DataKey::History(req.lobby_id)) {
-            return Err(Error::AlreadyExists)
-        }
-        // Also reject if persistent turns exist for this key
-        if persistent.has(&DataKey::HistoryTurns(req.lobby_id)) {
-            return Err(Error::AlreadyExists)
-        }
-        let lobby_parameters_key = DataKey::LobbyParameters(req.lobby_id);
+        let mut user = Self::load_user(e, &user_key).unwrap_or_else(Self::default_user);
         // validate full lobby parameters and board
         if !Self::validate_parameters(e, &req.parameters) {
             return Err(Error::InvalidArgs)
         }
+        if req.parameters.invited_guests.contains(&address) {
+            return Err(Error::InvalidArgs)
+        }
+        // escrow the host's stake
+        if let Some(stake_token) = req.parameters.stake_token.get(0) {
+            token::Client::new(e, &stake_token).transfer(&address, &e.current_contract_address(), &req.parameters.stake_amount);
+        }
         // update
-        let lobby_info = LobbyInfo {
-            guest_address: Vec::new(e),
-            host_address: Vec::from_array(e, [address]),
-            index: req.lobby_id,
-            last_edited_ledger_seq: e.ledger().sequence(),
-            phase: Phase::Lobby,
-            subphase: Subphase::Guest,
-        };
-        user.current_lobby = req.lobby_id;
+        let lobby_id = Self::allocate_lobby_id(e);
+        let lobby_info = Self::new_lobby_info(e, lobby_id, Vec::from_array(e, [address]), Vec::new(e));
+        user.current_lobby = lobby_id;
         // save
-        temporary.set(&lobby_info_key, &lobby_info);
-        temporary.set(&lobby_parameters_key, &req.parameters);
+        temporary.set(&DataKey::LobbyInfo(lobby_id), &lobby_info);
+        temporary.set(&DataKey::LobbyParameters(lobby_id), &req.parameters);
         persistent.set(&user_key, &user);
-        Ok(())
+        // invite-only lobbies aren't advertised
+        if req.parameters.invited_guests.is_empty() {
+            Self::add_open_lobby(e, lobby_id);
+        }
+        Ok(lobby_id)
     }
     pub fn leave_lobby(e: &Env, address: Address) -> Result<(), Error> {
         address.require_auth();
         let persistent = e.storage().persistent();
         let temporary = e.storage().temporary();
         let user_key = DataKey::User(address.clone());
-        let mut user: User = match persistent.get(&user_key) {
+        let mut user: User = match Self::load_user(e, &user_key) {
             Some(user) => user,
             None => return Err(Error::NotFound),
         };
@@ -384,55 +595,54 @@ impl Contract {
         let original_phase = lobby_info.phase;
         let user_index = Self::get_player_index(&address, &lobby_info);
         user.current_lobby = 0;
-        
-        // Always clear the leaving player's address from lobby
-        if lobby_info.host_address.contains(&address) {
-            lobby_info.host_address = Vec::new(e);
-        } else if lobby_info.guest_address.contains(&address) {
-            lobby_info.guest_address = Vec::new(e);
-        }
-        
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
         // Handle different phases
         match original_phase {
             Phase::Lobby => {
                 // In lobby phase: any user leaving aborts the game
-                lobby_info.phase = Phase::Aborted;
-                lobby_info.subphase = Subphase::None;
-                // Clear the other player too (kick everyone out)
-                if lobby_info.host_address.len() > 0 {
-                    lobby_info.host_address = Vec::new(e);
-                }
-                if lobby_info.guest_address.len() > 0 {
-                    lobby_info.guest_address = Vec::new(e);
-                }
+                Self::end_game(&mut lobby_info, Phase::Aborted, Subphase::None, EndReason::LeftLobby, Self::user_subphase_from_player_index(user_index));
+                Self::remove_open_lobby(e, lobby_id);
             },
             Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {
-                // Game in progress: leaving player loses, opponent wins
-                lobby_info.phase = Phase::Finished;
-                lobby_info.subphase = Self::opponent_subphase_from_player_index(user_index);
+                // Game in progress: leaving is an explicit forfeit, disconnect keeps the player attached instead
+                Self::end_game(&mut lobby_info, Phase::Finished, Self::opponent_subphase_from_player_index(user_index), EndReason::LeftLobby, Self::user_subphase_from_player_index(user_index));
             },
+            Phase::SetupReveal => {
+                // the result is still pending, the player has to reveal or wait out the reveal
+                return Err(Error::WrongPhase)
+            }
             Phase::Finished | Phase::Aborted => {
+                if lobby_info.dispute_deadline != 0 {
+                    // settling the result still needs both players attached
+                    return Err(Error::WrongPhase)
+                }
                 // Game already ended: just remove the user, don't change game state
-                // Address was already cleared above
+                // a pending rematch can't happen without both players so refund the offer
+                Self::cancel_rematch_offer(e, lobby_id, &mut lobby_info);
             }
         }
-        // save user (always clear their current_lobby)
+        // save user (always clear their current_lobby) before finalize updates their record
         persistent.set(&user_key, &user);
-        // always save lobby_info since we always clear the address
-        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
-        temporary.set(&DataKey::LobbyInfo(lobby_id), &lobby_info);
-        // If lobby ended due to leave, write final history snapshot
-        if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            let history_opt: Option<History> = temporary.get(&DataKey::History(lobby_id));
-            if let Some(mut history) = history_opt {
-                let game_state_opt: Option<GameState> = temporary.get(&DataKey::GameState(lobby_id));
-                if let Some(game_state) = game_state_opt {
-                    history.final_game_state.push_back(game_state);
-                    history.final_lobby_info.push_back(lobby_info.clone());
-                    temporary.set(&DataKey::History(lobby_id), &history);
-                }
+        // If lobby ended due to leave, finalize while both addresses are still attached
+        if original_phase != Phase::Finished && original_phase != Phase::Aborted {
+            temporary.set(&DataKey::LobbyInfo(lobby_id), &lobby_info);
+            Self::finalize_game(e, lobby_id, &mut lobby_info);
+        }
+        // Clear the leaving player's address from lobby, unless the result still has to be settled with it.
+        // settle_game releases them once it has paid out
+        if lobby_info.dispute_deadline == 0 {
+            if lobby_info.host_address.contains(&address) {
+                lobby_info.host_address = Vec::new(e);
+            } else if lobby_info.guest_address.contains(&address) {
+                lobby_info.guest_address = Vec::new(e);
             }
         }
+        if original_phase == Phase::Lobby {
+            // Clear the other player too (kick everyone out)
+            lobby_info.host_address = Vec::new(e);
+            lobby_info.guest_address = Vec::new(e);
+        }
+        temporary.set(&DataKey::LobbyInfo(lobby_id), &lobby_info);
         Ok(())
     }
     pub fn join_lobby(e: &Env, address: Address, req: JoinLobbyReq) -> Result<(), Error> {
@@ -440,12 +650,7 @@ impl Contract {
         let persistent = e.storage().persistent();
         let temporary = e.storage().temporary();
         let user_key = DataKey::User(address.clone());
-        let mut user =  persistent.get(&user_key).unwrap_or_else(|| {
-            User {
-                current_lobby: 0,
-                games_completed: 0,
-            }
-        });
+        let mut user = Self::load_user(e, &user_key).unwrap_or_else(Self::default_user);
         let old_lobby_id = user.current_lobby;
         if temporary.has(&DataKey::LobbyInfo(old_lobby_id)) {
             return Err(Error::Unauthorized)
@@ -456,47 +661,276 @@ impl Contract {
         if lobby_info.phase != Phase::Lobby || lobby_info.subphase != Subphase::Guest {
             return Err(Error::LobbyNotJoinable)
         }
+        if !lobby_parameters.invited_guests.is_empty() && !lobby_parameters.invited_guests.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
+        // escrow the guest's stake
+        if let Some(stake_token) = lobby_parameters.stake_token.get(0) {
+            token::Client::new(e, &stake_token).transfer(&address, &e.current_contract_address(), &lobby_parameters.stake_amount);
+        }
         // update
         user.current_lobby = req.lobby_id;
         lobby_info.guest_address = Vec::from_array(e, [address.clone()]);
         // start game automatically
-        lobby_info.phase = Phase::SetupCommit;
-        lobby_info.subphase = Subphase::Both;
-        // generate pawns
-        let mut pawns: Vec<PackedPawn> = Vec::new(e);
-        for packed_tile in lobby_parameters.board.tiles.iter() {
-            let tile = Self::unpack_tile(packed_tile);
-            if tile.setup == 0 || tile.setup == 1 {
-                let pawn_state = PawnState {
-                    alive: true,
-                    moved: false,
-                    moved_scout: false,
-                    pawn_id: Self::encode_pawn_id(tile.pos, tile.setup),
-                    pos: tile.pos,
-                    rank: Vec::new(e),
-                    zz_revealed: false,
-                };
-                pawns.push_back(Self::pack_pawn(pawn_state));
-            }
+        Self::start_game(e, req.lobby_id, &mut lobby_info, &lobby_parameters);
+        Self::remove_open_lobby(e, req.lobby_id);
+        persistent.set(&user_key, &user);
+        Self::extend_lobby_ttl(e, address, req.lobby_id)?;
+        Ok(())
+    }
+    pub fn offer_rematch(e: &Env, address: Address, req: RematchReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        if lobby_info.phase != Phase::Finished {
+            return Err(Error::WrongPhase)
         }
-        let game_state = GameState {
-            moves: Self::create_empty_moves(e),
-            pawns,
-            rank_roots: Vec::from_array(e, [MerkleHash::from_array(e, &[0u8; 16]), MerkleHash::from_array(e, &[0u8; 16]),]),
-            turn: 1, // turn has to start from 1
+        // both players must still be attached to the finished lobby
+        let user: User = Self::load_user(e, &DataKey::User(address.clone())).unwrap_or_else(Self::default_user);
+        if user.current_lobby != req.lobby_id {
+            return Err(Error::Unauthorized)
+        }
+        if lobby_info.host_address.is_empty() || lobby_info.guest_address.is_empty() {
+            return Err(Error::LobbyNotJoinable)
+        }
+        if lobby_info.rematch_offer != Subphase::None {
+            return Err(Error::AlreadyExists)
+        }
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        // escrow the offering player's stake for the next game now since they won't sign the accept
+        if let Some(stake_token) = lobby_parameters.stake_token.get(0) {
+            token::Client::new(e, &stake_token).transfer(&address, &e.current_contract_address(), &lobby_parameters.stake_amount);
+        }
+        let user_index = Self::get_player_index(&address, &lobby_info);
+        lobby_info.rematch_offer = Self::user_subphase_from_player_index(user_index);
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        Self::extend_lobby_ttl(e, address, req.lobby_id)?;
+        Ok(lobby_info)
+    }
+    pub fn accept_rematch(e: &Env, address: Address, req: RematchReq) -> Result<LobbyId, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut old_lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
         };
-        lobby_info.phase = Phase::SetupCommit;
-        lobby_info.subphase = Subphase::Both;
-        // save
+        if old_lobby_info.phase != Phase::Finished {
+            return Err(Error::WrongPhase)
+        }
+        let user: User = Self::load_user(e, &DataKey::User(address.clone())).unwrap_or_else(Self::default_user);
+        if user.current_lobby != req.lobby_id {
+            return Err(Error::Unauthorized)
+        }
+        let user_index = Self::get_player_index(&address, &old_lobby_info);
+        if old_lobby_info.rematch_offer != Self::opponent_subphase_from_player_index(user_index) {
+            return Err(Error::WrongSubphase)
+        }
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        if let Some(stake_token) = lobby_parameters.stake_token.get(0) {
+            token::Client::new(e, &stake_token).transfer(&address, &e.current_contract_address(), &lobby_parameters.stake_amount);
+        }
+        // the offer is consumed by the new lobby
+        old_lobby_info.rematch_offer = Subphase::None;
+        old_lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &old_lobby_info);
+        // swap sides so the previous guest sets up as host
+        let mut lobby_info = Self::new_lobby_info(e, 0, old_lobby_info.guest_address, old_lobby_info.host_address);
+        lobby_info.previous_lobby = req.lobby_id;
+        Self::start_paired_game(e, &mut lobby_info, &lobby_parameters)
+    }
+    pub fn decline_invite(e: &Env, address: Address, req: DeclineInviteReq) -> Result<(), Error> {
+        address.require_auth();
+        let persistent = e.storage().persistent();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        if !lobby_parameters.invited_guests.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
+        if lobby_info.phase != Phase::Lobby {
+            return Err(Error::WrongPhase)
+        }
         lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        // the empty guest seat takes the blame so the host isn't charged an abort
+        Self::end_game(&mut lobby_info, Phase::Aborted, Subphase::None, EndReason::InviteDeclined, Subphase::Guest);
+        Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        // release the host like leave_lobby would
+        if let Some(host_address) = lobby_info.host_address.get(0) {
+            let host_key = DataKey::User(host_address);
+            let mut host_user: User = Self::load_user(e, &host_key).unwrap_or_else(Self::default_user);
+            if host_user.current_lobby == req.lobby_id {
+                host_user.current_lobby = 0;
+                persistent.set(&host_key, &host_user);
+            }
+        }
+        lobby_info.host_address = Vec::new(e);
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
-        temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
-        // Initialize empty history and persistent turns for this lobby
-        temporary.set(&DataKey::History(req.lobby_id), &History { start_game_state: game_state.clone(), start_lobby_info: lobby_info.clone(), final_game_state: Vec::new(e), final_lobby_info: Vec::new(e), lobby_parameters: lobby_parameters.clone() });
+        Ok(())
+    }
+    pub fn enqueue(e: &Env, address: Address, req: EnqueueReq) -> Result<LobbyId, Error> {
+        address.require_auth();
         let persistent = e.storage().persistent();
-        persistent.set(&DataKey::HistoryTurns(req.lobby_id), &HistoryTurns { turns: Vec::new(e) });
-        persistent.set(&user_key, &user);
-        Self::extend_lobby_ttl(e, address, req.lobby_id)?;
+        let temporary = e.storage().temporary();
+        let user_key = DataKey::User(address.clone());
+        let user = Self::load_user(e, &user_key).unwrap_or_else(Self::default_user);
+        if temporary.has(&DataKey::LobbyInfo(user.current_lobby)) {
+            return Err(Error::Unauthorized)
+        }
+        let entry_key = DataKey::QueueEntry(address.clone());
+        if temporary.has(&entry_key) {
+            return Err(Error::AlreadyExists)
+        }
+        if !Self::validate_parameters(e, &req.parameters) {
+            return Err(Error::InvalidArgs)
+        }
+        // stakes can't be escrowed for a queued player who isn't signing the matching call, and invites don't apply to strangers
+        if !req.parameters.stake_token.is_empty() || !req.parameters.invited_guests.is_empty() {
+            return Err(Error::InvalidArgs)
+        }
+        let queue: Vec<Address> = temporary.get(&DataKey::MatchmakingQueue).unwrap_or_else(|| Vec::new(e));
+        let mut remaining: Vec<Address> = Vec::new(e);
+        let mut opponent_opt: Option<QueueEntry> = None;
+        for queued_address in queue.iter() {
+            // entries that outlived their ttl are gone and get pruned here
+            let queued_key = DataKey::QueueEntry(queued_address.clone());
+            let queued: QueueEntry = match temporary.get(&queued_key) {
+                Some(queued) => queued,
+                None => continue,
+            };
+            if opponent_opt.is_none() && Self::is_queue_match(&queued, user.rating, &req) {
+                // drop players that found a game some other way while they were waiting
+                let queued_user: User = Self::load_user(e, &DataKey::User(queued_address.clone())).unwrap_or_else(Self::default_user);
+                temporary.remove(&queued_key);
+                if !temporary.has(&DataKey::LobbyInfo(queued_user.current_lobby)) {
+                    opponent_opt = Some(queued);
+                }
+                continue;
+            }
+            remaining.push_back(queued_address);
+        }
+        let opponent = match opponent_opt {
+            Some(opponent) => opponent,
+            None => {
+                if remaining.len() >= MAX_QUEUE_SIZE {
+                    return Err(Error::LobbyNotJoinable)
+                }
+                let entry = QueueEntry {
+                    address: address.clone(),
+                    enqueued_ledger_seq: e.ledger().sequence(),
+                    lobby_parameters: req.parameters,
+                    rating: user.rating,
+                    rating_window: req.rating_window,
+                };
+                remaining.push_back(address);
+                temporary.set(&entry_key, &entry);
+                temporary.set(&DataKey::MatchmakingQueue, &remaining);
+                temporary.extend_ttl(&entry_key, QUEUE_ENTRY_TTL, QUEUE_ENTRY_TTL);
+                temporary.extend_ttl(&DataKey::MatchmakingQueue, QUEUE_ENTRY_TTL, QUEUE_ENTRY_TTL);
+                persistent.set(&user_key, &user);
+                return Ok(0)
+            }
+        };
+        temporary.set(&DataKey::MatchmakingQueue, &remaining);
+        // the player who waited hosts with their template
+        let mut lobby_info = Self::new_lobby_info(e, 0, Vec::from_array(e, [opponent.address]), Vec::from_array(e, [address]));
+        Self::start_paired_game(e, &mut lobby_info, &opponent.lobby_parameters)
+    }
+    pub fn dequeue(e: &Env, address: Address) -> Result<(), Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let entry_key = DataKey::QueueEntry(address.clone());
+        if !temporary.has(&entry_key) {
+            return Err(Error::NotFound)
+        }
+        temporary.remove(&entry_key);
+        let queue: Vec<Address> = temporary.get(&DataKey::MatchmakingQueue).unwrap_or_else(|| Vec::new(e));
+        if let Some(index) = queue.first_index_of(&address) {
+            let mut queue = queue;
+            queue.remove(index);
+            temporary.set(&DataKey::MatchmakingQueue, &queue);
+        }
+        Ok(())
+    }
+    pub fn make_tournament(e: &Env, address: Address, req: MakeTournamentReq) -> Result<TournamentId, Error> {
+        address.require_auth();
+        if !Self::validate_parameters(e, &req.parameters) {
+            return Err(Error::InvalidArgs)
+        }
+        // every game is created by the contract so nothing can be escrowed, invited or chained
+        if !req.parameters.stake_token.is_empty() || !req.parameters.invited_guests.is_empty() || req.parameters.series_target_wins != 0 {
+            return Err(Error::InvalidArgs)
+        }
+        if req.max_players < 2 || req.max_players > MAX_TOURNAMENT_PLAYERS {
+            return Err(Error::InvalidArgs)
+        }
+        let instance = e.storage().instance();
+        let tournament_id: TournamentId = instance.get(&DataKey::NextTournamentId).unwrap_or(1);
+        instance.set(&DataKey::NextTournamentId, &(tournament_id + 1));
+        let tournament = Tournament {
+            entrants: Vec::new(e),
+            finished: false,
+            index: tournament_id,
+            lobby_parameters: req.parameters,
+            matches: Vec::new(e),
+            max_players: req.max_players,
+            organizer: address,
+            pending_matches: 0,
+            players: Vec::new(e),
+            results: Vec::new(e),
+            round: 0,
+            winner: Vec::new(e),
+        };
+        e.storage().persistent().set(&DataKey::Tournament(tournament_id), &tournament);
+        Ok(tournament_id)
+    }
+    pub fn join_tournament(e: &Env, address: Address, req: TournamentReq) -> Result<(), Error> {
+        address.require_auth();
+        let persistent = e.storage().persistent();
+        let tournament_key = DataKey::Tournament(req.tournament_id);
+        let mut tournament: Tournament = match persistent.get(&tournament_key) {
+            Some(tournament) => tournament,
+            None => return Err(Error::NotFound),
+        };
+        if tournament.round != 0 {
+            return Err(Error::WrongPhase)
+        }
+        if tournament.players.contains(&address) {
+            return Err(Error::AlreadyExists)
+        }
+        tournament.players.push_back(address);
+        // registration closes by itself once the bracket is full
+        if tournament.players.len() == tournament.max_players {
+            Self::start_tournament(e, &mut tournament);
+        }
+        persistent.set(&tournament_key, &tournament);
+        Ok(())
+    }
+    pub fn close_tournament(e: &Env, address: Address, req: TournamentReq) -> Result<(), Error> {
+        address.require_auth();
+        let persistent = e.storage().persistent();
+        let tournament_key = DataKey::Tournament(req.tournament_id);
+        let mut tournament: Tournament = match persistent.get(&tournament_key) {
+            Some(tournament) => tournament,
+            None => return Err(Error::NotFound),
+        };
+        if tournament.organizer != address {
+            return Err(Error::Unauthorized)
+        }
+        if tournament.round != 0 {
+            return Err(Error::WrongPhase)
+        }
+        if tournament.players.len() < 2 {
+            return Err(Error::InvalidArgs)
+        }
+        Self::start_tournament(e, &mut tournament);
+        persistent.set(&tournament_key, &tournament);
         Ok(())
     }
     pub fn commit_setup(e: &Env, address: Address, req: CommitSetupReq) -> Result<(), Error> {
@@ -504,6 +938,7 @@ impl Contract {
         let temporary = e.storage().temporary();
         let mut lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
         let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
         let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
         let u_index = Self::get_player_index(&address, &lobby_info);
         if lobby_info.phase != Phase::SetupCommit {
@@ -540,11 +975,16 @@ impl Contract {
                 return Err(Error::InvalidArgs)
             }
         }
+        if req.rank_commitment_root.len() != Self::hash_length(lobby_parameters.hash_scheme) {
+            return Err(Error::InvalidArgs)
+        }
         let next_subphase = Self::next_subphase(&lobby_info.subphase, u_index)?;
         game_state.rank_roots.set(u_index.u32(), req.rank_commitment_root);
         if next_subphase == Subphase::None {
             lobby_info.phase = Phase::MoveCommit;
             lobby_info.subphase = Subphase::Both;
+            // an offer made during setup doesn't carry over into the game
+            lobby_info.draw_offer = Subphase::None;
         }
         else {
             lobby_info.subphase = next_subphase;
@@ -560,25 +1000,17 @@ impl Contract {
         let mut lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
         let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
         let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
         if !lobby_parameters.security_mode {
             return Err(Error::WrongSecurityMode)
         }
-        Self::commit_move_internal(&address, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
+        Self::commit_move_internal(e, &address, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
         lobby_info.last_edited_ledger_seq = e.ledger().sequence();
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
         temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
         // Write final history at game end for insecure flow
         if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            let mut history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap_or(History {
-                start_game_state: game_state.clone(),
-                start_lobby_info: lobby_info.clone(),
-                final_game_state: Vec::new(e),
-                final_lobby_info: Vec::new(e),
-                lobby_parameters: lobby_parameters.clone(),
-            });
-            history.final_game_state.push_back(game_state.clone());
-            history.final_lobby_info.push_back(lobby_info.clone());
-            temporary.set(&DataKey::History(req.lobby_id), &history);
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
         }
         Ok(lobby_info)
     }
@@ -588,23 +1020,15 @@ impl Contract {
         let mut lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
         let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
         let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
-        Self::commit_move_internal(&address, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
+        Self::commit_move_internal(e, &address, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
         Self::prove_move_internal(e, &address, req.lobby_id, &req2, &mut lobby_info, &mut game_state, &lobby_parameters)?;
         lobby_info.last_edited_ledger_seq = e.ledger().sequence();
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
         temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
         // Write final history at game end (covers insecure flow)
         if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            let mut history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap_or(History {
-                start_game_state: game_state.clone(),
-                start_lobby_info: lobby_info.clone(),
-                final_game_state: Vec::new(e),
-                final_lobby_info: Vec::new(e),
-                lobby_parameters: lobby_parameters.clone(),
-            });
-            history.final_game_state.push_back(game_state.clone());
-            history.final_lobby_info.push_back(lobby_info.clone());
-            temporary.set(&DataKey::History(req.lobby_id), &history);
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
         }
         Ok(lobby_info)
     }
@@ -614,18 +1038,16 @@ impl Contract {
         let mut lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
         let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
         let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
         if !lobby_parameters.security_mode {
             return Err(Error::WrongSecurityMode)
         }
-        let mut history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap();
         Self::prove_move_internal(e, &address, req.lobby_id, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
         lobby_info.last_edited_ledger_seq = e.ledger().sequence();
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
         temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
         if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            history.final_game_state.push_back(game_state.clone());
-            history.final_lobby_info.push_back(lobby_info.clone());
-            temporary.set(&DataKey::History(req.lobby_id), &history);
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
         }
         // HistoryTurns are written in record_packed_moves_for_completed_turn
         Ok(lobby_info)
@@ -636,10 +1058,10 @@ impl Contract {
         let mut lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
         let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
         let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
         if !lobby_parameters.security_mode {
             return Err(Error::WrongSecurityMode)
         }
-        let mut history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap();
         Self::prove_move_internal(e, &address, req.lobby_id, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
         // skip if game was aborted due to an illegal move
         if lobby_info.phase != Phase::Aborted {
@@ -649,9 +1071,7 @@ impl Contract {
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
         temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
         if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            history.final_game_state.push_back(game_state.clone());
-            history.final_lobby_info.push_back(lobby_info.clone());
-            temporary.set(&DataKey::History(req.lobby_id), &history);
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
         }
         // HistoryTurns are written in record_packed_moves_for_completed_turn
         Ok(lobby_info)
@@ -662,71 +1082,444 @@ impl Contract {
         let mut lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
         let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
         let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
-        let mut history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
         Self::prove_rank_internal(e, &address, req.lobby_id, &req, &mut lobby_info, &mut game_state, &lobby_parameters)?;
         lobby_info.last_edited_ledger_seq = e.ledger().sequence();
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
         temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
         // Write final history at game end
         if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            history.final_game_state.push_back(game_state.clone());
-            history.final_lobby_info.push_back(lobby_info.clone());
-            temporary.set(&DataKey::History(req.lobby_id), &history);
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
         }
         Ok(lobby_info)
     }
-    pub fn redeem_win(e: &Env, address: Address, req: RedeemWinReq) -> Result<LobbyInfo, Error> {
+    pub fn reveal_setup(e: &Env, address: Address, req: RevealSetupReq) -> Result<LobbyInfo, Error> {
         address.require_auth();
         let temporary = e.storage().temporary();
         let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
             Some(info) => info,
             None => return Err(Error::NotFound),
         };
-        let time_limit_ledger_seq = match &lobby_info.phase {
-            Phase::Lobby | Phase::Finished | Phase::Aborted => {
+        if lobby_info.phase != Phase::SetupReveal {
+            return Err(Error::WrongPhase)
+        }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        let next_subphase = Self::next_subphase(&lobby_info.subphase, u_index)?;
+        let game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
+        let rank_slots = Self::get_rank_slots(e, &pawns_map, u_index);
+        // every pawn the player set up, each exactly once
+        let mut revealed_ranks: Map<PawnId, Rank> = Map::new(e);
+        for hidden_rank in req.hidden_ranks.iter() {
+            revealed_ranks.set(hidden_rank.pawn_id, hidden_rank.rank);
+        }
+        if req.hidden_ranks.len() != rank_slots.len() || revealed_ranks.len() != rank_slots.len() {
+            return Err(Error::InvalidArgs)
+        }
+        let rank_root = game_state.rank_roots.get_unchecked(u_index.u32());
+        if !Self::validate_rank_proofs(e, lobby_parameters.hash_scheme, &req.hidden_ranks, &req.merkle_proofs, &rank_slots, &rank_root) {
+            return Err(Error::HashFail)
+        }
+        // the whole army has to match max_ranks exactly, which also means exactly one throne
+        let mut rank_counts = [0u32; 13];
+        for (_, rank) in revealed_ranks.iter() {
+            if rank as usize >= rank_counts.len() {
+                return Err(Error::InvalidArgs)
+            }
+            rank_counts[rank as usize] += 1;
+        }
+        for (rank_index, max_rank) in lobby_parameters.max_ranks.iter().enumerate() {
+            if rank_counts[rank_index] != max_rank {
+                log!(e, "reveal_setup: failed [rank count doesn't match max_ranks]", rank_index as u32, rank_counts[rank_index], max_rank);
+                return Err(Error::InvalidArgs)
+            }
+        }
+        let mut history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap();
+        history.setup_reveals.set(u_index.u32(), req.hidden_ranks);
+        temporary.set(&DataKey::History(req.lobby_id), &history);
+        lobby_info.subphase = next_subphase;
+        if next_subphase == Subphase::None {
+            Self::resolve_setup_reveal(&mut lobby_info);
+        }
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        }
+        Ok(lobby_info)
+    }
+    pub fn dispute(e: &Env, address: Address, req: DisputeReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let persistent = e.storage().persistent();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        if lobby_info.phase != Phase::Finished || lobby_info.dispute_deadline == 0 {
+            return Err(Error::WrongPhase)
+        }
+        if e.ledger().sequence() >= lobby_info.dispute_deadline {
+            return Err(Error::InvalidArgs)
+        }
+        if !lobby_info.host_address.contains(&address) && !lobby_info.guest_address.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
+        if req.hidden_ranks.is_empty() || req.hidden_ranks.len() > 2 {
+            return Err(Error::InvalidArgs)
+        }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        let o_index = Self::get_opponent_index(&address, &lobby_info);
+        let game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
+        let rank_slots = Self::get_rank_slots(e, &pawns_map, o_index);
+        let rank_root = game_state.rank_roots.get_unchecked(o_index.u32());
+        if !Self::validate_rank_proofs(e, lobby_parameters.hash_scheme, &req.hidden_ranks, &req.merkle_proofs, &rank_slots, &rank_root) {
+            return Err(Error::HashFail)
+        }
+        // every opening is genuine, so any two that disagree about a pawn prove the commitment was equivocal
+        let history: History = temporary.get(&DataKey::History(req.lobby_id)).unwrap();
+        let setup_reveal = history.setup_reveals.get_unchecked(o_index.u32());
+        let first = req.hidden_ranks.get_unchecked(0);
+        let mut fraud = match req.hidden_ranks.get(1) {
+            Some(second) => first.pawn_id == second.pawn_id && first != second,
+            None => false,
+        };
+        for hidden_rank in req.hidden_ranks.iter() {
+            let (_, pawn) = pawns_map.get_unchecked(hidden_rank.pawn_id);
+            if let Some(proven_rank) = pawn.rank.get(0) {
+                fraud |= proven_rank != hidden_rank.rank;
+            }
+            for revealed_rank in setup_reveal.iter() {
+                fraud |= revealed_rank.pawn_id == hidden_rank.pawn_id && revealed_rank != hidden_rank;
+            }
+        }
+        if !fraud {
+            return Err(Error::InvalidArgs)
+        }
+        // the cheater loses whatever the board said, and the honest player's win is settled without another window
+        Self::end_game(&mut lobby_info, Phase::Finished, Self::user_subphase_from_player_index(u_index), EndReason::FraudProven, Self::user_subphase_from_player_index(o_index));
+        lobby_info.dispute_deadline = 0;
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        // players who left mid-game stay attached until settlement, so both addresses are still here
+        let cheater_address = if o_index == UserIndex::Host { lobby_info.host_address.get_unchecked(0) } else { lobby_info.guest_address.get_unchecked(0) };
+        let cheater_key = DataKey::User(cheater_address);
+        let mut cheater: User = Self::load_user(e, &cheater_key).unwrap_or_else(Self::default_user);
+        cheater.frauds += 1;
+        persistent.set(&cheater_key, &cheater);
+        Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        Self::release_departed_players(e, req.lobby_id, &mut lobby_info);
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        Ok(lobby_info)
+    }
+    pub fn redeem_win(e: &Env, address: Address, req: RedeemWinReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        let time_limit_ledger_seq = match Self::phase_time_limit(lobby_info.phase) {
+            Some(time_limit) => time_limit,
+            None => return Err(Error::WrongPhase),
+        };
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        let mut o_index = Self::opponent_subphase_from_player_index(u_index);
+        let mut winner = Self::user_subphase_from_player_index(u_index);
+        if lobby_info.phase == Phase::SetupReveal {
+            // the reveal isn't on the clock or the disconnect window, the opponent just has to run out the time limit
+            if lobby_info.subphase != o_index {
+                return Err(Error::WrongSubphase)
+            }
+            if e.ledger().sequence() < lobby_info.last_edited_ledger_seq + time_limit_ledger_seq {
+                return Err(Error::InvalidArgs)
+            }
+            Self::resolve_setup_reveal(&mut lobby_info);
+            lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+            temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+            return Ok(lobby_info)
+        }
+        if lobby_info.disconnected == o_index {
+            // a disconnected opponent forfeits once their window to rejoin runs out, whoever owes an action
+            if e.ledger().sequence() < Self::disconnect_deadline(&lobby_info, &lobby_parameters) {
+                return Err(Error::InvalidArgs)
+            }
+        } else if lobby_parameters.clock_budget > 0 {
+            // with time controls the opponent loses as soon as their clock runs out, even if both owe an action
+            if lobby_info.subphase != o_index && lobby_info.subphase != Subphase::Both {
+                return Err(Error::WrongSubphase)
+            }
+            if Self::remaining_clock(e, &lobby_info, Self::get_opponent_index(&address, &lobby_info)) > 0 {
+                return Err(Error::InvalidArgs)
+            }
+            // if the caller's clock ran out too neither side can claim the other flagged first
+            if Self::remaining_clock(e, &lobby_info, u_index) == 0 {
+                winner = Subphase::None;
+                o_index = Subphase::None;
+            }
+        } else {
+            if lobby_info.subphase != o_index {
+                return Err(Error::WrongSubphase)
+            }
+            // check if called too early
+            if e.ledger().sequence() < lobby_info.last_edited_ledger_seq + time_limit_ledger_seq {
+                return Err(Error::InvalidArgs)
+            }
+        }
+        // Handle SetupCommit differently - abort the game instead of declaring winner
+        if lobby_info.phase == Phase::SetupCommit {
+            Self::end_game(&mut lobby_info, Phase::Aborted, Subphase::None, EndReason::Timeout, o_index);
+        } else {
+            Self::finish_game(&mut lobby_info, &lobby_parameters, winner, EndReason::Timeout, o_index);
+        }
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        // This call always ends the game, though the result may still wait on a setup reveal
+        if lobby_info.phase != Phase::SetupReveal {
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        }
+        Ok(lobby_info)
+    }
+    pub fn disconnect(e: &Env, address: Address, req: SessionReq) -> Result<LobbyInfo, Error> {
+        // marks the player as away without forfeiting. leave_lobby is the explicit forfeit
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        match &lobby_info.phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted | Phase::SetupReveal => {
                 return Err(Error::WrongPhase)
             }
-            Phase::SetupCommit => 100,
-            Phase::MoveCommit => 100,
-            Phase::MoveProve => 40,
-            Phase::RankProve => 40,
+            Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {}
+        }
+        if !lobby_info.host_address.contains(&address) && !lobby_info.guest_address.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
+        if lobby_info.disconnected != Subphase::None {
+            return Err(Error::AlreadyExists)
+        }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        // last_edited_ledger_seq is left alone so disconnecting never buys time on the phase timeout
+        lobby_info.disconnected = Self::user_subphase_from_player_index(u_index);
+        lobby_info.disconnected_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        Ok(lobby_info)
+    }
+    pub fn rejoin(e: &Env, address: Address, req: SessionReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
         };
+        match &lobby_info.phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted | Phase::SetupReveal => {
+                return Err(Error::WrongPhase)
+            }
+            Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {}
+        }
+        if !lobby_info.host_address.contains(&address) && !lobby_info.guest_address.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
         let u_index = Self::get_player_index(&address, &lobby_info);
-        let o_index = Self::opponent_subphase_from_player_index(u_index);
-        if lobby_info.subphase != o_index {
+        if lobby_info.disconnected != Self::user_subphase_from_player_index(u_index) {
             return Err(Error::WrongSubphase)
         }
-        // check if called too early
-        if e.ledger().sequence() < lobby_info.last_edited_ledger_seq + time_limit_ledger_seq {
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        if e.ledger().sequence() >= Self::disconnect_deadline(&lobby_info, &lobby_parameters) {
             return Err(Error::InvalidArgs)
         }
-        // Handle SetupCommit differently - abort the game instead of declaring winner
+        lobby_info.disconnected = Subphase::None;
+        lobby_info.disconnected_ledger_seq = 0;
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        Ok(lobby_info)
+    }
+    pub fn reap_stale_lobby(e: &Env, req: ReapStaleLobbyReq) -> Result<LobbyInfo, Error> {
+        // when both players owe an action neither can redeem_win, so anyone may close the lobby once it has sat long enough
+        let persistent = e.storage().persistent();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        let time_limit_ledger_seq = match Self::phase_time_limit(lobby_info.phase) {
+            Some(time_limit) => time_limit,
+            None => return Err(Error::WrongPhase),
+        };
+        if lobby_info.subphase != Subphase::Both {
+            return Err(Error::WrongSubphase)
+        }
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        if e.ledger().sequence() < lobby_info.last_edited_ledger_seq + time_limit_ledger_seq + lobby_parameters.reap_grace_period {
+            return Err(Error::InvalidArgs)
+        }
+        // nobody is at fault: an unfinished setup is aborted, a game in progress is drawn
         if lobby_info.phase == Phase::SetupCommit {
-            lobby_info.phase = Phase::Aborted;
-            lobby_info.subphase = Subphase::None;
+            Self::end_game(&mut lobby_info, Phase::Aborted, Subphase::None, EndReason::Timeout, Subphase::None);
+        } else if lobby_info.phase == Phase::SetupReveal {
+            // except after the game, where neither player revealing voids the result
+            Self::resolve_setup_reveal(&mut lobby_info);
         } else {
-            lobby_info.phase = Phase::Finished;
-            lobby_info.subphase = Self::user_subphase_from_player_index(u_index);
+            Self::end_game(&mut lobby_info, Phase::Finished, Subphase::None, EndReason::Timeout, Subphase::None);
         }
         lobby_info.last_edited_ledger_seq = e.ledger().sequence();
         temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
-        // If this call ends the game, append final history snapshot
-        if lobby_info.phase == Phase::Finished || lobby_info.phase == Phase::Aborted {
-            let history_opt: Option<History> = temporary.get(&DataKey::History(req.lobby_id));
-            if let Some(mut history) = history_opt {
-                let game_state_opt: Option<GameState> = temporary.get(&DataKey::GameState(req.lobby_id));
-                if let Some(game_state) = game_state_opt {
-                    history.final_game_state.push_back(game_state);
-                    history.final_lobby_info.push_back(lobby_info.clone());
-                    temporary.set(&DataKey::History(req.lobby_id), &history);
-                }
+        Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        // release both players like leave_lobby would, unless a series already moved them on
+        for address in lobby_info.host_address.iter().chain(lobby_info.guest_address.iter()) {
+            let user_key = DataKey::User(address);
+            let mut user: User = Self::load_user(e, &user_key).unwrap_or_else(Self::default_user);
+            if user.current_lobby == req.lobby_id {
+                user.current_lobby = 0;
+                persistent.set(&user_key, &user);
+            }
+        }
+        Ok(lobby_info)
+    }
+    pub fn settle_game(e: &Env, req: SettleGameReq) -> Result<LobbyInfo, Error> {
+        // once the dispute window closes anyone may pay out the result
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        if lobby_info.phase != Phase::Finished || lobby_info.dispute_deadline == 0 {
+            return Err(Error::WrongPhase)
+        }
+        if e.ledger().sequence() < lobby_info.dispute_deadline {
+            return Err(Error::InvalidArgs)
+        }
+        lobby_info.dispute_deadline = 0;
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::settle_game_result(e, req.lobby_id, &lobby_info, &lobby_parameters);
+        Self::release_departed_players(e, req.lobby_id, &mut lobby_info);
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        Ok(lobby_info)
+    }
+    pub fn surrender(e: &Env, address: Address, req: SurrenderReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        match &lobby_info.phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted | Phase::SetupReveal => {
+                return Err(Error::WrongPhase)
+            }
+            Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {}
+        }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
+        Self::close_interrupted_turn(e, req.lobby_id, &mut game_state);
+        // unlike leave_lobby, both players stay attached so they can view the result and rematch
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::finish_game(&mut lobby_info, &lobby_parameters, Self::opponent_subphase_from_player_index(u_index), EndReason::Surrender, Self::user_subphase_from_player_index(u_index));
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
+        if lobby_info.phase == Phase::Finished {
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        }
+        Ok(lobby_info)
+    }
+    pub fn offer_draw(e: &Env, address: Address, req: DrawReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        match &lobby_info.phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted | Phase::SetupReveal => {
+                return Err(Error::WrongPhase)
+            }
+            Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {}
+        }
+        if !lobby_info.host_address.contains(&address) && !lobby_info.guest_address.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
+        if lobby_info.draw_offer != Subphase::None {
+            return Err(Error::AlreadyExists)
+        }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
+        lobby_info.draw_offer = Self::user_subphase_from_player_index(u_index);
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        Ok(lobby_info)
+    }
+    pub fn accept_draw(e: &Env, address: Address, req: DrawReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        match &lobby_info.phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted | Phase::SetupReveal => {
+                return Err(Error::WrongPhase)
+            }
+            Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {}
+        }
+        if !lobby_info.host_address.contains(&address) && !lobby_info.guest_address.contains(&address) {
+            return Err(Error::Unauthorized)
+        }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        if lobby_info.draw_offer != Self::opponent_subphase_from_player_index(u_index) {
+            return Err(Error::WrongSubphase)
+        }
+        let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
+        Self::close_interrupted_turn(e, req.lobby_id, &mut game_state);
+        lobby_info.draw_offer = Subphase::None;
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::finish_game(&mut lobby_info, &lobby_parameters, Subphase::None, EndReason::DrawAgreed, Subphase::None);
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
+        temporary.set(&DataKey::GameState(req.lobby_id), &game_state);
+        if lobby_info.phase == Phase::Finished {
+            Self::finalize_game(e, req.lobby_id, &mut lobby_info);
+        }
+        Ok(lobby_info)
+    }
+    pub fn decline_draw(e: &Env, address: Address, req: DrawReq) -> Result<LobbyInfo, Error> {
+        address.require_auth();
+        let temporary = e.storage().temporary();
+        let mut lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(req.lobby_id)) {
+            Some(info) => info,
+            None => return Err(Error::NotFound),
+        };
+        match &lobby_info.phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted | Phase::SetupReveal => {
+                return Err(Error::WrongPhase)
             }
+            Phase::SetupCommit | Phase::MoveCommit | Phase::MoveProve | Phase::RankProve => {}
+        }
+        if !lobby_info.host_address.contains(&address) && !lobby_info.guest_address.contains(&address) {
+            return Err(Error::Unauthorized)
         }
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        if lobby_info.draw_offer != Self::opponent_subphase_from_player_index(u_index) {
+            return Err(Error::WrongSubphase)
+        }
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        Self::charge_clocks(e, &mut lobby_info, &lobby_parameters);
+        lobby_info.draw_offer = Subphase::None;
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(req.lobby_id), &lobby_info);
         Ok(lobby_info)
     }
     // endregion
     // region internal
-pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby_info: &mut LobbyInfo, game_state: &mut GameState, lobby_parameters: &LobbyParameters) -> Result<(), Error> {
+pub(crate) fn commit_move_internal(e: &Env, address: &Address, req: &CommitMoveReq, lobby_info: &mut LobbyInfo, game_state: &mut GameState, lobby_parameters: &LobbyParameters) -> Result<(), Error> {
         let u_index = Self::get_player_index(address, &lobby_info);
         if lobby_info.phase != Phase::MoveCommit {
             return Err(Error::WrongPhase)
@@ -745,9 +1538,36 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
                 return Err(Error::InvalidArgs)
             }
         }
+        // a duplicate, echoed or replayed commitment would let one proof answer for another.
+        // bound schemes tie each hash to its turn, the others have to remember every hash committed so far
+        let o_move = game_state.moves.get_unchecked(Self::get_opponent_index(address, lobby_info).u32());
+        let is_bound = Self::is_bound_scheme(lobby_parameters.hash_scheme);
+        let persistent = e.storage().persistent();
+        let mut committed_hashes: Map<HiddenMoveHash, u32> = if is_bound { Map::new(e) } else { persistent.get(&DataKey::MoveHashes(req.lobby_id)).unwrap_or(Map::new(e)) };
+        for (i, move_hash) in req.move_hashes.iter().enumerate() {
+            if move_hash.len() != Self::hash_length(lobby_parameters.hash_scheme) {
+                return Err(Error::InvalidArgs)
+            }
+            if req.move_hashes.first_index_of(&move_hash) != Some(i as u32) || o_move.move_hashes.contains(&move_hash) || committed_hashes.contains_key(move_hash.clone()) {
+                return Err(Error::InvalidArgs)
+            }
+        }
+        if !is_bound {
+            for move_hash in req.move_hashes.iter() {
+                committed_hashes.set(move_hash, game_state.turn);
+            }
+            persistent.set(&DataKey::MoveHashes(req.lobby_id), &committed_hashes);
+        }
         let mut u_move = game_state.moves.get_unchecked(u_index.u32());
         // update
         u_move.move_hashes = req.move_hashes.clone();
+        // a pending draw offer lapses once the offering player moves on
+        if lobby_info.draw_offer == Self::user_subphase_from_player_index(u_index) {
+            lobby_info.draw_offer = Subphase::None;
+        }
+        if lobby_parameters.clock_budget > 0 {
+            if u_index == UserIndex::Host { lobby_info.host_clock += lobby_parameters.clock_increment; } else { lobby_info.guest_clock += lobby_parameters.clock_increment; }
+        }
         if next_subphase == Subphase::None {
             if lobby_parameters.security_mode {
                 lobby_info.phase = Phase::MoveProve;
@@ -782,42 +1602,33 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             // Inline comments allowed for this session
             // 1) Hash count must match
             if req.move_proofs.len() != u_move.move_hashes.len() {
-                Self::abort_illegal_move(lobby_info, u_index);
+                Self::abort_illegal_move(lobby_info, u_index, EndReason::MoveHashMismatch);
                 return Ok(())
             }
             // Precompute maps for quick lookups
             let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
-            let mut passable_map: Map<Pos, bool> = Map::new(e);
-            for packed_tile in lobby_parameters.board.tiles.iter() {
-                let tile = Self::unpack_tile(packed_tile);
-                passable_map.set(tile.pos, tile.passable);
-            }
-            let mut pos_to_pawn_id: Map<Pos, PawnId> = Map::new(e);
-            for (_, (_, ps)) in pawns_map.iter() {
-                if ps.alive {
-                    pos_to_pawn_id.set(ps.pos, ps.pawn_id);
-                }
-            }
+            let (passable_map, pos_to_pawn_id) = Self::movement_maps(e, lobby_parameters, &pawns_map);
             // Single-pass validation
             let mut validated_proofs = Vec::new(e);
             let mut seen_ids: Vec<PawnId> = Vec::new(e);
             let mut target_counts: Map<Pos, u32> = Map::new(e);
             let mut moving_ids: Vec<PawnId> = Vec::new(e);
             let mut are_moves_valid = true;
+            let mut invalid_reason = EndReason::IllegalMove;
             for i in 0..req.move_proofs.len() {
                 let move_proof = req.move_proofs.get_unchecked(i);
                 // hash check first
                 let expected_hash_opt = u_move.move_hashes.get(i);
                 if expected_hash_opt.is_none() {
                     are_moves_valid = false;
+                    invalid_reason = EndReason::MoveHashMismatch;
                     break;
                 }
                 let expected_hash = expected_hash_opt.unwrap();
-                let serialized = move_proof.clone().to_xdr(e);
-                let full_hash = e.crypto().sha256(&serialized).to_bytes().to_array();
-                let submitted_hash = HiddenMoveHash::from_array(e, &full_hash[0..16].try_into().unwrap());
+                let submitted_hash = Self::move_commitment(e, lobby_parameters.hash_scheme, lobby_id, game_state.turn, u_index, &move_proof);
                 if expected_hash != submitted_hash {
                     are_moves_valid = false;
+                    invalid_reason = EndReason::MoveHashMismatch;
                     break;
                 }
                 // unique pawn and unique target per user
@@ -868,38 +1679,19 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
                     are_moves_valid = false;
                     break;
                 }
-                // rank-based legality
-                if let Some(rank) = pawn.rank.get(0) {
-                    if [0u32, 11u32].contains(&rank) {
-                        are_moves_valid = false; break;
-                    }
-                    let num_neighbor_directions: usize = if lobby_parameters.board.hex { 6 } else { 4 };
-                    let max_traversal_steps: i32 = if rank == 2 { 16 } else { 1 };
-                    let mut found_straight_path = false;
-                    'direction_scan: for direction_index in 0..num_neighbor_directions {
-                        let mut current_pos = move_proof.start_pos;
-                        let mut neighbor_positions = [Pos { x: -42069, y: -42069 }; 6];
-                        for _step in 0..max_traversal_steps {
-                            Self::get_neighbors(&current_pos, lobby_parameters.board.hex, &mut neighbor_positions);
-                            let next_pos = neighbor_positions[direction_index];
-                            if next_pos.x == -42069 { break; }
-                            if next_pos == move_proof.target_pos { found_straight_path = true; break 'direction_scan; }
-                            if match passable_map.get(next_pos) { Some(p) => !p, None => true } { break; }
-                            // Existing occupant blocks
-                            if let Some(_occupant_id) = pos_to_pawn_id.get(next_pos) { break; }
-                            // Allied incoming move to this tile blocks line-of-sight only for single-step movers
-                            if max_traversal_steps == 1 {
-                                if let Some(cnt) = target_counts.get(next_pos) { if cnt > 0 { break; } }
-                            }
-                            current_pos = next_pos;
-                        }
-                    }
-                    if !found_straight_path { are_moves_valid = false; break; }
+                // rank-based legality. a hidden pawn gets the most permissive profile in play for now
+                // and has to prove its rank later if only some ranks could have made the move
+                let profile = match pawn.rank.get(0) {
+                    Some(rank) => Self::movement_profile(lobby_parameters, rank),
+                    None => Self::unknown_movement_profile(lobby_parameters),
+                };
+                if !Self::is_move_legal(lobby_parameters, &profile, &move_proof, &passable_map, &pos_to_pawn_id, &target_counts) {
+                    are_moves_valid = false; break;
                 }
                 validated_proofs.push_back(move_proof);
             }
             if !are_moves_valid {
-                Self::abort_illegal_move(lobby_info, u_index);
+                Self::abort_illegal_move(lobby_info, u_index, invalid_reason);
                 return Ok(())
             }
             // disallow targeting ally-occupied tiles
@@ -911,10 +1703,20 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
                     }
                 }
                 if violation {
-                    Self::abort_illegal_move(lobby_info, u_index);
+                    Self::abort_illegal_move(lobby_info, u_index, EndReason::IllegalMove);
                     return Ok(())
                 }
             }
+            // disallow shuttling a pawn back and forth past the lobby's limit
+            if lobby_parameters.max_repeated_moves > 0 {
+                let history_turns: HistoryTurns = e.storage().persistent().get(&DataKey::HistoryTurns(lobby_id)).unwrap_or(HistoryTurns { turns: Vec::new(e) });
+                for mp in validated_proofs.iter() {
+                    if Self::count_repeated_moves(&history_turns, &mp) >= lobby_parameters.max_repeated_moves {
+                        Self::abort_illegal_move(lobby_info, u_index, EndReason::RepeatedMove);
+                        return Ok(())
+                    }
+                }
+            }
             // finalize
             u_move.move_proofs = validated_proofs;
             game_state.moves.set(u_index.u32(), u_move);
@@ -935,7 +1737,7 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         if next_subphase == Subphase::None {
             let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
             let collisions = Self::compute_collisions(e, game_state, &pawns_map);
-            let (h_needed_rank_proofs, g_needed_rank_proofs) = Self::derive_needed_rank_proofs(e, &collisions, &pawns_map);
+            let (h_needed_rank_proofs, g_needed_rank_proofs) = Self::derive_needed_rank_proofs(e, game_state, &collisions, &pawns_map, lobby_parameters);
             let mut h_move = game_state.moves.get_unchecked(UserIndex::Host.u32());
             let mut g_move = game_state.moves.get_unchecked(UserIndex::Guest.u32());
             h_move.needed_rank_proofs = h_needed_rank_proofs;
@@ -945,11 +1747,13 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             // check if rank proofs are needed
             match (game_state.moves.get_unchecked(u_index.u32()).needed_rank_proofs.is_empty(), game_state.moves.get_unchecked(o_index.u32()).needed_rank_proofs.is_empty()) {
                 (true, true) => {
-                    Self::complete_move_resolution(e, game_state, Some(collisions), &pawns_map);
-                    let winner = Self::check_game_over(e, &game_state, &lobby_parameters);
+                    Self::complete_move_resolution(e, game_state, Some(collisions), &pawns_map, lobby_parameters);
+                    let (mut winner, mut end_reason) = Self::check_game_over(e, &game_state, &lobby_parameters);
+                    if winner == Subphase::Both {
+                        (winner, end_reason) = Self::check_turn_limit(e, game_state, lobby_parameters);
+                    }
                     if winner != Subphase::Both {
-                        lobby_info.phase = Phase::Finished;
-                        lobby_info.subphase = winner;
+                        Self::finish_game(lobby_info, lobby_parameters, winner, end_reason, Self::loser_from_winner(winner));
                         game_state.turn += 1;
                         // Record the final completed turn as well
                         Self::record_packed_moves_for_completed_turn(e, lobby_id, game_state);
@@ -995,13 +1799,16 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         {
             let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
             let rank_root = game_state.rank_roots.get_unchecked(u_index.u32());
-            if !Self::validate_rank_proofs(e, &req.hidden_ranks, &req.merkle_proofs, &rank_root) {
+            let rank_slots = Self::get_rank_slots(e, &pawns_map, u_index);
+            if !Self::validate_rank_proofs(e, lobby_parameters.hash_scheme, &req.hidden_ranks, &req.merkle_proofs, &rank_slots, &rank_root) {
                 // abort the game
-                lobby_info.phase = Phase::Aborted;
-                lobby_info.subphase = Self::opponent_subphase_from_player_index(u_index);
+                Self::end_game(lobby_info, Phase::Aborted, Self::opponent_subphase_from_player_index(u_index), EndReason::InvalidRankProof, Self::user_subphase_from_player_index(u_index));
                 return Ok(())
             }
             for hidden_rank in req.hidden_ranks.iter() {
+                if !u_move.needed_rank_proofs.contains(hidden_rank.pawn_id) {
+                    return Err(Error::InvalidArgs)
+                }
                 let (pawn_index, mut pawn) = pawns_map.get_unchecked(hidden_rank.pawn_id);
                 pawn.rank = Vec::from_array(e, [hidden_rank.rank]);
                 log!(e, "prove_rank_internal: pawn rank set to ", pawn.pawn_id, hidden_rank.rank);
@@ -1015,8 +1822,24 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
                 let revealed_rank_count = revealed_rank_counts[rank_index];
                 if revealed_rank_count > max_rank {
                     // abort the game
-                    lobby_info.phase = Phase::Aborted;
-                    lobby_info.subphase = Self::opponent_subphase_from_player_index(u_index);
+                    Self::end_game(lobby_info, Phase::Aborted, Self::opponent_subphase_from_player_index(u_index), EndReason::RankCountExceeded, Self::user_subphase_from_player_index(u_index));
+                    return Ok(())
+                }
+            }
+        }
+        {
+            // moves made while the rank was hidden are held to the proven rank's profile now
+            let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
+            let (passable_map, pos_to_pawn_id) = Self::movement_maps(e, lobby_parameters, &pawns_map);
+            let target_counts = Self::move_target_counts(e, &u_move);
+            for move_proof in u_move.move_proofs.iter() {
+                if !req.hidden_ranks.iter().any(|hidden_rank| hidden_rank.pawn_id == move_proof.pawn_id) {
+                    continue;
+                }
+                let (_, pawn) = pawns_map.get_unchecked(move_proof.pawn_id);
+                let profile = Self::movement_profile(lobby_parameters, pawn.rank.get_unchecked(0));
+                if !Self::is_move_legal(lobby_parameters, &profile, &move_proof, &passable_map, &pos_to_pawn_id, &target_counts) {
+                    Self::abort_illegal_move(lobby_info, u_index, EndReason::IllegalMove);
                     return Ok(())
                 }
             }
@@ -1030,11 +1853,13 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         let next_subphase = Self::next_subphase(&lobby_info.subphase, u_index)?;
         if next_subphase == Subphase::None {
             let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
-            Self::complete_move_resolution(e, game_state, None, &pawns_map);
-            let winner = Self::check_game_over(e, &game_state, &lobby_parameters);
+            Self::complete_move_resolution(e, game_state, None, &pawns_map, lobby_parameters);
+            let (mut winner, mut end_reason) = Self::check_game_over(e, &game_state, &lobby_parameters);
+            if winner == Subphase::Both {
+                (winner, end_reason) = Self::check_turn_limit(e, game_state, lobby_parameters);
+            }
             if winner != Subphase::Both {
-                lobby_info.phase = Phase::Finished;
-                lobby_info.subphase = winner;
+                Self::finish_game(lobby_info, lobby_parameters, winner, end_reason, Self::loser_from_winner(winner));
                 game_state.turn += 1;
                 // Record the final completed turn as well
                 Self::record_packed_moves_for_completed_turn(e, lobby_id, game_state);
@@ -1050,92 +1875,620 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             // Standard case: advance to next player's turn
             lobby_info.subphase = next_subphase;
         }
-        Ok(())
+        Ok(())
+    }
+    // endregion
+    // region read-only contract simulation
+    pub fn simulate_collisions(e: &Env, address: Address, req: ProveMoveReq) -> Result<UserMove, Error> {
+        let temporary = e.storage().temporary();
+        let lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
+        let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
+        let u_index = Self::get_player_index(&address, &lobby_info);
+        if lobby_info.phase != Phase::MoveProve {
+            return Err(Error::WrongPhase)
+        }
+        if lobby_info.subphase == Subphase::Both
+        {
+            // move_hash with all 8s and empty needed_rank_proofs signifies that simulate_collisions is being called too early
+            return Ok(UserMove {
+                move_hashes: Vec::from_array(e, [HiddenRankHash::from_array(e, &[8u8; 16])]),
+                move_proofs: Vec::new(e),
+                needed_rank_proofs: Vec::new(e),
+            })
+        }
+        let next_subphase = Self::next_subphase(&lobby_info.subphase, u_index)?;
+        if next_subphase != Subphase::None
+        {
+            return Err(Error::WrongSubphase)
+        }
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(req.lobby_id)).unwrap();
+        // we don't bother to validate the move
+        let mut u_move = game_state.moves.get_unchecked(u_index.u32());
+        u_move.move_proofs = req.move_proofs;
+        game_state.moves.set(u_index.u32(), u_move);
+        let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
+        let collisions = Self::compute_collisions(e, &game_state, &pawns_map);
+        let (h_needed_rank_proofs, g_needed_rank_proofs) = Self::derive_needed_rank_proofs(e, &game_state, &collisions, &pawns_map, &lobby_parameters);
+        let mut updated_move = game_state.moves.get_unchecked(u_index.u32());
+        if u_index == UserIndex::Host { updated_move.needed_rank_proofs = h_needed_rank_proofs; }
+        else { updated_move.needed_rank_proofs = g_needed_rank_proofs; }
+        Ok(updated_move)
+    }
+    pub fn list_open_lobbies(e: &Env, cursor: LobbyId, limit: u32) -> Vec<OpenLobby> {
+        // returns joinable lobbies with ids above cursor, pass the last returned id to get the next page
+        let temporary = e.storage().temporary();
+        let open_lobbies: Vec<LobbyId> = e.storage().instance().get(&DataKey::OpenLobbies).unwrap_or_else(|| Vec::new(e));
+        let mut page: Vec<OpenLobby> = Vec::new(e);
+        for lobby_id in open_lobbies.iter() {
+            if page.len() >= limit.min(MAX_OPEN_LOBBIES) {
+                break;
+            }
+            if lobby_id <= cursor {
+                continue;
+            }
+            let lobby_info: LobbyInfo = match temporary.get(&DataKey::LobbyInfo(lobby_id)) {
+                Some(info) => info,
+                None => continue,
+            };
+            if lobby_info.phase != Phase::Lobby || lobby_info.subphase != Subphase::Guest {
+                continue;
+            }
+            let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(lobby_id)).unwrap();
+            page.push_back(OpenLobby {
+                blitz_interval: lobby_parameters.blitz_interval,
+                board_name: lobby_parameters.board.name,
+                board_size: lobby_parameters.board.size,
+                dev_mode: lobby_parameters.dev_mode,
+                hex: lobby_parameters.board.hex,
+                host_team: lobby_parameters.host_team,
+                lobby_info,
+                security_mode: lobby_parameters.security_mode,
+                stake_amount: lobby_parameters.stake_amount,
+                stake_token: lobby_parameters.stake_token,
+            });
+        }
+        page
+    }
+    pub fn get_series(e: &Env, series_id: LobbyId) -> Result<Series, Error> {
+        match e.storage().persistent().get(&DataKey::Series(series_id)) {
+            Some(series) => Ok(series),
+            None => Err(Error::NotFound),
+        }
+    }
+    pub fn get_tournament(e: &Env, tournament_id: TournamentId) -> Result<Tournament, Error> {
+        match e.storage().persistent().get(&DataKey::Tournament(tournament_id)) {
+            Some(tournament) => Ok(tournament),
+            None => Err(Error::NotFound),
+        }
+    }
+    pub fn get_user(e: &Env, address: Address) -> Result<User, Error> {
+        match Self::load_user(e, &DataKey::User(address)) {
+            Some(user) => Ok(user),
+            None => Err(Error::NotFound),
+        }
+    }
+    // endregion
+    // region state mutators
+    pub(crate) fn resolve_collision(combat_outcomes: &Vec<CombatOutcome>, attacker: &mut PawnState, defender: &mut PawnState) -> () {
+        let attacker_rank = attacker.rank.get_unchecked(0);
+        let defender_rank = defender.rank.get_unchecked(0);
+        attacker.zz_revealed = true;
+        defender.zz_revealed = true;
+        let (attacker_dies, defender_dies) = Self::attack_deaths(combat_outcomes, attacker_rank, defender_rank);
+        if attacker_dies { attacker.alive = false; }
+        if defender_dies { defender.alive = false; }
+    }
+    pub(crate) fn attack_deaths(combat_outcomes: &Vec<CombatOutcome>, attacker_rank: Rank, defender_rank: Rank) -> (bool, bool) {
+        match Self::combat_outcome(combat_outcomes, attacker_rank, defender_rank) {
+            CombatOutcome::Neither => (false, false),
+            CombatOutcome::Attacker => (true, false),
+            CombatOutcome::Defender => (false, true),
+            CombatOutcome::Both => (true, true),
+        }
+    }
+    pub(crate) fn apply_move_to_pawn(move_proof: &HiddenMove, pawn: &mut PawnState) -> () {
+        if pawn.pos != move_proof.target_pos {
+            pawn.moved = true;
+        }
+        if Self::is_scout_move(move_proof) {
+            pawn.moved_scout = true;
+        }
+        pawn.pos = move_proof.target_pos;
+    }
+    pub(crate) fn abort_illegal_move(lobby_info: &mut LobbyInfo, offending_index: UserIndex, end_reason: EndReason) -> () {
+        Self::end_game(lobby_info, Phase::Aborted, Self::opponent_subphase_from_player_index(offending_index), end_reason, Self::user_subphase_from_player_index(offending_index));
+    }
+    pub(crate) fn end_game(lobby_info: &mut LobbyInfo, phase: Phase, subphase: Subphase, end_reason: EndReason, end_player: Subphase) {
+        // every terminal transition goes through here so the reason is never left unset
+        lobby_info.phase = phase;
+        lobby_info.subphase = subphase;
+        lobby_info.end_reason = end_reason;
+        lobby_info.end_player = end_player;
+        lobby_info.draw_offer = Subphase::None;
+    }
+    pub(crate) fn finish_game(lobby_info: &mut LobbyInfo, lobby_parameters: &LobbyParameters, winner: Subphase, end_reason: EndReason, end_player: Subphase) {
+        // nobody has seen the whole board in security_mode, so with setup_reveal the result waits until both setups are checked
+        if lobby_parameters.security_mode && lobby_parameters.setup_reveal {
+            lobby_info.pending_winner = winner;
+            Self::end_game(lobby_info, Phase::SetupReveal, Subphase::Both, end_reason, end_player);
+        } else {
+            Self::end_game(lobby_info, Phase::Finished, winner, end_reason, end_player);
+        }
+    }
+    pub(crate) fn resolve_setup_reveal(lobby_info: &mut LobbyInfo) {
+        // the subphase is whoever hasn't revealed. a loser keeps their loss, anyone else who didn't reveal forfeits
+        let winner = lobby_info.pending_winner;
+        let end_reason = lobby_info.end_reason;
+        let end_player = lobby_info.end_player;
+        let missing = lobby_info.subphase;
+        if missing == Subphase::None || missing == Self::loser_from_winner(winner) {
+            Self::end_game(lobby_info, Phase::Finished, winner, end_reason, end_player);
+        } else if missing == Subphase::Both {
+            Self::end_game(lobby_info, Phase::Aborted, Subphase::None, EndReason::SetupNotRevealed, Subphase::None);
+        } else {
+            Self::end_game(lobby_info, Phase::Aborted, Self::loser_from_winner(missing), EndReason::SetupNotRevealed, missing);
+        }
+    }
+    pub(crate) fn new_lobby_info(e: &Env, lobby_id: LobbyId, host_address: Vec<Address>, guest_address: Vec<Address>) -> LobbyInfo {
+        LobbyInfo {
+            disconnected: Subphase::None,
+            disconnected_ledger_seq: 0,
+            dispute_deadline: 0,
+            draw_offer: Subphase::None,
+            end_player: Subphase::None,
+            end_reason: EndReason::None,
+            guest_address,
+            guest_clock: 0,
+            host_address,
+            host_clock: 0,
+            index: lobby_id,
+            last_edited_ledger_seq: e.ledger().sequence(),
+            pending_winner: Subphase::None,
+            phase: Phase::Lobby,
+            previous_lobby: 0,
+            rematch_offer: Subphase::None,
+            series: 0,
+            subphase: Subphase::Guest,
+            tournament: 0,
+        }
+    }
+    pub(crate) fn start_paired_game(e: &Env, lobby_info: &mut LobbyInfo, lobby_parameters: &LobbyParameters) -> Result<LobbyId, Error> {
+        // creates and starts a lobby for two known players without either of them joining
+        let persistent = e.storage().persistent();
+        for address in lobby_info.host_address.iter().chain(lobby_info.guest_address.iter()) {
+            if Self::is_in_active_lobby(e, &address) {
+                return Err(Error::Unauthorized)
+            }
+        }
+        let lobby_id = Self::allocate_lobby_id(e);
+        lobby_info.index = lobby_id;
+        e.storage().temporary().set(&DataKey::LobbyParameters(lobby_id), lobby_parameters);
+        Self::start_game(e, lobby_id, lobby_info, lobby_parameters);
+        for address in lobby_info.host_address.iter().chain(lobby_info.guest_address.iter()) {
+            let user_key = DataKey::User(address);
+            let mut user: User = Self::load_user(e, &user_key).unwrap_or_else(Self::default_user);
+            user.current_lobby = lobby_id;
+            persistent.set(&user_key, &user);
+        }
+        Self::extend_lobby_ttl(e, lobby_info.host_address.get_unchecked(0), lobby_id).unwrap();
+        Ok(lobby_id)
+    }
+    pub(crate) fn is_in_active_lobby(e: &Env, address: &Address) -> bool {
+        // finished lobbies don't count, players stay attached to them for rematches and history
+        let user = Self::load_user(e, &DataKey::User(address.clone())).unwrap_or_else(Self::default_user);
+        match e.storage().temporary().get::<DataKey, LobbyInfo>(&DataKey::LobbyInfo(user.current_lobby)) {
+            Some(lobby_info) => lobby_info.phase != Phase::Finished && lobby_info.phase != Phase::Aborted,
+            None => false,
+        }
+    }
+    pub(crate) fn start_game(e: &Env, lobby_id: LobbyId, lobby_info: &mut LobbyInfo, lobby_parameters: &LobbyParameters) {
+        let temporary = e.storage().temporary();
+        // generate pawns
+        let mut pawns: Vec<PackedPawn> = Vec::new(e);
+        for packed_tile in lobby_parameters.board.tiles.iter() {
+            let tile = Self::unpack_tile(packed_tile);
+            if tile.setup == 0 || tile.setup == 1 {
+                let pawn_state = PawnState {
+                    alive: true,
+                    moved: false,
+                    moved_scout: false,
+                    pawn_id: Self::encode_pawn_id(tile.pos, Self::get_setup_owner(tile.setup, lobby_parameters.host_team).u32()),
+                    pos: tile.pos,
+                    rank: Vec::new(e),
+                    zz_revealed: false,
+                };
+                pawns.push_back(Self::pack_pawn(pawn_state));
+            }
+        }
+        let game_state = GameState {
+            moves: Self::create_empty_moves(e),
+            pawns,
+            rank_roots: Vec::from_array(e, [MerkleHash::from_array(e, &[0u8; 16]), MerkleHash::from_array(e, &[0u8; 16]),]),
+            turn: 1, // turn has to start from 1
+            turns_without_capture: 0,
+        };
+        lobby_info.phase = Phase::SetupCommit;
+        lobby_info.subphase = Subphase::Both;
+        let persistent = e.storage().persistent();
+        // a series starts with the first game where both players are known
+        if lobby_parameters.series_target_wins > 0 && lobby_info.series == 0 {
+            lobby_info.series = lobby_id;
+            let series = Series {
+                draws: 0,
+                finished: false,
+                index: lobby_id,
+                lobbies: Vec::from_array(e, [lobby_id]),
+                players: Vec::from_array(e, [lobby_info.host_address.get_unchecked(0), lobby_info.guest_address.get_unchecked(0)]),
+                scores: Vec::from_array(e, [0u32, 0u32]),
+                target_wins: lobby_parameters.series_target_wins,
+                winner: Vec::new(e),
+            };
+            persistent.set(&DataKey::Series(lobby_id), &series);
+        }
+        // save
+        lobby_info.host_clock = lobby_parameters.clock_budget;
+        lobby_info.guest_clock = lobby_parameters.clock_budget;
+        lobby_info.last_edited_ledger_seq = e.ledger().sequence();
+        temporary.set(&DataKey::LobbyInfo(lobby_id), lobby_info);
+        temporary.set(&DataKey::GameState(lobby_id), &game_state);
+        // Initialize empty history and persistent turns for this lobby
+        temporary.set(&DataKey::History(lobby_id), &History { start_game_state: game_state.clone(), start_lobby_info: lobby_info.clone(), final_game_state: Vec::new(e), final_lobby_info: Vec::new(e), lobby_parameters: lobby_parameters.clone(), setup_reveals: Vec::from_array(e, [Vec::new(e), Vec::new(e)]) });
+        persistent.set(&DataKey::HistoryTurns(lobby_id), &HistoryTurns { turns: Vec::new(e) });
     }
-    // endregion
-    // region read-only contract simulation
-    pub fn simulate_collisions(e: &Env, address: Address, req: ProveMoveReq) -> Result<UserMove, Error> {
+    pub(crate) fn allocate_lobby_id(e: &Env) -> LobbyId {
+        let instance = e.storage().instance();
+        let mut lobby_id: LobbyId = instance.get(&DataKey::NextLobbyId).unwrap_or(1);
+        // skip ids that clients picked for their own lobbies before ids were assigned by the contract
+        while e.storage().temporary().has(&DataKey::LobbyInfo(lobby_id)) || e.storage().persistent().has(&DataKey::History(lobby_id)) || e.storage().persistent().has(&DataKey::HistoryTurns(lobby_id)) {
+            lobby_id += 1;
+        }
+        instance.set(&DataKey::NextLobbyId, &(lobby_id + 1));
+        lobby_id
+    }
+    pub(crate) fn add_open_lobby(e: &Env, lobby_id: LobbyId) {
+        let instance = e.storage().instance();
         let temporary = e.storage().temporary();
-        let lobby_info: LobbyInfo = temporary.get(&DataKey::LobbyInfo(req.lobby_id)).unwrap();
-        let mut game_state: GameState = temporary.get(&DataKey::GameState(req.lobby_id)).unwrap();
-        let u_index = Self::get_player_index(&address, &lobby_info);
-        if lobby_info.phase != Phase::MoveProve {
-            return Err(Error::WrongPhase)
+        let open_lobbies: Vec<LobbyId> = instance.get(&DataKey::OpenLobbies).unwrap_or_else(|| Vec::new(e));
+        // drop lobbies that expired without anyone joining or leaving
+        let mut pruned: Vec<LobbyId> = Vec::new(e);
+        for open_lobby_id in open_lobbies.iter() {
+            if temporary.has(&DataKey::LobbyInfo(open_lobby_id)) {
+                pruned.push_back(open_lobby_id);
+            }
         }
-        if lobby_info.subphase == Subphase::Both
-        {
-            // move_hash with all 8s and empty needed_rank_proofs signifies that simulate_collisions is being called too early
-            return Ok(UserMove {
-                move_hashes: Vec::from_array(e, [HiddenRankHash::from_array(e, &[8u8; 16])]),
-                move_proofs: Vec::new(e),
-                needed_rank_proofs: Vec::new(e),
-            })
+        // the oldest lobbies are the least likely to still have a waiting host
+        while pruned.len() >= MAX_OPEN_LOBBIES {
+            pruned.pop_front();
         }
-        let next_subphase = Self::next_subphase(&lobby_info.subphase, u_index)?;
-        if next_subphase != Subphase::None
-        {
-            return Err(Error::WrongSubphase)
+        pruned.push_back(lobby_id);
+        instance.set(&DataKey::OpenLobbies, &pruned);
+    }
+    pub(crate) fn remove_open_lobby(e: &Env, lobby_id: LobbyId) {
+        let instance = e.storage().instance();
+        let mut open_lobbies: Vec<LobbyId> = instance.get(&DataKey::OpenLobbies).unwrap_or_else(|| Vec::new(e));
+        if let Some(index) = open_lobbies.first_index_of(lobby_id) {
+            open_lobbies.remove(index);
+            instance.set(&DataKey::OpenLobbies, &open_lobbies);
         }
-        // we don't bother to validate the move
-        let mut u_move = game_state.moves.get_unchecked(u_index.u32());
-        u_move.move_proofs = req.move_proofs;
-        game_state.moves.set(u_index.u32(), u_move);
-        let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
-        let collisions = Self::compute_collisions(e, &game_state, &pawns_map);
-        let (h_needed_rank_proofs, g_needed_rank_proofs) = Self::derive_needed_rank_proofs(e, &collisions, &pawns_map);
-        let mut updated_move = game_state.moves.get_unchecked(u_index.u32());
-        if u_index == UserIndex::Host { updated_move.needed_rank_proofs = h_needed_rank_proofs; }
-        else { updated_move.needed_rank_proofs = g_needed_rank_proofs; }
-        Ok(updated_move)
     }
-    // endregion
-    // region state mutators
-    pub(crate) fn resolve_collision(a_pawn: &mut PawnState, b_pawn: &mut PawnState) -> () {
-        let a_pawn_rank = a_pawn.rank.get_unchecked(0);
-        let b_pawn_rank = b_pawn.rank.get_unchecked(0);
-        a_pawn.zz_revealed = true;
-        b_pawn.zz_revealed = true;
-        // special case for trap vs seer
-        if a_pawn_rank == 11 && b_pawn_rank == 3 {
-            a_pawn.alive = false;
+    pub(crate) fn release_departed_players(e: &Env, lobby_id: LobbyId, lobby_info: &mut LobbyInfo) {
+        // players who left while a result was pending are detached once it's settled, as leave_lobby would have done
+        let is_departed = |address_vec: &Vec<Address>| match address_vec.get(0) {
+            Some(address) => Self::load_user(e, &DataKey::User(address)).is_none_or(|user| user.current_lobby != lobby_id),
+            None => false,
+        };
+        let (host_departed, guest_departed) = (is_departed(&lobby_info.host_address), is_departed(&lobby_info.guest_address));
+        if host_departed || guest_departed {
+            // refund any rematch offer while the offerer's address is still known
+            Self::cancel_rematch_offer(e, lobby_id, lobby_info);
         }
-        else if b_pawn_rank == 11 && a_pawn_rank == 3 {
-            b_pawn.alive = false;
+        if host_departed { lobby_info.host_address = Vec::new(e); }
+        if guest_departed { lobby_info.guest_address = Vec::new(e); }
+    }
+    pub(crate) fn cancel_rematch_offer(e: &Env, lobby_id: LobbyId, lobby_info: &mut LobbyInfo) {
+        let offerer_opt = match lobby_info.rematch_offer {
+            Subphase::Host => lobby_info.host_address.get(0),
+            Subphase::Guest => lobby_info.guest_address.get(0),
+            _ => None,
+        };
+        lobby_info.rematch_offer = Subphase::None;
+        let offerer = match offerer_opt {
+            Some(offerer) => offerer,
+            None => return,
+        };
+        let lobby_parameters: LobbyParameters = e.storage().temporary().get(&DataKey::LobbyParameters(lobby_id)).unwrap();
+        if let Some(stake_token) = lobby_parameters.stake_token.get(0) {
+            token::Client::new(e, &stake_token).transfer(&e.current_contract_address(), &offerer, &lobby_parameters.stake_amount);
         }
-        // special case for warlord vs assassin
-        else if a_pawn_rank == 10 && b_pawn_rank == 1 {
-            a_pawn.alive = false;
+    }
+    pub(crate) fn finalize_game(e: &Env, lobby_id: LobbyId, lobby_info: &mut LobbyInfo) {
+        // called right after the transition to Finished or Aborted, and once more if a disputed result is overturned
+        let temporary = e.storage().temporary();
+        let history_opt: Option<History> = temporary.get(&DataKey::History(lobby_id));
+        if let Some(mut history) = history_opt {
+            let game_state_opt: Option<GameState> = temporary.get(&DataKey::GameState(lobby_id));
+            if let Some(game_state) = game_state_opt {
+                history.final_game_state.push_back(game_state);
+                history.final_lobby_info.push_back(lobby_info.clone());
+                temporary.set(&DataKey::History(lobby_id), &history);
+            }
         }
-        else if b_pawn_rank == 10 && a_pawn_rank == 1 {
-            b_pawn.alive = false;
+        let lobby_parameters: LobbyParameters = temporary.get(&DataKey::LobbyParameters(lobby_id)).unwrap();
+        if lobby_info.phase == Phase::Finished && lobby_parameters.dispute_window > 0 && lobby_info.end_reason != EndReason::FraudProven {
+            // payouts and ratings wait for settle_game so a fraud proof can still overturn the result
+            lobby_info.dispute_deadline = e.ledger().sequence() + lobby_parameters.dispute_window;
+            temporary.set(&DataKey::LobbyInfo(lobby_id), lobby_info);
+            return
         }
-        // normal cases
-        else if a_pawn_rank < b_pawn_rank {
-            a_pawn.alive = false;
+        Self::settle_game_result(e, lobby_id, lobby_info, &lobby_parameters);
+    }
+    pub(crate) fn settle_game_result(e: &Env, lobby_id: LobbyId, lobby_info: &LobbyInfo, lobby_parameters: &LobbyParameters) {
+        if lobby_info.series == 0 {
+            Self::settle_stakes(e, lobby_info, lobby_parameters);
+            Self::record_game_result(e, lobby_info, lobby_parameters);
+        } else {
+            // aborts still count per game, results and stakes are settled once for the whole series
+            if lobby_info.phase == Phase::Aborted {
+                Self::record_game_result(e, lobby_info, lobby_parameters);
+            }
+            Self::update_series(e, lobby_id, lobby_info, lobby_parameters);
         }
-        else if b_pawn_rank < a_pawn_rank {
-            b_pawn.alive = false;
+        if lobby_info.tournament != 0 {
+            Self::update_tournament(e, lobby_id, lobby_info);
         }
-        // both die if equal rank
-        else {
-            a_pawn.alive = false;
-            b_pawn.alive = false;
+    }
+    pub(crate) fn start_tournament(e: &Env, tournament: &mut Tournament) {
+        // seed by rating, earlier registration wins ties
+        let mut seeded: Vec<Address> = Vec::new(e);
+        let mut seeded_ratings: Vec<u32> = Vec::new(e);
+        for player in tournament.players.iter() {
+            let rating = Self::load_user(e, &DataKey::User(player.clone())).unwrap_or_else(Self::default_user).rating;
+            let mut insert_at = seeded_ratings.len();
+            while insert_at > 0 && seeded_ratings.get_unchecked(insert_at - 1) < rating {
+                insert_at -= 1;
+            }
+            seeded.insert(insert_at, player);
+            seeded_ratings.insert(insert_at, rating);
+        }
+        tournament.players = seeded;
+        // standard bracket order keeps the top seeds apart until the late rounds, e.g. 1 8 4 5 2 7 3 6
+        let mut bracket: Vec<u32> = Vec::from_array(e, [1u32]);
+        while bracket.len() < tournament.players.len() {
+            let mut next_bracket: Vec<u32> = Vec::new(e);
+            for seed in bracket.iter() {
+                next_bracket.push_back(seed);
+                next_bracket.push_back(bracket.len() * 2 + 1 - seed);
+            }
+            bracket = next_bracket;
+        }
+        let mut entrants: Vec<Vec<Address>> = Vec::new(e);
+        for seed in bracket.iter() {
+            match tournament.players.get(seed - 1) {
+                Some(player) => entrants.push_back(Vec::from_array(e, [player])),
+                None => entrants.push_back(Vec::new(e)),
+            }
         }
+        tournament.entrants = entrants;
+        tournament.round = 1;
+        Self::start_tournament_round(e, tournament);
     }
-    pub(crate) fn apply_move_to_pawn(move_proof: &HiddenMove, pawn: &mut PawnState) -> () {
-        if pawn.pos != move_proof.target_pos {
-            pawn.moved = true;
+    pub(crate) fn start_tournament_round(e: &Env, tournament: &mut Tournament) {
+        loop {
+            let mut matches: Vec<LobbyId> = Vec::new(e);
+            let mut results: Vec<Vec<Address>> = Vec::new(e);
+            let mut pending_matches = 0;
+            for pair_index in 0..tournament.entrants.len() / 2 {
+                let mut first = tournament.entrants.get_unchecked(pair_index * 2);
+                let mut second = tournament.entrants.get_unchecked(pair_index * 2 + 1);
+                // a player still sitting in another game forfeits the match
+                if first.get(0).is_some_and(|player| Self::is_in_active_lobby(e, &player)) {
+                    first = Vec::new(e);
+                }
+                if second.get(0).is_some_and(|player| Self::is_in_active_lobby(e, &player)) {
+                    second = Vec::new(e);
+                }
+                if first.is_empty() || second.is_empty() {
+                    // byes and forfeits advance without playing
+                    matches.push_back(0);
+                    results.push_back(if first.is_empty() { second } else { first });
+                    continue;
+                }
+                let mut lobby_info = Self::new_lobby_info(e, 0, first, second);
+                lobby_info.tournament = tournament.index;
+                matches.push_back(Self::start_paired_game(e, &mut lobby_info, &tournament.lobby_parameters).unwrap());
+                results.push_back(Vec::new(e));
+                pending_matches += 1;
+            }
+            tournament.matches = matches;
+            tournament.results = results;
+            tournament.pending_matches = pending_matches;
+            // a round made up of byes is over before it starts
+            if pending_matches > 0 || !Self::advance_tournament(tournament) {
+                return
+            }
         }
-        if Self::is_scout_move(move_proof) {
-            pawn.moved_scout = true;
+    }
+    pub(crate) fn advance_tournament(tournament: &mut Tournament) -> bool {
+        // moves the results of a completed round forward, returns true if another round has to be played
+        if tournament.results.len() == 1 {
+            tournament.winner = tournament.results.get_unchecked(0);
+            tournament.finished = true;
+            return false
+        }
+        tournament.entrants = tournament.results.clone();
+        tournament.round += 1;
+        true
+    }
+    pub(crate) fn update_tournament(e: &Env, lobby_id: LobbyId, lobby_info: &LobbyInfo) {
+        let persistent = e.storage().persistent();
+        let tournament_key = DataKey::Tournament(lobby_info.tournament);
+        let mut tournament: Tournament = persistent.get(&tournament_key).unwrap();
+        let pair_index = match tournament.matches.first_index_of(lobby_id) {
+            Some(pair_index) => pair_index,
+            None => return,
+        };
+        let host_address = lobby_info.host_address.get_unchecked(0);
+        let guest_address = lobby_info.guest_address.get_unchecked(0);
+        let advancing: Vec<Address> = match (lobby_info.subphase, lobby_info.end_player) {
+            (Subphase::Host, _) => Vec::from_array(e, [host_address]),
+            (Subphase::Guest, _) => Vec::from_array(e, [guest_address]),
+            // setup timeouts end without a winner but the player who waited still advances
+            (_, Subphase::Host) => Vec::from_array(e, [guest_address]),
+            (_, Subphase::Guest) => Vec::from_array(e, [host_address]),
+            // double aborts eliminate both players
+            _ if lobby_info.phase == Phase::Aborted => Vec::new(e),
+            // draws go to the higher seed
+            _ => {
+                let host_seed = tournament.players.first_index_of(&host_address).unwrap();
+                let guest_seed = tournament.players.first_index_of(&guest_address).unwrap();
+                Vec::from_array(e, [if host_seed < guest_seed { host_address } else { guest_address }])
+            }
+        };
+        tournament.results.set(pair_index, advancing);
+        tournament.pending_matches -= 1;
+        if tournament.pending_matches == 0 && Self::advance_tournament(&mut tournament) {
+            Self::start_tournament_round(e, &mut tournament);
+        }
+        persistent.set(&tournament_key, &tournament);
+    }
+    pub(crate) fn update_series(e: &Env, lobby_id: LobbyId, lobby_info: &LobbyInfo, lobby_parameters: &LobbyParameters) {
+        let persistent = e.storage().persistent();
+        let series_key = DataKey::Series(lobby_info.series);
+        let mut series: Series = persistent.get(&series_key).unwrap();
+        if series.finished {
+            return
+        }
+        let winner_opt = match (lobby_info.subphase, lobby_info.end_player) {
+            (Subphase::Host, _) => lobby_info.host_address.get(0),
+            (Subphase::Guest, _) => lobby_info.guest_address.get(0),
+            // an abort counts against the player who caused it so stalling setup can't turn a loss into a draw
+            (_, Subphase::Host) if lobby_info.phase == Phase::Aborted => lobby_info.guest_address.get(0),
+            (_, Subphase::Guest) if lobby_info.phase == Phase::Aborted => lobby_info.host_address.get(0),
+            _ => None,
+        };
+        let mut winner_index_opt: Option<u32> = None;
+        match &winner_opt {
+            Some(winner) => {
+                let winner_index = series.players.first_index_of(winner).unwrap();
+                series.scores.set(winner_index, series.scores.get_unchecked(winner_index) + 1);
+                winner_index_opt = Some(winner_index);
+            }
+            None => series.draws += 1,
+        }
+        // leaving a game forfeits the rest of the series
+        let mut forfeit = lobby_info.end_reason == EndReason::LeftLobby;
+        let best_score = series.scores.get_unchecked(0).max(series.scores.get_unchecked(1));
+        if !forfeit && best_score < series.target_wins && series.lobbies.len() < series.target_wins * 3 {
+            // so does sitting down in another game before the next one starts
+            let first_busy = Self::is_in_active_lobby(e, &series.players.get_unchecked(0));
+            let second_busy = Self::is_in_active_lobby(e, &series.players.get_unchecked(1));
+            if first_busy || second_busy {
+                forfeit = true;
+                winner_index_opt = if first_busy && second_busy { None } else if first_busy { Some(1) } else { Some(0) };
+            }
+        }
+        if !forfeit && best_score < series.target_wins && series.lobbies.len() < series.target_wins * 3 {
+            // start the next game with sides swapped
+            let mut next_lobby_info = Self::new_lobby_info(e, 0, lobby_info.guest_address.clone(), lobby_info.host_address.clone());
+            next_lobby_info.previous_lobby = lobby_id;
+            next_lobby_info.series = series.index;
+            let next_lobby_id = Self::start_paired_game(e, &mut next_lobby_info, lobby_parameters).unwrap();
+            series.lobbies.push_back(next_lobby_id);
+            persistent.set(&series_key, &series);
+            return
+        }
+        if !forfeit {
+            let (first_score, second_score) = (series.scores.get_unchecked(0), series.scores.get_unchecked(1));
+            winner_index_opt = if first_score > second_score { Some(0) } else if second_score > first_score { Some(1) } else { None };
+        }
+        series.finished = true;
+        // settle the series as if it was a single game between its players
+        let mut series_result = lobby_info.clone();
+        series_result.host_address = Vec::from_array(e, [series.players.get_unchecked(0)]);
+        series_result.guest_address = Vec::from_array(e, [series.players.get_unchecked(1)]);
+        series_result.phase = Phase::Finished;
+        series_result.subphase = match winner_index_opt {
+            Some(0) => Subphase::Host,
+            Some(_) => Subphase::Guest,
+            None => Subphase::None,
+        };
+        if let Some(winner_index) = winner_index_opt {
+            series.winner = Vec::from_array(e, [series.players.get_unchecked(winner_index)]);
+        }
+        persistent.set(&series_key, &series);
+        Self::settle_stakes(e, &series_result, lobby_parameters);
+        Self::record_game_result(e, &series_result, lobby_parameters);
+    }
+    pub(crate) fn record_game_result(e: &Env, lobby_info: &LobbyInfo, lobby_parameters: &LobbyParameters) {
+        if lobby_parameters.dev_mode {
+            return
+        }
+        let persistent = e.storage().persistent();
+        match lobby_info.phase {
+            Phase::Aborted => {
+                // count the abort against whoever caused it, or everyone present if nobody did
+                for (player, address_vec) in [(Subphase::Host, &lobby_info.host_address), (Subphase::Guest, &lobby_info.guest_address)] {
+                    if let Some(address) = address_vec.get(0) {
+                        if lobby_info.end_player == player || lobby_info.end_player == Subphase::None {
+                            let user_key = DataKey::User(address);
+                            let mut user: User = Self::load_user(e, &user_key).unwrap_or_else(Self::default_user);
+                            user.aborts += 1;
+                            persistent.set(&user_key, &user);
+                        }
+                    }
+                }
+            }
+            Phase::Finished => {
+                let host_key = DataKey::User(lobby_info.host_address.get_unchecked(0));
+                let guest_key = DataKey::User(lobby_info.guest_address.get_unchecked(0));
+                let mut host_user: User = Self::load_user(e, &host_key).unwrap_or_else(Self::default_user);
+                let mut guest_user: User = Self::load_user(e, &guest_key).unwrap_or_else(Self::default_user);
+                // scores are in thousandths, Subphase::None is a draw
+                let host_score: i64 = match lobby_info.subphase {
+                    Subphase::Host => { host_user.wins += 1; guest_user.losses += 1; 1000 }
+                    Subphase::Guest => { host_user.losses += 1; guest_user.wins += 1; 0 }
+                    _ => { host_user.draws += 1; guest_user.draws += 1; 500 }
+                };
+                let host_rating = host_user.rating;
+                host_user.rating = Self::next_rating(host_rating, guest_user.rating, host_score);
+                guest_user.rating = Self::next_rating(guest_user.rating, host_rating, 1000 - host_score);
+                host_user.games_completed += 1;
+                guest_user.games_completed += 1;
+                persistent.set(&host_key, &host_user);
+                persistent.set(&guest_key, &guest_user);
+            }
+            _ => {}
         }
-        pawn.pos = move_proof.target_pos;
     }
-    pub(crate) fn abort_illegal_move(lobby_info: &mut LobbyInfo, offending_index: UserIndex) -> () {
-        lobby_info.phase = Phase::Aborted;
-        lobby_info.subphase = Self::opponent_subphase_from_player_index(offending_index);
+    pub(crate) fn settle_stakes(e: &Env, lobby_info: &LobbyInfo, lobby_parameters: &LobbyParameters) {
+        let stake_token = match lobby_parameters.stake_token.get(0) {
+            Some(stake_token) => stake_token,
+            None => return,
+        };
+        let token_client = token::Client::new(e, &stake_token);
+        let contract_address = e.current_contract_address();
+        // the guest has only paid in if they joined
+        let mut depositors: Vec<Address> = Vec::new(e);
+        depositors.append(&lobby_info.host_address);
+        depositors.append(&lobby_info.guest_address);
+        let pot = lobby_parameters.stake_amount * depositors.len() as i128;
+        let winner_opt = match lobby_info.subphase {
+            Subphase::Host => lobby_info.host_address.get(0),
+            Subphase::Guest => lobby_info.guest_address.get(0),
+            _ => None,
+        };
+        match winner_opt {
+            // winners and players whose opponent was caught cheating take the pot
+            Some(winner) => token_client.transfer(&contract_address, &winner, &pot),
+            // aborted games are refunded and draws split the pot
+            None => {
+                for depositor in depositors.iter() {
+                    token_client.transfer(&contract_address, &depositor, &lobby_parameters.stake_amount);
+                }
+            }
+        }
     }
-    pub(crate) fn complete_move_resolution(e: &Env, game_state: &mut GameState, collisions_opt: Option<Vec<Collision>>, pawns_map: &Map<PawnId, (u32, PawnState)>) -> () {
+    pub(crate) fn complete_move_resolution(e: &Env, game_state: &mut GameState, collisions_opt: Option<Vec<Collision>>, pawns_map: &Map<PawnId, (u32, PawnState)>, lobby_parameters: &LobbyParameters) -> () {
         let h_move = game_state.moves.get_unchecked(UserIndex::Host.u32());
         let g_move = game_state.moves.get_unchecked(UserIndex::Guest.u32());
         if !h_move.needed_rank_proofs.is_empty() || !g_move.needed_rank_proofs.is_empty() {
@@ -1155,30 +2508,123 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             game_state.pawns.set(pawn_index, Self::pack_pawn(pawn));
         }
         // now apply collisions
+        let mut captured = false;
+        let mut bounced: Vec<PawnId> = Vec::new(e);
         for collision in collisions.iter() {
             let (h_index, _) = pawns_map.get_unchecked(collision.h_pawn_id);
             let (g_index, _) = pawns_map.get_unchecked(collision.g_pawn_id);
             let mut h_pawn = Self::unpack_pawn(e, game_state.pawns.get_unchecked(h_index));
             let mut g_pawn = Self::unpack_pawn(e, game_state.pawns.get_unchecked(g_index));
-            Self::resolve_collision(&mut h_pawn, &mut g_pawn);
+            // a pawn that moved this turn attacks the other. when both moved, each attacks and the deaths combine
+            let h_attacks = pawn_id_to_move_proof.contains_key(collision.h_pawn_id);
+            let g_attacks = pawn_id_to_move_proof.contains_key(collision.g_pawn_id);
+            if h_pawn.rank.is_empty() || g_pawn.rank.is_empty() {
+                // a hidden pawn only skips its rank proof when every rank it could have leaves it alive
+                let (h_dies, g_dies) = Self::collision_deaths(lobby_parameters, &h_pawn, &g_pawn, h_attacks, g_attacks).unwrap();
+                h_pawn.alive = !h_dies;
+                g_pawn.alive = !g_dies;
+                h_pawn.zz_revealed |= !h_pawn.rank.is_empty();
+                g_pawn.zz_revealed |= !g_pawn.rank.is_empty();
+            } else {
+                if h_attacks {
+                    Self::resolve_collision(&lobby_parameters.combat_outcomes, &mut h_pawn, &mut g_pawn);
+                }
+                if g_attacks {
+                    Self::resolve_collision(&lobby_parameters.combat_outcomes, &mut g_pawn, &mut h_pawn);
+                }
+            }
+            // when neither dies the attackers fall back to where they started instead of sharing a tile or passing through each other
+            if h_pawn.alive && g_pawn.alive {
+                if let Some(move_proof) = pawn_id_to_move_proof.get(collision.h_pawn_id) {
+                    h_pawn.pos = move_proof.start_pos;
+                    bounced.push_back(collision.h_pawn_id);
+                }
+                if let Some(move_proof) = pawn_id_to_move_proof.get(collision.g_pawn_id) {
+                    g_pawn.pos = move_proof.start_pos;
+                    bounced.push_back(collision.g_pawn_id);
+                }
+            }
+            captured |= !h_pawn.alive || !g_pawn.alive;
             game_state.pawns.set(h_index, Self::pack_pawn(h_pawn));
             game_state.pawns.set(g_index, Self::pack_pawn(g_pawn));
         }
+        // a pawn that moved into a tile someone just fell back to is pushed back too, which can chain
+        while let Some(bounced_id) = bounced.pop_back() {
+            let (bounced_index, _) = pawns_map.get_unchecked(bounced_id);
+            let bounced_pos = Self::unpack_pawn(e, game_state.pawns.get_unchecked(bounced_index)).pos;
+            for (pawn_id, move_proof) in pawn_id_to_move_proof.iter() {
+                let (pawn_index, _) = pawns_map.get_unchecked(pawn_id);
+                let mut pawn = Self::unpack_pawn(e, game_state.pawns.get_unchecked(pawn_index));
+                if pawn_id != bounced_id && pawn.alive && pawn.pos == bounced_pos && pawn.pos != move_proof.start_pos {
+                    pawn.pos = move_proof.start_pos;
+                    game_state.pawns.set(pawn_index, Self::pack_pawn(pawn));
+                    bounced.push_back(pawn_id);
+                }
+            }
+        }
+        game_state.turns_without_capture = if captured { 0 } else { game_state.turns_without_capture + 1 };
         // Do not clear moves here; callers will record history and then clear moves
     }
     // endregion
     // region validation
-    pub(crate) fn derive_needed_rank_proofs(e: &Env, collisions: &Vec<Collision>, pawns_map: &Map<PawnId, (u32, PawnState)>) -> (Vec<PawnId>, Vec<PawnId>) {
+    pub(crate) fn derive_needed_rank_proofs(e: &Env, game_state: &GameState, collisions: &Vec<Collision>, pawns_map: &Map<PawnId, (u32, PawnState)>, lobby_parameters: &LobbyParameters) -> (Vec<PawnId>, Vec<PawnId>) {
         let mut h_needed: Vec<PawnId> = Vec::new(e);
         let mut g_needed: Vec<PawnId> = Vec::new(e);
+        let h_move = game_state.moves.get_unchecked(UserIndex::Host.u32());
+        let g_move = game_state.moves.get_unchecked(UserIndex::Guest.u32());
+        // a hidden pawn whose move only some ranks could make has to show it was one of them
+        let (passable_map, pos_to_pawn_id) = Self::movement_maps(e, lobby_parameters, pawns_map);
+        for (u_move, needed) in [(&h_move, &mut h_needed), (&g_move, &mut g_needed)] {
+            let target_counts = Self::move_target_counts(e, u_move);
+            for move_proof in u_move.move_proofs.iter() {
+                let (_, pawn) = pawns_map.get_unchecked(move_proof.pawn_id);
+                if pawn.rank.is_empty() && !Self::is_move_legal_for_every_rank(lobby_parameters, &move_proof, &passable_map, &pos_to_pawn_id, &target_counts) {
+                    needed.push_back(pawn.pawn_id);
+                }
+            }
+        }
         for collision in collisions.iter() {
             let (_, h_pawn) = pawns_map.get_unchecked(collision.h_pawn_id);
             let (_, g_pawn) = pawns_map.get_unchecked(collision.g_pawn_id);
-            if h_pawn.rank.is_empty() { h_needed.push_back(h_pawn.pawn_id); }
-            if g_pawn.rank.is_empty() { g_needed.push_back(g_pawn.pawn_id); }
+            // the combat table can settle a collision without a proof, but a pawn that might die always has to show its rank
+            let h_attacks = h_move.move_proofs.iter().any(|move_proof| move_proof.pawn_id == h_pawn.pawn_id);
+            let g_attacks = g_move.move_proofs.iter().any(|move_proof| move_proof.pawn_id == g_pawn.pawn_id);
+            let deaths_opt = Self::collision_deaths(lobby_parameters, &h_pawn, &g_pawn, h_attacks, g_attacks);
+            if h_pawn.rank.is_empty() && deaths_opt.is_none_or(|(h_dies, _)| h_dies) && !h_needed.contains(h_pawn.pawn_id) { h_needed.push_back(h_pawn.pawn_id); }
+            if g_pawn.rank.is_empty() && deaths_opt.is_none_or(|(_, g_dies)| g_dies) && !g_needed.contains(g_pawn.pawn_id) { g_needed.push_back(g_pawn.pawn_id); }
         }
         (h_needed, g_needed)
     }
+    pub(crate) fn collision_deaths(lobby_parameters: &LobbyParameters, h_pawn: &PawnState, g_pawn: &PawnState, h_attacks: bool, g_attacks: bool) -> Option<(bool, bool)> {
+        // the deaths every rank the pawns could have agrees on, None if it depends on a hidden rank
+        let mut deaths_opt: Option<(bool, bool)> = None;
+        for h_rank in 0..13u32 {
+            if !Self::is_possible_rank(lobby_parameters, h_pawn, h_rank) { continue; }
+            for g_rank in 0..13u32 {
+                if !Self::is_possible_rank(lobby_parameters, g_pawn, g_rank) { continue; }
+                let mut deaths = (false, false);
+                if h_attacks {
+                    let (attacker_dies, defender_dies) = Self::attack_deaths(&lobby_parameters.combat_outcomes, h_rank, g_rank);
+                    deaths = (deaths.0 || attacker_dies, deaths.1 || defender_dies);
+                }
+                if g_attacks {
+                    let (attacker_dies, defender_dies) = Self::attack_deaths(&lobby_parameters.combat_outcomes, g_rank, h_rank);
+                    deaths = (deaths.0 || defender_dies, deaths.1 || attacker_dies);
+                }
+                match deaths_opt {
+                    Some(agreed) if agreed != deaths => return None,
+                    _ => deaths_opt = Some(deaths),
+                }
+            }
+        }
+        deaths_opt
+    }
+    pub(crate) fn is_possible_rank(lobby_parameters: &LobbyParameters, pawn: &PawnState, rank: Rank) -> bool {
+        match pawn.rank.get(0) {
+            Some(known_rank) => known_rank == rank,
+            None => lobby_parameters.max_ranks.get(rank).unwrap_or(0) > 0,
+        }
+    }
 
     pub(crate) fn get_revealed_rank_counts(e: &Env, player_index: UserIndex, game_state: &GameState) -> [u32; 13] {
         let mut revealed_ranks_counts = [0u32; 13];
@@ -1371,6 +2817,31 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             log!(e, "validate_board: failed [max_ranks sum must equal both teams' setup tiles]");
             return false;
         }
+        if !lobby_parameters.movement_profiles.is_empty() && lobby_parameters.movement_profiles.len() != 13 {
+            log!(e, "validate_board: failed [movement_profiles must be empty or have one entry per rank]");
+            return false;
+        }
+        for profile in lobby_parameters.movement_profiles.iter() {
+            if profile.can_move && (profile.max_range == 0 || profile.max_range > 16) {
+                log!(e, "validate_board: failed [movement_profiles max_range must be 1 to 16 for a movable rank]");
+                return false;
+            }
+        }
+        if !lobby_parameters.combat_outcomes.is_empty() && lobby_parameters.combat_outcomes.len() != 13 * 13 {
+            log!(e, "validate_board: failed [combat_outcomes must be empty or cover every rank pair]");
+            return false;
+        }
+        for attacker_rank in 0..lobby_parameters.combat_outcomes.len() / 13 {
+            for defender_rank in 0..13 {
+                // who attacks can decide the winner, but a pair can't bounce off each other one way and trade the other
+                let outcome = lobby_parameters.combat_outcomes.get_unchecked(attacker_rank * 13 + defender_rank);
+                let mirrored = lobby_parameters.combat_outcomes.get_unchecked(defender_rank * 13 + attacker_rank);
+                if (outcome == CombatOutcome::Neither && mirrored == CombatOutcome::Both) || (outcome == CombatOutcome::Both && mirrored == CombatOutcome::Neither) {
+                    log!(e, "validate_board: failed [combat_outcomes contradict each other for a mirrored rank pair]");
+                    return false;
+                }
+            }
+        }
         if lobby_parameters.blitz_interval > 8 {
             log!(e, "validate_board: failed [blitz_interval exceeds limit]");
             return false;
@@ -1383,6 +2854,26 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             log!(e, "validate_board: failed [blitz_max_simultaneous_moves must be >= 1 when blitz is enabled]");
             return false;
             }
+        if lobby_parameters.stake_token.len() > 1 {
+            log!(e, "validate_board: failed [stake_token must have at most one address]");
+            return false;
+        }
+        if lobby_parameters.stake_token.is_empty() && lobby_parameters.stake_amount != 0 {
+            log!(e, "validate_board: failed [stake_amount requires a stake_token]");
+            return false;
+        }
+        if !lobby_parameters.stake_token.is_empty() && lobby_parameters.stake_amount <= 0 {
+            log!(e, "validate_board: failed [stake_amount must be positive]");
+            return false;
+        }
+        if lobby_parameters.series_target_wins > MAX_SERIES_TARGET_WINS {
+            log!(e, "validate_board: failed [series_target_wins out of bounds]");
+            return false;
+        }
+        if lobby_parameters.invited_guests.len() > MAX_INVITED_GUESTS {
+            log!(e, "validate_board: failed [too many invited_guests]");
+            return false;
+        }
         true
     }
     pub(crate) fn is_scout_move(hidden_move: &HiddenMove) -> bool {
@@ -1393,54 +2884,343 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         }
         false
     }
-    pub(crate) fn validate_rank_proofs(e: &Env, hidden_ranks: &Vec<HiddenRank>, merkle_proofs: &Vec<MerkleProof>, root: &MerkleHash) -> bool {
+    pub(crate) fn validate_rank_proofs(e: &Env, hash_scheme: HashScheme, hidden_ranks: &Vec<HiddenRank>, merkle_proofs: &Vec<MerkleProof>, rank_slots: &Map<PawnId, u32>, root: &MerkleHash) -> bool {
         let mut valid_rank_proof = true;
         // Check that we have the same number of hidden ranks and merkle proofs
         if hidden_ranks.len() != merkle_proofs.len() {
             return false;
         }
+        let leaf_count = rank_slots.len();
+        let depth = Self::get_merkle_depth(leaf_count);
         for (i, hidden_rank) in hidden_ranks.iter().enumerate() {
-            let serialized_hidden_rank = hidden_rank.to_xdr(e);
-            let full_hash = e.crypto().sha256(&serialized_hidden_rank).to_bytes().to_array();
-            let rank_hash = HiddenRankHash::from_array(e, &full_hash[0..16].try_into().unwrap());
             let merkle_proof = merkle_proofs.get(i as u32).unwrap();
-            let is_valid = Self::verify_merkle_proof(e, &rank_hash, &merkle_proof, root);
-            if !is_valid {
+            // each pawn has exactly one leaf, so the proof must sit at its slot in a tree of the committed size
+            let slot = match rank_slots.get(hidden_rank.pawn_id) {
+                Some(slot) => slot,
+                None => return false,
+            };
+            if merkle_proof.leaf_index != slot || merkle_proof.siblings.len() != depth {
+                log!(e, "validate_rank_proofs: failed [proof not at the pawn's slot]", hidden_rank.pawn_id, merkle_proof.leaf_index, slot);
+                return false;
+            }
+            let rank_hash = Self::commitment_hash(e, hash_scheme, HASH_TAG_LEAF, &hidden_rank.to_xdr(e));
+            let tree_root = Self::get_merkle_root(e, hash_scheme, &rank_hash, &merkle_proof);
+            if Self::rank_commitment(e, hash_scheme, &tree_root, leaf_count) != *root {
                 valid_rank_proof = false;
             }
         }
         valid_rank_proof
     }
+    pub(crate) fn rank_commitment(e: &Env, hash_scheme: HashScheme, tree_root: &MerkleHash, leaf_count: u32) -> MerkleHash {
+        // the committed root also covers the leaf count so the tree can't be padded with spare leaves.
+        // legacy roots are the bare tree root, hashed the way clients did before the count was added
+        if hash_scheme == HashScheme::Legacy {
+            return tree_root.clone()
+        }
+        let mut combined_bytes = tree_root.clone();
+        combined_bytes.extend_from_array(&leaf_count.to_be_bytes());
+        Self::commitment_hash(e, hash_scheme, HASH_TAG_ROOT, &combined_bytes)
+    }
+    pub(crate) fn commitment_hash(e: &Env, hash_scheme: HashScheme, tag: u8, data: &Bytes) -> Bytes {
+        // every committed hash goes through here. tags keep a leaf, a node and a move from ever hashing alike
+        let digest: Bytes = match hash_scheme {
+            HashScheme::Legacy => e.crypto().sha256(data).to_bytes().into(),
+            HashScheme::Tagged | HashScheme::Tagged32 | HashScheme::Bound | HashScheme::Bound32 => {
+                let mut tagged = Bytes::from_array(e, &[hash_scheme as u8, tag]);
+                tagged.append(data);
+                e.crypto().sha256(&tagged).to_bytes().into()
+            }
+        };
+        digest.slice(0..Self::hash_length(hash_scheme))
+    }
+    pub(crate) fn move_commitment(e: &Env, hash_scheme: HashScheme, lobby_id: LobbyId, turn: u32, u_index: UserIndex, hidden_move: &HiddenMove) -> HiddenMoveHash {
+        // bound schemes prefix the lobby, turn and player so a move hash means nothing outside the slot it was committed for
+        let mut data = Bytes::new(e);
+        if Self::is_bound_scheme(hash_scheme) {
+            data.extend_from_array(&lobby_id.to_be_bytes());
+            data.extend_from_array(&turn.to_be_bytes());
+            data.push_back(u_index.u32() as u8);
+        }
+        data.append(&hidden_move.clone().to_xdr(e));
+        Self::commitment_hash(e, hash_scheme, HASH_TAG_MOVE, &data)
+    }
+    pub(crate) fn is_bound_scheme(hash_scheme: HashScheme) -> bool {
+        hash_scheme == HashScheme::Bound || hash_scheme == HashScheme::Bound32
+    }
+    pub(crate) fn hash_length(hash_scheme: HashScheme) -> u32 {
+        if hash_scheme == HashScheme::Tagged32 || hash_scheme == HashScheme::Bound32 { 32 } else { 16 }
+    }
+    pub(crate) fn get_merkle_depth(leaf_count: u32) -> u32 {
+        // leaves are padded to the next power of two
+        let mut depth = 0;
+        while (1u32 << depth) < leaf_count {
+            depth += 1;
+        }
+        depth
+    }
+    pub(crate) fn get_rank_slots(e: &Env, pawns_map: &Map<PawnId, (u32, PawnState)>, u_index: UserIndex) -> Map<PawnId, u32> {
+        // canonical leaf index of each of the player's pawns, in ascending pawn_id order
+        let mut rank_slots: Map<PawnId, u32> = Map::new(e);
+        for (pawn_id, _) in pawns_map.iter() {
+            if Self::decode_pawn_id(pawn_id).1 == u_index {
+                rank_slots.set(pawn_id, rank_slots.len());
+            }
+        }
+        rank_slots
+    }
     // validate_move_proof is temporarily unused; inlined in prove_move_internal to access full state and both players' moves
-    pub(crate) fn verify_merkle_proof(e: &Env, leaf: &MerkleHash, proof: &MerkleProof, root: &MerkleHash) -> bool {
+    pub(crate) fn get_merkle_root(e: &Env, hash_scheme: HashScheme, leaf: &MerkleHash, proof: &MerkleProof) -> MerkleHash {
         let mut current_hash = leaf.clone();
         let mut index = proof.leaf_index;
         for (_, sibling) in proof.siblings.iter().enumerate() {
-            // Create a 32-byte array directly for concatenation
-            let mut combined_bytes = [0u8; 32];
             // Determine order based on index (even = current is left, odd = current is right)
+            let mut combined_bytes = Bytes::new(e);
             if index % 2 == 0 {
                 // Current hash goes on the left, sibling on the right
-                combined_bytes[0..16].copy_from_slice(&current_hash.to_array());
-                combined_bytes[16..32].copy_from_slice(&sibling.to_array());
+                combined_bytes.append(&current_hash);
+                combined_bytes.append(&sibling);
             } else {
                 // Sibling goes on the left, current hash on the right
-                combined_bytes[0..16].copy_from_slice(&sibling.to_array());
-                combined_bytes[16..32].copy_from_slice(&current_hash.to_array());
+                combined_bytes.append(&sibling);
+                combined_bytes.append(&current_hash);
             }
             // Hash the combined bytes
-            let parent_full = e.crypto().sha256(&Bytes::from_array(e, &combined_bytes));
-            // Take first 16 bytes as the new current hash
-            let parent_bytes = parent_full.to_array();
-            current_hash = MerkleHash::from_array(e, &parent_bytes[0..16].try_into().unwrap());
+            current_hash = Self::commitment_hash(e, hash_scheme, HASH_TAG_NODE, &combined_bytes);
             // Move up the tree
             index = index / 2;
         }
-        let result = current_hash == *root;
-        result
+        current_hash
     }
     // endregion
     // region questions
+    pub(crate) fn next_rating(rating: u32, opponent_rating: u32, score: i64) -> u32 {
+        // expected score in thousandths for every 25 points of rating difference, capped at 800
+        const EXPECTED_SCORES: [i64; 33] = [
+            500, 536, 571, 606, 640, 673, 703, 733, 760, 785, 808, 830, 849, 867, 882, 896, 909,
+            920, 930, 939, 947, 954, 960, 965, 969, 973, 977, 980, 983, 985, 987, 989, 990,
+        ];
+        let difference = rating as i64 - opponent_rating as i64;
+        let table_index = (difference.abs().min(800) / 25) as usize;
+        let expected = if difference >= 0 { EXPECTED_SCORES[table_index] } else { 1000 - EXPECTED_SCORES[table_index] };
+        let next = rating as i64 + RATING_K_FACTOR * (score - expected) / 1000;
+        next.max(0) as u32
+    }
+    pub(crate) fn phase_time_limit(phase: Phase) -> Option<u32> {
+        // ledgers a player has to act before the opponent can claim a timeout
+        match phase {
+            Phase::Lobby | Phase::Finished | Phase::Aborted => None,
+            Phase::SetupCommit => Some(100),
+            Phase::SetupReveal => Some(100),
+            Phase::MoveCommit => Some(100),
+            Phase::MoveProve => Some(40),
+            Phase::RankProve => Some(40),
+        }
+    }
+    pub(crate) fn disconnect_deadline(lobby_info: &LobbyInfo, lobby_parameters: &LobbyParameters) -> u32 {
+        // first ledger the opponent of a disconnected player can claim the win on
+        let window = if lobby_parameters.disconnect_window > 0 {
+            lobby_parameters.disconnect_window
+        } else {
+            Self::phase_time_limit(lobby_info.phase).unwrap_or(0)
+        };
+        lobby_info.disconnected_ledger_seq + window
+    }
+    pub(crate) fn remaining_clock(e: &Env, lobby_info: &LobbyInfo, u_index: UserIndex) -> u32 {
+        // a player's clock runs from last_edited_ledger_seq while the subphase waits on them
+        let clock = if u_index == UserIndex::Host { lobby_info.host_clock } else { lobby_info.guest_clock };
+        let on_clock = Self::phase_time_limit(lobby_info.phase).is_some() && (lobby_info.subphase == Subphase::Both || lobby_info.subphase == Self::user_subphase_from_player_index(u_index));
+        if !on_clock {
+            return clock
+        }
+        clock.saturating_sub(e.ledger().sequence() - lobby_info.last_edited_ledger_seq)
+    }
+    pub(crate) fn charge_clocks(e: &Env, lobby_info: &mut LobbyInfo, lobby_parameters: &LobbyParameters) {
+        // deducts the time since the last action from whoever owed it. call once per action, before last_edited_ledger_seq moves
+        if lobby_parameters.clock_budget == 0 {
+            return
+        }
+        lobby_info.host_clock = Self::remaining_clock(e, lobby_info, UserIndex::Host);
+        lobby_info.guest_clock = Self::remaining_clock(e, lobby_info, UserIndex::Guest);
+    }
+    pub(crate) fn movement_profile(lobby_parameters: &LobbyParameters, rank: Rank) -> MovementProfile {
+        if lobby_parameters.movement_profiles.is_empty() {
+            return Self::default_movement_profile(rank)
+        }
+        lobby_parameters.movement_profiles.get_unchecked(rank)
+    }
+    pub(crate) fn default_movement_profile(rank: Rank) -> MovementProfile {
+        // throne and traps never move, scouts travel up to 16 tiles, everything else steps 1 tile
+        MovementProfile {
+            can_move: rank != 0 && rank != 11,
+            diagonal: false,
+            jump: false,
+            max_range: if rank == 2 { 16 } else { 1 },
+        }
+    }
+    pub(crate) fn movement_maps(e: &Env, lobby_parameters: &LobbyParameters, pawns_map: &Map<PawnId, (u32, PawnState)>) -> (Map<Pos, bool>, Map<Pos, PawnId>) {
+        let mut passable_map: Map<Pos, bool> = Map::new(e);
+        for packed_tile in lobby_parameters.board.tiles.iter() {
+            let tile = Self::unpack_tile(packed_tile);
+            passable_map.set(tile.pos, tile.passable);
+        }
+        let mut pos_to_pawn_id: Map<Pos, PawnId> = Map::new(e);
+        for (_, (_, ps)) in pawns_map.iter() {
+            if ps.alive {
+                pos_to_pawn_id.set(ps.pos, ps.pawn_id);
+            }
+        }
+        (passable_map, pos_to_pawn_id)
+    }
+    pub(crate) fn move_target_counts(e: &Env, u_move: &UserMove) -> Map<Pos, u32> {
+        let mut target_counts: Map<Pos, u32> = Map::new(e);
+        for move_proof in u_move.move_proofs.iter() {
+            target_counts.set(move_proof.target_pos, target_counts.get(move_proof.target_pos).unwrap_or(0) + 1);
+        }
+        target_counts
+    }
+    pub(crate) fn is_move_legal(lobby_parameters: &LobbyParameters, profile: &MovementProfile, move_proof: &HiddenMove, passable_map: &Map<Pos, bool>, pos_to_pawn_id: &Map<Pos, PawnId>, target_counts: &Map<Pos, u32>) -> bool {
+        if !profile.can_move {
+            return false
+        }
+        let num_move_directions = Self::num_move_directions(lobby_parameters.board.hex, profile.diagonal);
+        let max_traversal_steps = profile.max_range as i32;
+        for direction_index in 0..num_move_directions {
+            let mut current_pos = move_proof.start_pos;
+            for _step in 0..max_traversal_steps {
+                let next_pos = Self::get_move_neighbor(&current_pos, lobby_parameters.board.hex, direction_index);
+                if next_pos.x == -42069 { break; }
+                if next_pos == move_proof.target_pos { return true }
+                if match passable_map.get(next_pos) { Some(p) => !p, None => true } { break; }
+                // Existing occupant blocks unless the pawn jumps
+                if !profile.jump {
+                    if let Some(_occupant_id) = pos_to_pawn_id.get(next_pos) { break; }
+                }
+                // Allied incoming move to this tile blocks line-of-sight only for single-step movers
+                if max_traversal_steps == 1 {
+                    if let Some(cnt) = target_counts.get(next_pos) { if cnt > 0 { break; } }
+                }
+                current_pos = next_pos;
+            }
+        }
+        false
+    }
+    pub(crate) fn is_move_legal_for_every_rank(lobby_parameters: &LobbyParameters, move_proof: &HiddenMove, passable_map: &Map<Pos, bool>, pos_to_pawn_id: &Map<Pos, PawnId>, target_counts: &Map<Pos, u32>) -> bool {
+        for (rank, max_rank) in lobby_parameters.max_ranks.iter().enumerate() {
+            let profile = Self::movement_profile(lobby_parameters, rank as Rank);
+            if max_rank == 0 || !profile.can_move { continue; }
+            if !Self::is_move_legal(lobby_parameters, &profile, move_proof, passable_map, pos_to_pawn_id, target_counts) {
+                return false
+            }
+        }
+        true
+    }
+    pub(crate) fn unknown_movement_profile(lobby_parameters: &LobbyParameters) -> MovementProfile {
+        // the most permissive profile among ranks in play, used for pawns whose rank is hidden
+        let mut unknown = MovementProfile { can_move: false, diagonal: false, jump: false, max_range: 0 };
+        for (i, max) in lobby_parameters.max_ranks.iter().enumerate() {
+            if max == 0 { continue; }
+            let profile = Self::movement_profile(lobby_parameters, i as Rank);
+            if !profile.can_move { continue; }
+            unknown.can_move = true;
+            unknown.diagonal |= profile.diagonal;
+            unknown.jump |= profile.jump;
+            unknown.max_range = unknown.max_range.max(profile.max_range);
+        }
+        unknown
+    }
+    pub(crate) fn combat_outcome(combat_outcomes: &Vec<CombatOutcome>, attacker_rank: Rank, defender_rank: Rank) -> CombatOutcome {
+        if combat_outcomes.is_empty() {
+            return Self::default_combat_outcome(attacker_rank, defender_rank)
+        }
+        combat_outcomes.get_unchecked(attacker_rank * 13 + defender_rank)
+    }
+    pub(crate) fn default_combat_outcome(attacker_rank: Rank, defender_rank: Rank) -> CombatOutcome {
+        // special case for trap vs seer
+        if attacker_rank == 11 && defender_rank == 3 {
+            CombatOutcome::Attacker
+        }
+        else if defender_rank == 11 && attacker_rank == 3 {
+            CombatOutcome::Defender
+        }
+        // special case for warlord vs assassin
+        else if attacker_rank == 10 && defender_rank == 1 {
+            CombatOutcome::Attacker
+        }
+        else if defender_rank == 10 && attacker_rank == 1 {
+            CombatOutcome::Defender
+        }
+        // normal cases
+        else if attacker_rank < defender_rank {
+            CombatOutcome::Attacker
+        }
+        else if defender_rank < attacker_rank {
+            CombatOutcome::Defender
+        }
+        // both die if equal rank
+        else {
+            CombatOutcome::Both
+        }
+    }
+    pub(crate) fn count_repeated_moves(history_turns: &HistoryTurns, hidden_move: &HiddenMove) -> u32 {
+        // counts the turns immediately before this one where the same pawn moved between the same two tiles
+        let mut count: u32 = 0;
+        for turn_index in (0..history_turns.turns.len()).rev() {
+            let mut repeated = false;
+            for packed_move in history_turns.turns.get_unchecked(turn_index).moves.iter() {
+                let previous_move = Self::unpack_move(packed_move);
+                if previous_move.pawn_id != hidden_move.pawn_id { continue; }
+                repeated = (previous_move.start_pos == hidden_move.start_pos && previous_move.target_pos == hidden_move.target_pos)
+                    || (previous_move.start_pos == hidden_move.target_pos && previous_move.target_pos == hidden_move.start_pos);
+                break;
+            }
+            if !repeated { break; }
+            count += 1;
+        }
+        count
+    }
+    pub(crate) fn check_turn_limit(e: &Env, game_state: &GameState, lobby_parameters: &LobbyParameters) -> (Subphase, EndReason) {
+        // called after a turn resolves without a winner. game_state.turn is the turn that just resolved
+        // returns winner and why like check_game_over. Subphase::Both means no limit was reached
+        let end_reason = if lobby_parameters.max_turns > 0 && game_state.turn >= lobby_parameters.max_turns {
+            EndReason::TurnLimit
+        } else if lobby_parameters.max_turns_without_capture > 0 && game_state.turns_without_capture >= lobby_parameters.max_turns_without_capture {
+            EndReason::NoCaptureLimit
+        } else {
+            return (Subphase::Both, EndReason::None)
+        };
+        (Self::adjudicate(e, game_state, lobby_parameters, lobby_parameters.turn_limit_adjudication), end_reason)
+    }
+    pub(crate) fn adjudicate(e: &Env, game_state: &GameState, lobby_parameters: &LobbyParameters, adjudication: Adjudication) -> Subphase {
+        // returns the winner of an unfinished game, Subphase::None for a draw
+        match adjudication {
+            Adjudication::Draw => Subphase::None,
+            Adjudication::Material => {
+                let mut h_material: u32 = 0;
+                let mut g_material: u32 = 0;
+                for packed_pawn in game_state.pawns.iter() {
+                    let pawn = Self::unpack_pawn(e, packed_pawn);
+                    if !pawn.alive { continue; }
+                    if Self::decode_pawn_id(pawn.pawn_id).1 == UserIndex::Host { h_material += 1; } else { g_material += 1; }
+                }
+                if h_material > g_material { Subphase::Host } else if g_material > h_material { Subphase::Guest } else { Subphase::None }
+            }
+            Adjudication::DeadMovables => {
+                let mut h_dead_movable: u32 = 0;
+                let mut g_dead_movable: u32 = 0;
+                for packed_pawn in game_state.pawns.iter() {
+                    let pawn = Self::unpack_pawn(e, packed_pawn);
+                    if pawn.alive || pawn.rank.is_empty() { continue; }
+                    if !Self::movement_profile(lobby_parameters, pawn.rank.get_unchecked(0)).can_move { continue; }
+                    if Self::decode_pawn_id(pawn.pawn_id).1 == UserIndex::Host { h_dead_movable += 1; } else { g_dead_movable += 1; }
+                }
+                if h_dead_movable < g_dead_movable { Subphase::Host } else if g_dead_movable < h_dead_movable { Subphase::Guest } else { Subphase::None }
+            }
+        }
+    }
+    pub(crate) fn is_queue_match(queued: &QueueEntry, rating: u32, req: &EnqueueReq) -> bool {
+        let rating_difference = queued.rating.abs_diff(rating);
+        queued.lobby_parameters == req.parameters && rating_difference <= queued.rating_window && rating_difference <= req.rating_window
+    }
     pub(crate) fn is_blitz_turn(game_state: &GameState, lobby_parameters: &LobbyParameters) -> bool {
         lobby_parameters.blitz_interval > 0 && game_state.turn % lobby_parameters.blitz_interval == 0
     }
@@ -1478,6 +3258,20 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             // neighbors[4] and neighbors[5] remain as sentinel values
         }
     }
+    pub(crate) fn num_move_directions(is_hex: bool, diagonal: bool) -> usize {
+        if is_hex { 6 } else if diagonal { 8 } else { 4 }
+    }
+    pub(crate) fn get_move_neighbor(pos: &Pos, is_hex: bool, direction_index: usize) -> Pos {
+        // directions 0-5 follow get_neighbors, 4-7 on square boards are the diagonals NE, SE, SW, NW
+        if !is_hex && direction_index >= 4 {
+            const DIAGONALS: [(i32, i32); 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)];
+            let (dx, dy) = DIAGONALS[direction_index - 4];
+            return Pos { x: pos.x + dx, y: pos.y + dy }
+        }
+        let mut neighbors = [Pos { x: -42069, y: -42069 }; 6];
+        Self::get_neighbors(pos, is_hex, &mut neighbors);
+        neighbors[direction_index]
+    }
     // cube conversion helpers removed; neighbor stepping handles both grids
     // legacy detection removed
     pub(crate) fn compute_collisions(e: &Env, game_state: &GameState, pawns_map: &Map<PawnId, (u32, PawnState)>) -> Vec<Collision> {
@@ -1536,12 +3330,15 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         }
         collisions_list
     }
-    pub(crate) fn check_game_over(e: &Env, game_state: &GameState, lobby_parameters: &LobbyParameters) -> Subphase {
+    pub(crate) fn check_game_over(e: &Env, game_state: &GameState, lobby_parameters: &LobbyParameters) -> (Subphase, EndReason) {
         // game over check happens at the end of turn resolution
-        // returns winner. Subphase::None means tie, Subphase::Both means not game over
+        // returns winner and why. Subphase::None means tie, Subphase::Both means not game over
         // case: game ends when a flag is not alive OR when a team has no movable pawns or no legal moves
         let mut h_survived = true;
         let mut g_survived = true;
+        // first reason each team lost, checked in order of precedence
+        let mut h_loss_reason = EndReason::None;
+        let mut g_loss_reason = EndReason::None;
 
         let pawns_map = Self::create_pawns_map(e, &game_state.pawns);
 
@@ -1554,8 +3351,8 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
                     let r = pawn.rank.get_unchecked(0);
                     let (_, owner_index) = Self::decode_pawn_id(pawn.pawn_id);
                     if r == 0 {
-                        if owner_index == UserIndex::Host { h_survived = false; } else { g_survived = false; }
-                    } else if r != 11 {
+                        if owner_index == UserIndex::Host { h_survived = false; h_loss_reason = EndReason::ThroneCaptured; } else { g_survived = false; g_loss_reason = EndReason::ThroneCaptured; }
+                    } else if Self::movement_profile(lobby_parameters, r).can_move {
                         if owner_index == UserIndex::Host { h_dead_movable += 1; } else { g_dead_movable += 1; }
                     }
                 }
@@ -1566,16 +3363,16 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         let mut total_movable_max: u32 = 0;
         for (i, max) in lobby_parameters.max_ranks.iter().enumerate() {
             let index = i as u32;
-            if index != 0 && index != 11 && index != 12 {
+            if index != 0 && index != 12 && Self::movement_profile(lobby_parameters, index).can_move {
                 total_movable_max += max;
             }
         }
         if total_movable_max > 0 {
-            if h_dead_movable >= total_movable_max { h_survived = false; }
-            if g_dead_movable >= total_movable_max { g_survived = false; }
+            if h_dead_movable >= total_movable_max && h_survived { h_survived = false; h_loss_reason = EndReason::NoMovablePawns; }
+            if g_dead_movable >= total_movable_max && g_survived { g_survived = false; g_loss_reason = EndReason::NoMovablePawns; }
         }
 
-        // Stalemate: no legal adjacent moves (skip known immovables 0 and 11 if revealed)
+        // Stalemate: no legal moves (skip known immovables if revealed, hidden pawns move as permissively as any rank)
         let mut passable_map: Map<Pos, bool> = Map::new(e);
         for packed_tile in lobby_parameters.board.tiles.iter() {
             let tile = Self::unpack_tile(packed_tile);
@@ -1592,29 +3389,38 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         let mut g_any_can_move = false;
         let mut h_considered_movables: u32 = 0;
         let mut g_considered_movables: u32 = 0;
-        let neighbor_count: usize = if lobby_parameters.board.hex { 6 } else { 4 };
+        let unknown_profile = Self::unknown_movement_profile(lobby_parameters);
         for (_, (_, pawn)) in pawns_map.iter() {
             if !pawn.alive { continue; }
             let (_, owner_index) = Self::decode_pawn_id(pawn.pawn_id);
-            if !pawn.rank.is_empty() {
+            let profile = if !pawn.rank.is_empty() {
                 let r = pawn.rank.get_unchecked(0);
-                if r == 0 || r == 11 { continue; }
+                let profile = Self::movement_profile(lobby_parameters, r);
+                if !profile.can_move { continue; }
                 if owner_index == UserIndex::Host { h_considered_movables += 1; } else { g_considered_movables += 1; }
-            }
-            let mut neighbors = [Pos { x: -42069, y: -42069 }; 6];
-            Self::get_neighbors(&pawn.pos, lobby_parameters.board.hex, &mut neighbors);
+                profile
+            } else {
+                // a hidden pawn may be movable, it only helps its side if it has somewhere to go
+                if owner_index == UserIndex::Host { h_considered_movables += 1; } else { g_considered_movables += 1; }
+                unknown_profile
+            };
             let mut can_move = false;
-            for j in 0..neighbor_count {
-                let next_pos = neighbors[j];
-                if next_pos.x == -42069 { continue; }
-                let is_passable = match passable_map.get(next_pos) { Some(p) => p, None => false };
-                if !is_passable { continue; }
-                match pos_to_pawn_id.get(next_pos) {
-                    Some(occupant_id) => {
-                        let (_, occ_owner) = Self::decode_pawn_id(occupant_id);
-                        if occ_owner != owner_index { can_move = true; break; }
+            'direction_scan: for direction_index in 0..Self::num_move_directions(lobby_parameters.board.hex, profile.diagonal) {
+                let mut current_pos = pawn.pos;
+                for _step in 0..profile.max_range {
+                    let next_pos = Self::get_move_neighbor(&current_pos, lobby_parameters.board.hex, direction_index);
+                    if next_pos.x == -42069 { break; }
+                    let is_passable = match passable_map.get(next_pos) { Some(p) => p, None => false };
+                    if !is_passable { break; }
+                    match pos_to_pawn_id.get(next_pos) {
+                        Some(occupant_id) => {
+                            let (_, occ_owner) = Self::decode_pawn_id(occupant_id);
+                            if occ_owner != owner_index { can_move = true; break 'direction_scan; }
+                            if !profile.jump { break; }
+                        }
+                        None => { can_move = true; break 'direction_scan; }
                     }
-                    None => { can_move = true; break; }
+                    current_pos = next_pos;
                 }
             }
             if can_move {
@@ -1625,19 +3431,23 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
 
         // Only declare blocked-loss if both sides have at least one movable pawn considered
         if h_considered_movables > 0 && g_considered_movables > 0 {
-            // Only declare blocked-loss asymmetrically. If both cannot move, do not end the game here.
-            if !h_any_can_move && g_any_can_move { h_survived = false; }
-            if !g_any_can_move && h_any_can_move { g_survived = false; }
+            // Only declare blocked-loss asymmetrically. If both cannot move, neither is at fault.
+            if !h_any_can_move && g_any_can_move && h_survived { h_survived = false; h_loss_reason = EndReason::Blocked; }
+            if !g_any_can_move && h_any_can_move && g_survived { g_survived = false; g_loss_reason = EndReason::Blocked; }
+            // If both cannot move nobody can ever prove a move again, so settle it by the lobby's policy
+            if !h_any_can_move && !g_any_can_move && h_survived && g_survived {
+                return (Self::adjudicate(e, game_state, lobby_parameters, lobby_parameters.mutual_block_adjudication), EndReason::MutualBlock)
+            }
         }
 
         match (h_survived, g_survived) {
-            (true, false) => return Subphase::Host,
-            (false, true) => return Subphase::Guest,
-            (false, false) => return Subphase::None,
+            (true, false) => return (Subphase::Host, g_loss_reason),
+            (false, true) => return (Subphase::Guest, h_loss_reason),
+            (false, false) => return (Subphase::None, h_loss_reason),
             _ => ()
         }
 
-        Subphase::Both
+        (Subphase::Both, EndReason::None)
     }
     // endregion
     // Data Access Helpers
@@ -1651,6 +3461,13 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         }
         panic!()
     }
+    pub(crate) fn get_setup_owner(setup: u32, host_team: u32) -> UserIndex {
+        // the host plays the setup side chosen by host_team, the guest gets the other one
+        if setup == host_team {
+            return UserIndex::Host
+        }
+        UserIndex::Guest
+    }
     pub(crate) fn get_opponent_index(address: &Address, lobby_info: &LobbyInfo) -> UserIndex {
         if lobby_info.host_address.contains(address) {
             return UserIndex::Guest
@@ -1660,6 +3477,30 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
         }
         panic!()
     }
+    pub(crate) fn load_user(e: &Env, user_key: &DataKey) -> Option<User> {
+        // users written before the upgrade are missing every field but current_lobby and games_completed
+        let fields: Map<Symbol, Val> = e.storage().persistent().get(user_key)?;
+        if fields.contains_key(Symbol::new(e, "rating")) {
+            return Some(User::from_val(e, &fields.to_val()))
+        }
+        let old_user = UserV0::from_val(e, &fields.to_val());
+        let mut user = Self::default_user();
+        user.current_lobby = old_user.current_lobby;
+        user.games_completed = old_user.games_completed;
+        Some(user)
+    }
+    pub(crate) fn default_user() -> User {
+        User {
+            aborts: 0,
+            current_lobby: 0,
+            draws: 0,
+            frauds: 0,
+            games_completed: 0,
+            losses: 0,
+            rating: DEFAULT_RATING,
+            wins: 0,
+        }
+    }
     pub(crate) fn create_empty_moves(e: &Env) -> Vec<UserMove> {
         Vec::from_array(e, [
             UserMove {
@@ -1689,6 +3530,13 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
     pub(crate) fn opponent_subphase_from_player_index(user_index: UserIndex) -> Subphase {
         if user_index == UserIndex::Host { Subphase::Guest } else { Subphase::Host }
     }
+    pub(crate) fn loser_from_winner(winner: Subphase) -> Subphase {
+        match winner {
+            Subphase::Host => Subphase::Guest,
+            Subphase::Guest => Subphase::Host,
+            _ => Subphase::None,
+        }
+    }
     pub(crate) fn create_pawns_map(e: &Env, pawns: &Vec<PackedPawn>) -> Map<PawnId, (u32, PawnState)> {
         let mut map = Map::new(e);
         for (index, packed_pawn) in pawns.iter().enumerate() {
@@ -1807,6 +3655,16 @@ pub(crate) fn commit_move_internal(address: &Address, req: &CommitMoveReq, lobby
             target_pos: Pos { x: tx, y: ty },
         }
     }
+    pub(crate) fn close_interrupted_turn(e: &Env, lobby_id: LobbyId, game_state: &mut GameState) {
+        // a game ended mid-turn only records the turn once both players' moves are proved. a half-proved turn
+        // never happened as far as the history is concerned, its proofs stay in the final game state
+        let host_proved = !game_state.moves.get_unchecked(UserIndex::Host.u32()).move_proofs.is_empty();
+        let guest_proved = !game_state.moves.get_unchecked(UserIndex::Guest.u32()).move_proofs.is_empty();
+        if host_proved && guest_proved {
+            game_state.turn += 1;
+            Self::record_packed_moves_for_completed_turn(e, lobby_id, game_state);
+        }
+    }
     pub(crate) fn record_packed_moves_for_completed_turn(e: &Env, lobby_id: LobbyId, game_state: &mut GameState) {
         // Completed turn is the one we just incremented to, so use turn-1 as index
         let turn_index = if game_state.turn > 0 { game_state.turn - 1 } else { 0u32 };
@@ -350,6 +350,12 @@ pub fn generate_valid_move_req(env: &Env, pawns_map: &Map<PawnId, (u32, PawnStat
     }
 
     
+    // The host plays the setup side chosen by host_team, the guest gets the other one
+    let team_side = match team {
+        UserIndex::Host => lobby_parameters.host_team,
+        UserIndex::Guest => 1 - lobby_parameters.host_team,
+    };
+    
     // Collect all pawns that can make forward moves and all pawns that can make any moves
     let mut forward_movable_pawns = Vec::new(env);
     let mut any_movable_pawns = Vec::new(env);
@@ -362,11 +368,15 @@ pub fn generate_valid_move_req(env: &Env, pawns_map: &Map<PawnId, (u32, PawnStat
             continue;
         }
         
-        // Skip if pawn is unmovable (flag or bomb) - use provided team ranks, not game state
-        if let Some(rank) = rank_map.get(pawn.pawn_id) {
-            if rank == 0 || rank == 11 {
-                continue;
-            }
+        // Look up the movement profile - use provided team ranks, not game state
+        let profile = match rank_map.get(pawn.pawn_id) {
+            Some(rank) => Contract::movement_profile(lobby_parameters, rank),
+            None => Contract::unknown_movement_profile(lobby_parameters),
+        };
+        
+        // Skip if pawn is unmovable (flag or bomb by default)
+        if !profile.can_move {
+            continue;
         }
         
         // Team check is legitimate - ensure we only move our own pieces
@@ -375,53 +385,66 @@ pub fn generate_valid_move_req(env: &Env, pawns_map: &Map<PawnId, (u32, PawnStat
             continue; // Skip if pawn doesn't belong to this team
         }
         
-        // Get adjacent positions (up, down, left, right)
-        let adjacent_positions = Vec::from_array(env, [
-            Pos { x: pawn.pos.x, y: pawn.pos.y + 1 }, // up
-            Pos { x: pawn.pos.x, y: pawn.pos.y - 1 }, // down
-            Pos { x: pawn.pos.x - 1, y: pawn.pos.y }, // left
-            Pos { x: pawn.pos.x + 1, y: pawn.pos.y }, // right
-        ]);
+        // Directions: up, down, left, right, then diagonals if the profile allows them
+        let directions = [(0, 1), (0, -1), (-1, 0), (1, 0), (1, -1), (1, 1), (-1, 1), (-1, -1)];
+        let direction_count = if profile.diagonal { 8 } else { 4 };
         
         let mut valid_moves = Vec::new(env);
         let mut forward_moves = Vec::new(env);
         
-        for target_pos in adjacent_positions.iter() {
-            // Check if position is within board bounds
-            if target_pos.x < 0 || target_pos.x >= lobby_parameters.board.size.x ||
-               target_pos.y < 0 || target_pos.y >= lobby_parameters.board.size.y {
-                continue;
-            }
-            
-            // Check if tile exists and is passable
-            if let Some(tile) = tile_map.get(target_pos) {
-                if !tile.passable {
-                    continue;
+        for (dx, dy) in directions.iter().take(direction_count) {
+            let mut target_pos = pawn.pos;
+            // Travel in a straight line up to the profile's range
+            for _step in 0..profile.max_range {
+                target_pos = Pos { x: target_pos.x + dx, y: target_pos.y + dy };
+                
+                // Check if position is within board bounds
+                if target_pos.x < 0 || target_pos.x >= lobby_parameters.board.size.x ||
+                   target_pos.y < 0 || target_pos.y >= lobby_parameters.board.size.y {
+                    break;
                 }
-            } else {
-                continue;
-            }
-            
-            // Check if position is occupied by same team pawn
-            if let Some(occupying_pawn) = pawn_position_map.get(target_pos) {
-                let (_, occupying_team) = Contract::decode_pawn_id(occupying_pawn.pawn_id);
-                if occupying_team == *team {
-                    continue; // Skip if occupied by same team
+                
+                // Check if tile exists and is passable
+                if let Some(tile) = tile_map.get(target_pos) {
+                    if !tile.passable {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+                
+                // Check if position is occupied - same team pawns can only be jumped over
+                let occupied = if let Some(occupying_pawn) = pawn_position_map.get(target_pos) {
+                    let (_, occupying_team) = Contract::decode_pawn_id(occupying_pawn.pawn_id);
+                    if occupying_team == *team {
+                        if profile.jump {
+                            continue;
+                        }
+                        break; // Skip if occupied by same team
+                    }
+                    true
+                } else {
+                    false
+                };
+                
+                // This is a valid move
+                valid_moves.push_back(target_pos);
+                
+                // Check if this is a "forward" move
+                let is_forward = match team_side {
+                    0 => target_pos.y > pawn.pos.y, // Team 0 moves up (increasing y)
+                    1 => target_pos.y < pawn.pos.y, // Team 1 moves down (decreasing y)
+                    _ => false,
+                };
+                
+                if is_forward {
+                    forward_moves.push_back(target_pos);
+                }
+                
+                // Enemy pawns stop travel unless the pawn jumps
+                if occupied && !profile.jump {
+                    break;
                 }
-            }
-            
-            // This is a valid move
-            valid_moves.push_back(target_pos);
-            
-            // Check if this is a "forward" move
-            let is_forward = match team {
-                UserIndex::Host => target_pos.y > pawn.pos.y, // Team 0 moves up (increasing y)
-                UserIndex::Guest => target_pos.y < pawn.pos.y, // Team 1 moves down (decreasing y)
-                _ => false,
-            };
-            
-            if is_forward {
-                forward_moves.push_back(target_pos);
             }
         }
         
@@ -563,11 +586,29 @@ pub fn create_test_lobby_parameters(env: &Env) -> LobbyParameters {
         blitz_max_simultaneous_moves: 1,
         board_hash,
         board,
+        clock_budget: 0,
+        clock_increment: 0,
+        combat_outcomes: Vec::new(env),
         dev_mode: true,
+        disconnect_window: 0,
+        dispute_window: 0,
+        hash_scheme: HashScheme::Legacy,
         host_team: 0,
+        invited_guests: Vec::new(env),
         max_ranks: Vec::from_array(env, DEFAULT_MAX_RANKS),
+        max_repeated_moves: 0,
+        max_turns: 0,
+        max_turns_without_capture: 0,
+        movement_profiles: Vec::new(env),
+        mutual_block_adjudication: Adjudication::Draw,
         must_fill_all_tiles: false,
+        reap_grace_period: 0,
         security_mode: true,
+        series_target_wins: 0,
+        setup_reveal: false,
+        stake_amount: 0,
+        stake_token: Vec::new(env),
+        turn_limit_adjudication: Adjudication::Draw,
     }
 }
 
@@ -595,11 +636,29 @@ pub fn create_invalid_board_parameters(env: &Env) -> LobbyParameters {
         blitz_max_simultaneous_moves: 1,
         board_hash,
         board,
+        clock_budget: 0,
+        clock_increment: 0,
+        combat_outcomes: Vec::new(env),
         dev_mode: true,
+        disconnect_window: 0,
+        dispute_window: 0,
+        hash_scheme: HashScheme::Legacy,
         host_team: 0,
+        invited_guests: Vec::new(env),
         max_ranks: Vec::from_array(env, [1u32, 0,0,0,0,0,0,0,0,0,0,0]),
+        max_repeated_moves: 0,
+        max_turns: 0,
+        max_turns_without_capture: 0,
+        movement_profiles: Vec::new(env),
+        mutual_block_adjudication: Adjudication::Draw,
         must_fill_all_tiles: false,
+        reap_grace_period: 0,
         security_mode: true,
+        series_target_wins: 0,
+        setup_reveal: false,
+        stake_amount: 0,
+        stake_token: Vec::new(env),
+        turn_limit_adjudication: Adjudication::Draw,
     }
 }
 
@@ -617,11 +676,29 @@ pub fn create_full_stratego_board_parameters(env: &Env) -> LobbyParameters {
         blitz_max_simultaneous_moves: 1,
         board_hash,
         board,
+        clock_budget: 0,
+        clock_increment: 0,
+        combat_outcomes: Vec::new(env),
         dev_mode: false,
+        disconnect_window: 0,
+        dispute_window: 0,
+        hash_scheme: HashScheme::Legacy,
         host_team: 0,
+        invited_guests: Vec::new(env),
         max_ranks: Vec::from_array(env, DEFAULT_MAX_RANKS),
+        max_repeated_moves: 0,
+        max_turns: 0,
+        max_turns_without_capture: 0,
+        movement_profiles: Vec::new(env),
+        mutual_block_adjudication: Adjudication::Draw,
         must_fill_all_tiles: true,
+        reap_grace_period: 0,
         security_mode: true,
+        series_target_wins: 0,
+        setup_reveal: false,
+        stake_amount: 0,
+        stake_token: Vec::new(env),
+        turn_limit_adjudication: Adjudication::Draw,
     }
 }
 
@@ -710,8 +787,7 @@ pub fn create_setup_commits_from_game_state(env: &Env, lobby_id: u32, team: &Use
         };
         
         let serialized_hidden_rank = hidden_rank.clone().to_xdr(env);
-        let full_hash = env.crypto().sha256(&serialized_hidden_rank).to_bytes().to_array();
-        let hidden_rank_hash = HiddenRankHash::from_array(env, &full_hash[0..16].try_into().unwrap());
+        let hidden_rank_hash = Contract::commitment_hash(env, lobby_parameters.hash_scheme, HASH_TAG_LEAF, &serialized_hidden_rank);
 
         hidden_ranks.push_back(hidden_rank.clone());
         
@@ -789,8 +865,7 @@ pub fn create_setup_commits_from_game_state2(env: &Env, lobby_id: u32) -> ((Vec<
         };
         
         let serialized_hidden_rank = hidden_rank.clone().to_xdr(env);
-        let full_hash = env.crypto().sha256(&serialized_hidden_rank).to_bytes().to_array();
-        let hidden_rank_hash = HiddenRankHash::from_array(env, &full_hash[0..16].try_into().unwrap());
+        let hidden_rank_hash = Contract::commitment_hash(env, lobby_parameters.hash_scheme, HASH_TAG_LEAF, &serialized_hidden_rank);
         
         host_hidden_ranks.push_back(hidden_rank.clone());
         
@@ -820,8 +895,7 @@ pub fn create_setup_commits_from_game_state2(env: &Env, lobby_id: u32) -> ((Vec<
         };
         
         let serialized_hidden_rank = hidden_rank.clone().to_xdr(env);
-        let full_hash = env.crypto().sha256(&serialized_hidden_rank).to_bytes().to_array();
-        let hidden_rank_hash = HiddenRankHash::from_array(env, &full_hash[0..16].try_into().unwrap());
+        let hidden_rank_hash = Contract::commitment_hash(env, lobby_parameters.hash_scheme, HASH_TAG_LEAF, &serialized_hidden_rank);
         
         guest_hidden_ranks.push_back(hidden_rank.clone());
         
@@ -907,7 +981,7 @@ pub fn create_setup_commits_from_game_state2(env: &Env, lobby_id: u32) -> ((Vec<
 //
 //         let serialized_hidden_rank = hidden_rank.clone().to_xdr(env);
 //         let full_hash = env.crypto().sha256(&serialized_hidden_rank).to_bytes().to_array();
-//         let hidden_rank_hash = HiddenRankHash::from_array(env, &full_hash[0..16].try_into().unwrap());
+//         let hidden_rank_hash = HiddenRankHash::from_slice(env, &full_hash[0..16]);
 //
 //         rank_hashes.push_back(hidden_rank_hash.clone());
 //         hidden_ranks.push_back(hidden_rank.clone());
@@ -1216,18 +1290,23 @@ pub fn validate_history_after_step(pre: &SnapshotFull, post: &SnapshotFull) {
     }
 }
 
-pub fn get_merkel(e: &Env, setup_commits: &Vec<SetupCommit>, hidden_ranks: &Vec<HiddenRank>) -> (BytesN<16>, Vec<MerkleProof>) {
+pub fn get_merkel(e: &Env, setup_commits: &Vec<SetupCommit>, hidden_ranks: &Vec<HiddenRank>) -> (MerkleHash, Vec<MerkleProof>) {
+    get_merkel_with_scheme(e, setup_commits, hidden_ranks, HashScheme::Legacy)
+}
+
+pub fn get_merkel_with_scheme(e: &Env, setup_commits: &Vec<SetupCommit>, hidden_ranks: &Vec<HiddenRank>, hash_scheme: HashScheme) -> (MerkleHash, Vec<MerkleProof>) {
     let mut leaves: Vec<HiddenRankHash> = Vec::new(e);
     for commit in setup_commits.iter() {
         leaves.push_back(commit.hidden_rank_hash.clone())
     }
     let mut proofs: Vec<MerkleProof> = Vec::new(e);
-    let (root, tree) = build_merkle_tree(e, leaves.clone());
+    let (root, tree) = build_merkle_tree_with_scheme(e, leaves.clone(), hash_scheme);
     for (i, leaf) in leaves.iter().enumerate() {
         let proof = tree.generate_proof(e, i as u32);
         proofs.push_back(proof);
     }
-    return (root, proofs);
+    // the contract commits to the tree root together with the leaf count
+    return (Contract::rank_commitment(e, hash_scheme, &root, leaves.len()), proofs);
 }
 // endregion
 
@@ -1371,11 +1450,29 @@ pub fn create_user_board_parameters(env: &Env) -> LobbyParameters {
         blitz_max_simultaneous_moves: 1,
         board,
         board_hash: BytesN::from_array(env, &[0u8; 16]),
+        clock_budget: 0,
+        clock_increment: 0,
+        combat_outcomes: Vec::new(env),
         dev_mode: false,
+        disconnect_window: 0,
+        dispute_window: 0,
+        hash_scheme: HashScheme::Legacy,
         host_team: 0,
+        invited_guests: Vec::new(env),
         max_ranks: Vec::from_array(env, [1, 1, 8, 3, 4, 4, 4, 3, 2, 1, 1, 4, 0]),
+        max_repeated_moves: 0,
+        max_turns: 0,
+        max_turns_without_capture: 0,
+        movement_profiles: Vec::new(env),
+        mutual_block_adjudication: Adjudication::Draw,
         must_fill_all_tiles: true,
+        reap_grace_period: 0,
         security_mode: true,
+        series_target_wins: 0,
+        setup_reveal: false,
+        stake_amount: 0,
+        stake_token: Vec::new(env),
+        turn_limit_adjudication: Adjudication::Draw,
     }
 }
 
@@ -1432,7 +1529,13 @@ impl Tree {
     }
 }
 
-pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tree) {
+pub fn build_merkle_tree(env: &Env, leaves: Vec<MerkleHash>) -> (MerkleHash, Tree) {
+    build_merkle_tree_with_scheme(env, leaves, HashScheme::Legacy)
+}
+
+pub fn build_merkle_tree_with_scheme(env: &Env, leaves: Vec<MerkleHash>, hash_scheme: HashScheme) -> (MerkleHash, Tree) {
+    let zero_bytes = [0u8; 32];
+    let hash_length = Contract::hash_length(hash_scheme) as usize;
     //std::println!("=== build_merkle_tree START ===");
     //std::println!("Number of leaves: {}", leaves.len());
     
@@ -1441,7 +1544,7 @@ pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tre
     }
     
     if leaves.is_empty() {
-        let zero_root = MerkleHash::from_array(env, &[0u8; 16]);
+        let zero_root = MerkleHash::from_slice(env, &zero_bytes[0..hash_length]);
         let tree = Tree {
             leaves: Vec::new(env),
             levels: Vec::from_array(env, [Vec::from_array(env, [zero_root.clone()])]),
@@ -1451,7 +1554,7 @@ pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tre
     }
     
     let mut padded_leaves = leaves.clone();
-    let empty_hash = MerkleHash::from_array(env, &[0u8; 16]);
+    let empty_hash = MerkleHash::from_slice(env, &zero_bytes[0..hash_length]);
     
     let mut target_size = 1;
     while target_size < leaves.len() {
@@ -1483,15 +1586,12 @@ pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tre
             
             //std::println!("  Pair {}: left={:?}, right={:?}", i/2, left.to_array(), right.to_array());
             
-            let mut combined_bytes = [0u8; 32];
-            combined_bytes[0..16].copy_from_slice(&left.to_array());
-            combined_bytes[16..32].copy_from_slice(&right.to_array());
+            let mut combined_bytes = left.clone();
+            combined_bytes.append(&right);
             
             //std::println!("  Combined bytes: {:?}", combined_bytes);
             
-            let parent_full = env.crypto().sha256(&Bytes::from_array(env, &combined_bytes));
-            let parent_bytes = parent_full.to_array();
-            let parent_hash = MerkleHash::from_array(env, &parent_bytes[0..16].try_into().unwrap());
+            let parent_hash = Contract::commitment_hash(env, hash_scheme, HASH_TAG_NODE, &combined_bytes);
             
             //std::println!("  Parent hash: {:?}", parent_hash.to_array());
             
@@ -355,7 +355,7 @@
 //             for mv in host_prove.move_proofs.iter() {
 //                 let ser = mv.clone().to_xdr(&setup.env);
 //                 let full = setup.env.crypto().sha256(&ser).to_bytes().to_array();
-//                 hashes.push_back(HiddenMoveHash::from_array(&setup.env, &full[0..16].try_into().unwrap()));
+//                 hashes.push_back(HiddenMoveHash::from_slice(&setup.env, &full[0..16]));
 //             }
 //             CommitMoveReq { lobby_id, move_hashes: hashes }
 //         };
@@ -364,7 +364,7 @@
 //             for mv in guest_prove.move_proofs.iter() {
 //                 let ser = mv.clone().to_xdr(&setup.env);
 //                 let full = setup.env.crypto().sha256(&ser).to_bytes().to_array();
-//                 hashes.push_back(HiddenMoveHash::from_array(&setup.env, &full[0..16].try_into().unwrap()));
+//                 hashes.push_back(HiddenMoveHash::from_slice(&setup.env, &full[0..16]));
 //             }
 //             CommitMoveReq { lobby_id, move_hashes: hashes }
 //         };
@@ -226,14 +226,13 @@ pub fn execute_insecure_batch_pattern(
 #[test]
 pub fn test_insecure_blitz_multi_move_resolution() {
     let setup = TestSetup::new();
-    let lobby_id = 5001;
     let host = setup.generate_address();
     let guest = setup.generate_address();
     let mut params = create_test_lobby_parameters(&setup.env);
     params.security_mode = false;
     params.blitz_interval = 1;
     params.blitz_max_simultaneous_moves = 3;
-    setup.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params.clone() }, ">blitz host");
+    let lobby_id = setup.make_lobby(&host, &MakeLobbyReq { parameters: params.clone() }, ">blitz host");
     setup.join_lobby(&guest, &JoinLobbyReq { lobby_id }, ">blitz guest");
     let (host_setup, host_ranks) = setup.env.as_contract(&setup.contract_id, || {
         create_setup_commits_from_game_state(&setup.env, lobby_id, &UserIndex::Host)
@@ -257,7 +256,7 @@ pub fn test_insecure_blitz_multi_move_resolution() {
             for mv in host_prove.move_proofs.iter() {
                 let ser = mv.clone().to_xdr(&setup.env);
                 let full = setup.env.crypto().sha256(&ser).to_bytes().to_array();
-                hashes.push_back(HiddenMoveHash::from_array(&setup.env, &full[0..16].try_into().unwrap()));
+                hashes.push_back(HiddenMoveHash::from_slice(&setup.env, &full[0..16]));
             }
             hashes
         };
@@ -266,7 +265,7 @@ pub fn test_insecure_blitz_multi_move_resolution() {
             for mv in guest_prove.move_proofs.iter() {
                 let ser = mv.clone().to_xdr(&setup.env);
                 let full = setup.env.crypto().sha256(&ser).to_bytes().to_array();
-                hashes.push_back(HiddenMoveHash::from_array(&setup.env, &full[0..16].try_into().unwrap()));
+                hashes.push_back(HiddenMoveHash::from_slice(&setup.env, &full[0..16]));
             }
             hashes
         };
@@ -337,9 +336,6 @@ fn test_compare_move_submission_methods() {
     let host_insecure_label = "insecure host";
     let guest_insecure_label = "insecure guest";
     let setup = TestSetup::new();
-    // made up lobby ids
-    let lobby_secure = 301;
-    let lobby_insecure = 302;
     // four unique addresses
     let host_secure = setup.generate_address();
     let guest_secure = setup.generate_address();  
@@ -349,12 +345,10 @@ fn test_compare_move_submission_methods() {
     let secure_params = create_test_lobby_parameters(&setup.env);
     let mut insecure_params = create_test_lobby_parameters(&setup.env);
     insecure_params.security_mode = false;
-    setup.make_lobby(&host_secure, &MakeLobbyReq {
-        lobby_id: lobby_secure,
+    let lobby_secure = setup.make_lobby(&host_secure, &MakeLobbyReq {
         parameters: secure_params,
     }, host_secure_label);
-    setup.make_lobby(&host_insecure, &MakeLobbyReq {
-        lobby_id: lobby_insecure,
+    let lobby_insecure = setup.make_lobby(&host_insecure, &MakeLobbyReq {
         parameters: insecure_params,
     }, host_insecure_label);
     
@@ -418,10 +412,10 @@ fn test_compare_move_submission_methods() {
         let guest_move_proof = guest_move_opt.unwrap();
         let host_serialized = host_move_proof.clone().to_xdr(&setup.env);
         let host_hash_full = setup.env.crypto().sha256(&host_serialized).to_bytes().to_array();
-        let host_hash = HiddenMoveHash::from_array(&setup.env, &host_hash_full[0..16].try_into().unwrap());
+        let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
         let guest_serialized = guest_move_proof.clone().to_xdr(&setup.env);
         let guest_hash_full = setup.env.crypto().sha256(&guest_serialized).to_bytes().to_array();
-        let guest_hash = HiddenMoveHash::from_array(&setup.env, &guest_hash_full[0..16].try_into().unwrap());
+        let guest_hash = HiddenMoveHash::from_slice(&setup.env, &guest_hash_full[0..16]);
         // do secure turn
         {
             // create move reqs
@@ -530,14 +524,13 @@ fn test_compare_move_submission_methods() {
 #[test]
 fn test_redeem_win_simple() {
     let setup = TestSetup::new();
-    let lobby_id = 9000;
     
     // Create a simple lobby
     let host = setup.generate_address();
     let guest = setup.generate_address();
     
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     
     // Verify the lobby exists and is in SetupCommit phase
@@ -568,6 +561,8 @@ fn test_redeem_win_simple() {
     let result = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
     assert_eq!(result.phase, Phase::Aborted);
     assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::Timeout);
+    assert_eq!(result.end_player, Subphase::Guest);
 }
 
 #[test]
@@ -575,7 +570,7 @@ fn test_redeem_win_timeout_boundaries() {
     let setup = TestSetup::new();
     
     // Set up lobby and advance to MoveCommit phase
-    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup, 600);
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
     
     // Verify the lobby exists
     setup.verify_lobby_info(lobby_id, &host, Phase::MoveCommit);
@@ -599,7 +594,7 @@ fn test_redeem_win_timeout_boundaries() {
     // Create move hash
     let host_serialized = host_move.to_xdr(&setup.env);
     let host_hash_full = setup.env.crypto().sha256(&host_serialized).to_bytes().to_array();
-    let host_hash = HiddenMoveHash::from_array(&setup.env, &host_hash_full[0..16].try_into().unwrap());
+    let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
     
     // Host commits move
     setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
@@ -620,9 +615,11 @@ fn test_redeem_win_timeout_boundaries() {
     let exact_result = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
     assert_eq!(exact_result.phase, Phase::Finished);
     assert_eq!(exact_result.subphase, Subphase::Host);
+    assert_eq!(exact_result.end_reason, EndReason::Timeout);
+    assert_eq!(exact_result.end_player, Subphase::Guest);
     
     // Test 3: Set up another game for "well after timeout" test
-    let (lobby_id2, host2, guest2, _, _, _, _) = setup_lobby_for_commit_move(&setup, 601);
+    let (lobby_id2, host2, guest2, _, _, _, _) = setup_lobby_for_commit_move(&setup);
     
     // Get valid move and commit
     let snapshot2 = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id2);
@@ -637,7 +634,7 @@ fn test_redeem_win_timeout_boundaries() {
     
     let host_serialized2 = host_move2.to_xdr(&setup.env);
     let host_hash_full2 = setup.env.crypto().sha256(&host_serialized2).to_bytes().to_array();
-    let host_hash2 = HiddenMoveHash::from_array(&setup.env, &host_hash_full2[0..16].try_into().unwrap());
+    let host_hash2 = HiddenMoveHash::from_slice(&setup.env, &host_hash_full2[0..16]);
     
     setup.client.commit_move(&host2, &CommitMoveReq { lobby_id: lobby_id2, move_hashes: Vec::from_array(&setup.env, [host_hash2]) });
     
@@ -657,7 +654,7 @@ fn test_redeem_win_phase_specific_behavior() {
     let setup = TestSetup::new();
     
     // Test 1: SetupCommit timeout -> Aborted with no winner
-    let (lobby_id1, host1, guest1) = setup_lobby_for_commit_setup(&setup, 700);
+    let (lobby_id1, host1, guest1) = setup_lobby_for_commit_setup(&setup);
     
     // Host commits setup, guest doesn't
     let (host_setup, host_ranks) = setup.env.as_contract(&setup.contract_id, || {
@@ -681,7 +678,7 @@ fn test_redeem_win_phase_specific_behavior() {
     assert_eq!(result1.subphase, Subphase::None);
     
     // Test 2: MoveCommit timeout -> Winner
-    let (lobby_id2, host2, guest2, _, _, _, _) = setup_lobby_for_commit_move(&setup, 701);
+    let (lobby_id2, host2, guest2, _, _, _, _) = setup_lobby_for_commit_move(&setup);
     
     // Get valid move for host
     let snapshot2 = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id2);
@@ -696,7 +693,7 @@ fn test_redeem_win_phase_specific_behavior() {
     
     let host_serialized = host_move.to_xdr(&setup.env);
     let host_hash_full = setup.env.crypto().sha256(&host_serialized).to_bytes().to_array();
-    let host_hash = HiddenMoveHash::from_array(&setup.env, &host_hash_full[0..16].try_into().unwrap());
+    let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
     
     setup.client.commit_move(&host2, &CommitMoveReq { lobby_id: lobby_id2, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
     
@@ -712,7 +709,7 @@ fn test_redeem_win_phase_specific_behavior() {
 fn test_redeem_win_state_changes() {
     let setup = TestSetup::new();
     
-    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup, 900);
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
     
     // Get valid move for host
     let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
@@ -727,7 +724,7 @@ fn test_redeem_win_state_changes() {
     
     let host_serialized = host_move.to_xdr(&setup.env);
     let host_hash_full = setup.env.crypto().sha256(&host_serialized).to_bytes().to_array();
-    let host_hash = HiddenMoveHash::from_array(&setup.env, &host_hash_full[0..16].try_into().unwrap());
+    let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
     
     setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
     
@@ -764,7 +761,7 @@ fn test_redeem_win_state_changes() {
 fn test_action_invalidates_timeout_claim() {
     let setup = TestSetup::new();
     
-    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup, 1000);
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
     
     // Get valid moves for both players
     let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
@@ -789,11 +786,11 @@ fn test_action_invalidates_timeout_claim() {
     // Create hashes
     let host_serialized = host_move.to_xdr(&setup.env);
     let host_hash_full = setup.env.crypto().sha256(&host_serialized).to_bytes().to_array();
-    let host_hash = HiddenMoveHash::from_array(&setup.env, &host_hash_full[0..16].try_into().unwrap());
+    let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
     
     let guest_serialized = guest_move.to_xdr(&setup.env);
     let guest_hash_full = setup.env.crypto().sha256(&guest_serialized).to_bytes().to_array();
-    let guest_hash = HiddenMoveHash::from_array(&setup.env, &guest_hash_full[0..16].try_into().unwrap());
+    let guest_hash = HiddenMoveHash::from_slice(&setup.env, &guest_hash_full[0..16]);
     
     // Host commits move
     setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
@@ -821,5 +818,448 @@ fn test_action_invalidates_timeout_claim() {
     assert_eq!(final_state.subphase, Subphase::Both);
 }
 
+#[test]
+fn test_redeem_win_clock_time_controls() {
+    let setup = TestSetup::new();
+    let mut lobby_parameters = create_full_stratego_board_parameters(&setup.env);
+    lobby_parameters.clock_budget = 50;
+    lobby_parameters.clock_increment = 10;
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: lobby_parameters });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    advance_through_complete_setup_phase(&setup, lobby_id, &host, &guest);
+    
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.host_clock, 50);
+    assert_eq!(snapshot.lobby_info.guest_clock, 50);
+    let start_ledger = snapshot.lobby_info.last_edited_ledger_seq;
+    
+    // Host spends 20 ledgers on the move and gets the increment back, the guest's clock kept running
+    let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Host, &Vec::new(&setup.env), 12351).unwrap();
+    let host_hash_full = setup.env.crypto().sha256(&host_move.to_xdr(&setup.env)).to_bytes().to_array();
+    let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
+    extend_lobby_ttl(&setup, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 20);
+    let after_commit = setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
+    assert_eq!(after_commit.subphase, Subphase::Guest);
+    assert_eq!(after_commit.host_clock, 40);
+    assert_eq!(after_commit.guest_clock, 30);
+    
+    // The guest can't be claimed against while time is left, even though the host's clock is stopped
+    extend_lobby_ttl(&setup, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 49);
+    assert!(setup.client.try_redeem_win(&host, &RedeemWinReq { lobby_id }).is_err());
+    assert!(setup.client.try_redeem_win(&guest, &RedeemWinReq { lobby_id }).is_err());
+    
+    // Once the guest's clock hits zero the host wins well before the fixed timeout
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 50);
+    let result = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::Host);
+    assert_eq!(result.end_reason, EndReason::Timeout);
+    assert_eq!(result.end_player, Subphase::Guest);
+}
+
+#[test]
+fn test_redeem_win_both_clocks_out_is_a_draw() {
+    let setup = TestSetup::new();
+    let mut lobby_parameters = create_full_stratego_board_parameters(&setup.env);
+    lobby_parameters.clock_budget = 50;
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: lobby_parameters });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    advance_through_complete_setup_phase(&setup, lobby_id, &host, &guest);
+    let start_ledger = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info.last_edited_ledger_seq;
+    
+    // Both owe a move and neither makes it, so both clocks run out together
+    extend_lobby_ttl(&setup, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 50);
+    let result = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::Timeout);
+    assert_eq!(result.end_player, Subphase::None);
+}
+
+// endregion
+// region reap_stale_lobby tests
+
+#[test]
+fn test_reap_stale_lobby_aborts_stalled_setup() {
+    let setup = TestSetup::new();
+    let mut lobby_parameters = create_full_stratego_board_parameters(&setup.env);
+    lobby_parameters.reap_grace_period = 50;
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: lobby_parameters });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let lobby_info = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(lobby_info.phase, Phase::SetupCommit);
+    assert_eq!(lobby_info.subphase, Subphase::Both);
+    let start_ledger = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info.last_edited_ledger_seq;
+    
+    // The phase timeout alone is not enough, the grace period has to pass too
+    extend_lobby_ttl(&setup, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 149);
+    assert!(setup.client.try_reap_stale_lobby(&ReapStaleLobbyReq { lobby_id }).is_err());
+    
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 150);
+    let result = setup.client.reap_stale_lobby(&ReapStaleLobbyReq { lobby_id });
+    assert_eq!(result.phase, Phase::Aborted);
+    assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::Timeout);
+    assert_eq!(result.end_player, Subphase::None);
+    
+    // Both players are released and the history is finalized
+    assert_eq!(setup.client.get_user(&host).current_lobby, 0);
+    assert_eq!(setup.client.get_user(&guest).current_lobby, 0);
+    let history: History = setup.env.as_contract(&setup.contract_id, || {
+        setup.env.storage().temporary().get(&DataKey::History(lobby_id)).unwrap()
+    });
+    assert_eq!(history.final_lobby_info.len(), 1);
+    assert_eq!(history.final_lobby_info.get_unchecked(0), result);
+    
+    // Reaping twice is rejected
+    assert!(setup.client.try_reap_stale_lobby(&ReapStaleLobbyReq { lobby_id }).is_err());
+}
+
+#[test]
+fn test_reap_stale_lobby_draws_stalled_game() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
+    let start_ledger = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info.last_edited_ledger_seq;
+    
+    extend_lobby_ttl(&setup, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 99);
+    assert!(setup.client.try_reap_stale_lobby(&ReapStaleLobbyReq { lobby_id }).is_err());
+    
+    // Neither player can claim a timeout while both owe a move
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 100);
+    assert!(setup.client.try_redeem_win(&host, &RedeemWinReq { lobby_id }).is_err());
+    assert!(setup.client.try_redeem_win(&guest, &RedeemWinReq { lobby_id }).is_err());
+    let result = setup.client.reap_stale_lobby(&ReapStaleLobbyReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::Timeout);
+    assert_eq!(setup.client.get_user(&host).current_lobby, 0);
+    assert_eq!(setup.client.get_user(&guest).current_lobby, 0);
+    assert_eq!(setup.client.get_user(&host).draws, 1);
+}
+
+#[test]
+fn test_reap_stale_lobby_requires_both_subphase() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, _, _, _, _, _) = setup_lobby_for_commit_move(&setup);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Host, &Vec::new(&setup.env), 12352).unwrap();
+    let host_hash_full = setup.env.crypto().sha256(&host_move.to_xdr(&setup.env)).to_bytes().to_array();
+    let host_hash = HiddenMoveHash::from_slice(&setup.env, &host_hash_full[0..16]);
+    setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
+    
+    // Only the guest owes an action now, so the host redeems the win instead
+    extend_lobby_ttl(&setup, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = snapshot.lobby_info.last_edited_ledger_seq + 1000);
+    assert!(setup.client.try_reap_stale_lobby(&ReapStaleLobbyReq { lobby_id }).is_err());
+    assert_eq!(setup.client.redeem_win(&host, &RedeemWinReq { lobby_id }).phase, Phase::Finished);
+}
+
+// endregion
+// region disconnect tests
+
+fn setup_lobby_with_disconnect_window(setup: &TestSetup, disconnect_window: u32) -> (u32, Address, Address, u32) {
+    let mut lobby_parameters = create_full_stratego_board_parameters(&setup.env);
+    lobby_parameters.disconnect_window = disconnect_window;
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: lobby_parameters });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    advance_through_complete_setup_phase(setup, lobby_id, &host, &guest);
+    let start_ledger = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info.last_edited_ledger_seq;
+    extend_lobby_ttl(setup, lobby_id);
+    (lobby_id, host, guest, start_ledger)
+}
+
+#[test]
+fn test_disconnect_claim_after_window() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, start_ledger) = setup_lobby_with_disconnect_window(&setup, 30);
+    
+    let lobby_info = setup.client.disconnect(&host, &SessionReq { lobby_id });
+    assert_eq!(lobby_info.disconnected, Subphase::Host);
+    assert_eq!(lobby_info.phase, Phase::MoveCommit);
+    assert_eq!(lobby_info.last_edited_ledger_seq, start_ledger);
+    
+    // Both still owe a move, but the guest can claim once the host's window runs out
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 29);
+    assert!(setup.client.try_redeem_win(&guest, &RedeemWinReq { lobby_id }).is_err());
+    assert!(setup.client.try_redeem_win(&host, &RedeemWinReq { lobby_id }).is_err());
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 30);
+    let result = setup.client.redeem_win(&guest, &RedeemWinReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::Guest);
+    assert_eq!(result.end_reason, EndReason::Timeout);
+    assert_eq!(result.end_player, Subphase::Host);
+}
+
+#[test]
+fn test_rejoin_restores_session() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, start_ledger) = setup_lobby_with_disconnect_window(&setup, 30);
+    
+    setup.client.disconnect(&host, &SessionReq { lobby_id });
+    // Only one player can be away at a time, and only they can rejoin
+    assert!(setup.client.try_disconnect(&host, &SessionReq { lobby_id }).is_err());
+    assert!(setup.client.try_disconnect(&guest, &SessionReq { lobby_id }).is_err());
+    assert!(setup.client.try_rejoin(&guest, &SessionReq { lobby_id }).is_err());
+    
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 29);
+    let lobby_info = setup.client.rejoin(&host, &SessionReq { lobby_id });
+    assert_eq!(lobby_info.disconnected, Subphase::None);
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 30);
+    assert!(setup.client.try_redeem_win(&guest, &RedeemWinReq { lobby_id }).is_err());
+    
+    // A rejoin after the window has expired is too late
+    setup.client.disconnect(&host, &SessionReq { lobby_id });
+    setup.env.ledger().with_mut(|l| l.sequence_number = start_ledger + 60);
+    assert!(setup.client.try_rejoin(&host, &SessionReq { lobby_id }).is_err());
+    assert_eq!(setup.client.redeem_win(&guest, &RedeemWinReq { lobby_id }).subphase, Subphase::Guest);
+}
+
+// endregion
+// region setup reveal tests
+
+fn setup_lobby_with_setup_reveal(setup: &TestSetup, host_throne_count: u32) -> (u32, Address, Address, RevealSetupReq, RevealSetupReq) {
+    let mut lobby_parameters = create_full_stratego_board_parameters(&setup.env);
+    lobby_parameters.setup_reveal = true;
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: lobby_parameters });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let mut reveal_reqs = std::vec::Vec::new();
+    for (address, team) in [(&host, UserIndex::Host), (&guest, UserIndex::Guest)] {
+        let (mut setup_commits, mut hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
+            create_setup_commits_from_game_state(&setup.env, lobby_id, &team)
+        });
+        if team == UserIndex::Host {
+            // turn extra pawns into thrones, an illegal army that no collision would ever reveal
+            for i in 1..host_throne_count {
+                let mut hidden_rank = hidden_ranks.get_unchecked(i);
+                hidden_rank.rank = 0;
+                let hidden_rank_hash = Contract::commitment_hash(&setup.env, HashScheme::Legacy, HASH_TAG_LEAF, &hidden_rank.clone().to_xdr(&setup.env));
+                setup_commits.set(i, SetupCommit { hidden_rank_hash, pawn_id: hidden_rank.pawn_id });
+                hidden_ranks.set(i, hidden_rank);
+            }
+        }
+        let (root, merkle_proofs) = get_merkel(&setup.env, &setup_commits, &hidden_ranks);
+        setup.client.commit_setup(address, &CommitSetupReq { lobby_id, rank_commitment_root: root, zz_hidden_ranks: Vec::new(&setup.env) });
+        reveal_reqs.push(RevealSetupReq { hidden_ranks, lobby_id, merkle_proofs });
+    }
+    extend_lobby_ttl(setup, lobby_id);
+    let guest_reveal = reveal_reqs.pop().unwrap();
+    let host_reveal = reveal_reqs.pop().unwrap();
+    (lobby_id, host, guest, host_reveal, guest_reveal)
+}
+
+#[test]
+fn test_setup_reveal_confirms_result() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_reveal, guest_reveal) = setup_lobby_with_setup_reveal(&setup, 1);
+    let lobby_info = setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(lobby_info.phase, Phase::SetupReveal);
+    assert_eq!(lobby_info.subphase, Subphase::Both);
+    assert_eq!(lobby_info.pending_winner, Subphase::Host);
+    assert_eq!(lobby_info.end_reason, EndReason::Surrender);
+    assert!(setup.client.try_offer_rematch(&host, &RematchReq { lobby_id }).is_err());
+    // a partial reveal, a tampered rank and someone else's setup are all rejected
+    let mut partial = host_reveal.clone();
+    partial.hidden_ranks.pop_back();
+    partial.merkle_proofs.pop_back();
+    assert_eq!(setup.client.try_reveal_setup(&host, &partial).unwrap_err().unwrap(), Error::InvalidArgs);
+    let mut tampered = host_reveal.clone();
+    let mut hidden_rank = tampered.hidden_ranks.get_unchecked(0);
+    hidden_rank.rank = 11;
+    tampered.hidden_ranks.set(0, hidden_rank);
+    assert_eq!(setup.client.try_reveal_setup(&host, &tampered).unwrap_err().unwrap(), Error::HashFail);
+    assert!(setup.client.try_reveal_setup(&host, &guest_reveal).is_err());
+    let lobby_info = setup.client.reveal_setup(&host, &host_reveal);
+    assert_eq!(lobby_info.phase, Phase::SetupReveal);
+    assert_eq!(lobby_info.subphase, Subphase::Guest);
+    assert!(setup.client.try_reveal_setup(&host, &host_reveal).is_err());
+    let lobby_info = setup.client.reveal_setup(&guest, &guest_reveal);
+    assert_eq!(lobby_info.phase, Phase::Finished);
+    assert_eq!(lobby_info.subphase, Subphase::Host);
+    assert_eq!(lobby_info.end_reason, EndReason::Surrender);
+    assert_eq!(lobby_info.end_player, Subphase::Guest);
+    assert_eq!(setup.client.get_user(&host).wins, 1);
+    assert_eq!(setup.client.get_user(&guest).losses, 1);
+    let history: History = setup.env.as_contract(&setup.contract_id, || {
+        setup.env.storage().temporary().get(&DataKey::History(lobby_id)).unwrap()
+    });
+    assert_eq!(history.setup_reveals.get_unchecked(0), host_reveal.hidden_ranks);
+    assert_eq!(history.setup_reveals.get_unchecked(1), guest_reveal.hidden_ranks);
+    assert_eq!(history.final_lobby_info.len(), 1);
+}
+
+#[test]
+fn test_setup_reveal_overturns_illegal_army() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_reveal, guest_reveal) = setup_lobby_with_setup_reveal(&setup, 2);
+    let lobby_info = setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(lobby_info.pending_winner, Subphase::Host);
+    // the proofs are valid but the army has two thrones
+    assert_eq!(setup.client.try_reveal_setup(&host, &host_reveal).unwrap_err().unwrap(), Error::InvalidArgs);
+    let lobby_info = setup.client.reveal_setup(&guest, &guest_reveal);
+    assert_eq!(lobby_info.subphase, Subphase::Host);
+    setup.env.ledger().with_mut(|l| l.sequence_number = lobby_info.last_edited_ledger_seq + 99);
+    assert!(setup.client.try_redeem_win(&guest, &RedeemWinReq { lobby_id }).is_err());
+    assert!(setup.client.try_redeem_win(&host, &RedeemWinReq { lobby_id }).is_err());
+    setup.env.ledger().with_mut(|l| l.sequence_number = lobby_info.last_edited_ledger_seq + 100);
+    let lobby_info = setup.client.redeem_win(&guest, &RedeemWinReq { lobby_id });
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.subphase, Subphase::Guest);
+    assert_eq!(lobby_info.end_reason, EndReason::SetupNotRevealed);
+    assert_eq!(lobby_info.end_player, Subphase::Host);
+    assert_eq!(setup.client.get_user(&host).aborts, 1);
+    assert_eq!(setup.client.get_user(&host).wins, 0);
+    assert_eq!(setup.client.get_user(&guest).losses, 0);
+}
+
+#[test]
+fn test_setup_reveal_timeouts() {
+    // a loser who doesn't reveal keeps their loss
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_reveal, guest_reveal) = setup_lobby_with_setup_reveal(&setup, 1);
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    let lobby_info = setup.client.reveal_setup(&host, &host_reveal);
+    setup.env.ledger().with_mut(|l| l.sequence_number = lobby_info.last_edited_ledger_seq + 100);
+    let lobby_info = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
+    assert_eq!(lobby_info.phase, Phase::Finished);
+    assert_eq!(lobby_info.subphase, Subphase::Host);
+    assert_eq!(lobby_info.end_reason, EndReason::Surrender);
+    // nobody revealing voids the result
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_reveal, guest_reveal) = setup_lobby_with_setup_reveal(&setup, 1);
+    let lobby_info = setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(setup.client.try_leave_lobby(&guest).unwrap_err().unwrap(), Error::WrongPhase);
+    setup.env.ledger().with_mut(|l| l.sequence_number = lobby_info.last_edited_ledger_seq + 100);
+    assert!(setup.client.try_redeem_win(&host, &RedeemWinReq { lobby_id }).is_err());
+    let lobby_info = setup.client.reap_stale_lobby(&ReapStaleLobbyReq { lobby_id });
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.subphase, Subphase::None);
+    assert_eq!(lobby_info.end_reason, EndReason::SetupNotRevealed);
+    assert_eq!(lobby_info.end_player, Subphase::None);
+    assert_eq!(setup.client.get_user(&host).current_lobby, 0);
+}
+
+// endregion
+// region dispute tests
+
+fn setup_lobby_with_dispute_window(setup: &TestSetup, dispute_window: u32) -> (u32, Address, Address, Vec<HiddenRank>, Vec<MerkleProof>) {
+    let mut lobby_parameters = create_full_stratego_board_parameters(&setup.env);
+    lobby_parameters.dispute_window = dispute_window;
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: lobby_parameters });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let (host_ranks, _, host_proofs, _) = advance_through_complete_setup_phase(setup, lobby_id, &host, &guest);
+    extend_lobby_ttl(setup, lobby_id);
+    (lobby_id, host, guest, host_ranks, host_proofs)
+}
+
+#[test]
+fn test_settle_game_after_dispute_window() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_ranks, host_proofs) = setup_lobby_with_dispute_window(&setup, 50);
+    let lobby_info = setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(lobby_info.phase, Phase::Finished);
+    assert_eq!(lobby_info.dispute_deadline, lobby_info.last_edited_ledger_seq + 50);
+    // nothing is paid out or rated until the window closes
+    assert_eq!(setup.client.get_user(&host).wins, 0);
+    assert_eq!(setup.client.try_settle_game(&SettleGameReq { lobby_id }).unwrap_err().unwrap(), Error::InvalidArgs);
+    assert_eq!(setup.client.try_leave_lobby(&host).unwrap_err().unwrap(), Error::WrongPhase);
+    // a genuine opening that agrees with everything proven so far isn't fraud
+    let honest = DisputeReq {
+        hidden_ranks: Vec::from_array(&setup.env, [host_ranks.get_unchecked(0)]),
+        lobby_id,
+        merkle_proofs: Vec::from_array(&setup.env, [host_proofs.get_unchecked(0)]),
+    };
+    assert_eq!(setup.client.try_dispute(&guest, &honest).unwrap_err().unwrap(), Error::InvalidArgs);
+    setup.env.ledger().with_mut(|l| l.sequence_number = lobby_info.dispute_deadline);
+    assert!(setup.client.try_dispute(&guest, &honest).is_err());
+    let lobby_info = setup.client.settle_game(&SettleGameReq { lobby_id });
+    assert_eq!(lobby_info.dispute_deadline, 0);
+    assert_eq!(setup.client.get_user(&host).wins, 1);
+    assert_eq!(setup.client.get_user(&guest).losses, 1);
+    assert_eq!(setup.client.try_settle_game(&SettleGameReq { lobby_id }).unwrap_err().unwrap(), Error::WrongPhase);
+    setup.client.leave_lobby(&host);
+}
+
+#[test]
+fn test_dispute_overturns_contradicted_rank() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_ranks, host_proofs) = setup_lobby_with_dispute_window(&setup, 50);
+    // the host's first pawn was accepted as a different rank than the one it committed to
+    let committed_rank = host_ranks.get_unchecked(0);
+    setup.env.as_contract(&setup.contract_id, || {
+        let mut game_state: GameState = setup.env.storage().temporary().get(&DataKey::GameState(lobby_id)).unwrap();
+        let pawns_map = Contract::create_pawns_map(&setup.env, &game_state.pawns);
+        let (pawn_index, mut pawn) = pawns_map.get_unchecked(committed_rank.pawn_id);
+        pawn.rank = Vec::from_array(&setup.env, [if committed_rank.rank == 10 { 9 } else { 10 }]);
+        game_state.pawns.set(pawn_index, Contract::pack_pawn(pawn));
+        setup.env.storage().temporary().set(&DataKey::GameState(lobby_id), &game_state);
+    });
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    let fraud_proof = DisputeReq {
+        hidden_ranks: Vec::from_array(&setup.env, [committed_rank.clone()]),
+        lobby_id,
+        merkle_proofs: Vec::from_array(&setup.env, [host_proofs.get_unchecked(0)]),
+    };
+    // only the opponent's commitment can be disputed
+    assert!(setup.client.try_dispute(&host, &fraud_proof).is_err());
+    let mut forged = fraud_proof.clone();
+    forged.merkle_proofs = Vec::from_array(&setup.env, [host_proofs.get_unchecked(1)]);
+    assert!(setup.client.try_dispute(&guest, &forged).is_err());
+    let lobby_info = setup.client.dispute(&guest, &fraud_proof);
+    assert_eq!(lobby_info.phase, Phase::Finished);
+    assert_eq!(lobby_info.subphase, Subphase::Guest);
+    assert_eq!(lobby_info.end_reason, EndReason::FraudProven);
+    assert_eq!(lobby_info.end_player, Subphase::Host);
+    assert_eq!(lobby_info.dispute_deadline, 0);
+    // the overturned result is settled straight away as a win for the honest player
+    let host_user = setup.client.get_user(&host);
+    assert_eq!(host_user.frauds, 1);
+    assert_eq!(host_user.aborts, 0);
+    assert_eq!(host_user.wins, 0);
+    assert_eq!(host_user.losses, 1);
+    let guest_user = setup.client.get_user(&guest);
+    assert_eq!(guest_user.wins, 1);
+    assert_eq!(guest_user.losses, 0);
+    assert!(setup.client.try_settle_game(&SettleGameReq { lobby_id }).is_err());
+    assert!(setup.client.try_dispute(&guest, &fraud_proof).is_err());
+}
+
+#[test]
+fn test_settle_game_after_leaving_mid_game() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, _, _) = setup_lobby_with_dispute_window(&setup, 50);
+    // leaving forfeits but the result waits for the window like any other
+    setup.client.leave_lobby(&guest);
+    assert_eq!(setup.client.get_user(&guest).current_lobby, 0);
+    let lobby_info = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info;
+    assert_eq!(lobby_info.phase, Phase::Finished);
+    assert_eq!(lobby_info.subphase, Subphase::Host);
+    assert_eq!(lobby_info.guest_address, Vec::from_array(&setup.env, [guest.clone()]));
+    setup.env.ledger().with_mut(|l| l.sequence_number = lobby_info.dispute_deadline);
+    let lobby_info = setup.client.settle_game(&SettleGameReq { lobby_id });
+    assert_eq!(setup.client.get_user(&host).wins, 1);
+    assert_eq!(setup.client.get_user(&guest).losses, 1);
+    // the leaver is detached once the result is paid out, the winner stays for a rematch or to leave
+    assert!(lobby_info.guest_address.is_empty());
+    assert_eq!(lobby_info.host_address, Vec::from_array(&setup.env, [host.clone()]));
+    setup.client.leave_lobby(&host);
+}
+
 // endregion
 // endregion
\ No newline at end of file
@@ -4,39 +4,37 @@ extern crate std;
 use super::super::*;
 use super::super::test_utils::*;
 use super::test_utils::*;
+use soroban_sdk::testutils::Ledger as _;
 
 // region lobby tests
 #[test]
 fn test_make_lobby_success() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id,
-        parameters: params,
-    });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.verify_lobby_info(lobby_id, &host, Phase::Lobby);
     setup.verify_user_lobby(&host, lobby_id);
-    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
-    assert_eq!(snapshot.subphase, Subphase::Guest);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.subphase, Subphase::Guest);
+    assert_eq!(snapshot.lobby_info.end_reason, EndReason::None);
 }
 #[test]
-fn test_lobby_id_collision() {
+fn test_lobby_ids_assigned_by_contract() {
     let setup = TestSetup::new();
     let params = create_test_lobby_parameters(&setup.env);
     let host_1 = setup.generate_address();
-    setup.client.make_lobby(&host_1, &MakeLobbyReq {
-        lobby_id: 1,
+    let lobby_1 = setup.client.make_lobby(&host_1, &MakeLobbyReq {
         parameters: params.clone(),
     });
     let host_2 = setup.generate_address();
-    let result = setup.client.try_make_lobby(&host_2, &MakeLobbyReq {
-        lobby_id: 1,
+    let lobby_2 = setup.client.make_lobby(&host_2, &MakeLobbyReq {
         parameters: params,
     });
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().unwrap(), Error::AlreadyExists);
+    assert_eq!(lobby_1, 1);
+    assert_eq!(lobby_2, 2);
+    setup.verify_lobby_info(lobby_1, &host_1, Phase::Lobby);
+    setup.verify_lobby_info(lobby_2, &host_2, Phase::Lobby);
 }
 #[test]
 fn test_join_lobby_access_control() {
@@ -45,20 +43,18 @@ fn test_join_lobby_access_control() {
     let host = setup.generate_address();
     let guest_1 = setup.generate_address();
     let guest_2 = setup.generate_address();
-    setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id: 1,
+    let lobby_1 = setup.client.make_lobby(&host, &MakeLobbyReq {
         parameters: params.clone(),
     });
-    let result = setup.client.try_join_lobby(&host, &JoinLobbyReq { lobby_id: 1 });
+    let result = setup.client.try_join_lobby(&host, &JoinLobbyReq { lobby_id: lobby_1 });
     assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
-    setup.client.join_lobby(&guest_1, &JoinLobbyReq { lobby_id: 1 });
-    let result = setup.client.try_join_lobby(&guest_2, &JoinLobbyReq { lobby_id: 1 });
+    setup.client.join_lobby(&guest_1, &JoinLobbyReq { lobby_id: lobby_1 });
+    let result = setup.client.try_join_lobby(&guest_2, &JoinLobbyReq { lobby_id: lobby_1 });
     assert_eq!(result.unwrap_err().unwrap(), Error::LobbyNotJoinable);
-    setup.client.make_lobby(&guest_2, &MakeLobbyReq {
-        lobby_id: 2,
+    let lobby_2 = setup.client.make_lobby(&guest_2, &MakeLobbyReq {
         parameters: params,
     });
-    let result = setup.client.try_join_lobby(&guest_1, &JoinLobbyReq { lobby_id: 2 });
+    let result = setup.client.try_join_lobby(&guest_1, &JoinLobbyReq { lobby_id: lobby_2 });
     assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
 }
 #[test]
@@ -66,16 +62,15 @@ fn test_host_leaves_aborts_lobby() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id,
-        parameters: params,
-    });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.leave_lobby(&host);
     let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
     assert_eq!(snapshot.phase, Phase::Aborted);
     assert_eq!(snapshot.subphase, Subphase::None);
+    let lobby_snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(lobby_snapshot.lobby_info.end_reason, EndReason::LeftLobby);
+    assert_eq!(lobby_snapshot.lobby_info.end_player, Subphase::Host);
     let result = setup.client.try_join_lobby(&guest, &JoinLobbyReq { lobby_id });
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().unwrap(), Error::LobbyNotJoinable);
@@ -86,21 +81,19 @@ fn test_sequential_lobby_creation() {
     let user = setup.generate_address();
     let guest = setup.generate_address();
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&user, &MakeLobbyReq {
-        lobby_id: 1,
+    let lobby_1 = setup.client.make_lobby(&user, &MakeLobbyReq {
         parameters: params.clone(),
     });
-    setup.verify_user_lobby(&user, 1);
-    setup.client.make_lobby(&user, &MakeLobbyReq {
-        lobby_id: 2,
+    setup.verify_user_lobby(&user, lobby_1);
+    let lobby_2 = setup.client.make_lobby(&user, &MakeLobbyReq {
         parameters: params,
     });
-    setup.verify_user_lobby(&user, 2);
-    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, 1);
+    setup.verify_user_lobby(&user, lobby_2);
+    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_1);
     assert_eq!(snapshot.phase, Phase::Lobby);
     assert_eq!(snapshot.subphase, Subphase::Guest);
-    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id: 1 });
-    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, 1);
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id: lobby_1 });
+    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_1);
     assert_eq!(snapshot.phase, Phase::SetupCommit);
     assert_eq!(snapshot.subphase, Subphase::Both);
 }
@@ -109,36 +102,29 @@ fn test_lobby_id_reuse_blocked() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id,
-        parameters: params.clone(),
-    });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params.clone() });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     setup.client.leave_lobby(&host);
     let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
     assert_eq!(snapshot.phase, Phase::Finished);
     assert_eq!(snapshot.subphase, Subphase::Guest);
+    // a finished lobby's id is never handed out again
     let new_host = setup.generate_address();
-    let result = setup.client.try_make_lobby(&new_host, &MakeLobbyReq {
-        lobby_id,
+    let new_lobby_id = setup.client.make_lobby(&new_host, &MakeLobbyReq {
         parameters: params,
     });
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err().unwrap(), Error::AlreadyExists);
+    assert_ne!(new_lobby_id, lobby_id);
+    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.phase, Phase::Finished);
 }
 #[test]
 fn test_join_starts_game() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id,
-        parameters: params,
-    });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
     assert_eq!(snapshot.lobby_info.phase, Phase::SetupCommit);
@@ -152,28 +138,26 @@ fn test_leave_during_setup() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 10u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     setup.client.leave_lobby(&guest);
-    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
-    assert_eq!(snapshot.phase, Phase::Finished);
-    assert_eq!(snapshot.subphase, Subphase::Host);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.phase, Phase::Finished);
+    assert_eq!(snapshot.lobby_info.subphase, Subphase::Host);
+    assert_eq!(snapshot.lobby_info.end_reason, EndReason::LeftLobby);
+    assert_eq!(snapshot.lobby_info.end_player, Subphase::Guest);
+    let history = snapshot.history_opt.unwrap();
+    assert_eq!(history.final_lobby_info.get_unchecked(0).end_reason, EndReason::LeftLobby);
 }
 #[test]
 fn test_abandoned_lobby_joinable() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 105u32;
     let params = create_test_lobby_parameters(&setup.env);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params.clone() });
     setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id,
-        parameters: params.clone(),
-    });
-    setup.client.make_lobby(&host, &MakeLobbyReq {
-        lobby_id: 999,
         parameters: params,
     });
     let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
@@ -184,4 +168,357 @@ fn test_abandoned_lobby_joinable() {
     assert_eq!(snapshot.phase, Phase::SetupCommit);
     assert_eq!(snapshot.subphase, Subphase::Both);
 }
-// endregion
\ No newline at end of file
+// endregion
+// region surrender tests
+#[test]
+fn test_surrender_during_setup() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let params = create_test_lobby_parameters(&setup.env);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let result = setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::Host);
+    assert_eq!(result.end_reason, EndReason::Surrender);
+    assert_eq!(result.end_player, Subphase::Guest);
+    // both players remain attached to the finished lobby
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert!(snapshot.lobby_info.host_address.contains(&host));
+    assert!(snapshot.lobby_info.guest_address.contains(&guest));
+    setup.verify_user_lobby(&host, lobby_id);
+    setup.verify_user_lobby(&guest, lobby_id);
+    let history = snapshot.history_opt.unwrap();
+    assert_eq!(history.final_lobby_info.len(), 1);
+    assert_eq!(history.final_lobby_info.get_unchecked(0), result);
+    assert_eq!(history.final_game_state.len(), 1);
+    assert_eq!(snapshot.history_turns_opt.unwrap().turns.len(), 0);
+}
+#[test]
+fn test_surrender_mid_turn_keeps_half_proved_turn_out_of_history() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, host_ranks, _, _, _) = setup_lobby_for_commit_move(&setup);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Host, &host_ranks, 777).unwrap();
+    let host_hash = create_test_move_hash(&setup.env, host_move.pawn_id, host_move.start_pos, host_move.target_pos, host_move.salt);
+    let guest_hash = Bytes::from_array(&setup.env, &[3u8; 16]);
+    setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) });
+    setup.client.commit_move(&guest, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [guest_hash]) });
+    setup.client.prove_move(&host, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [host_move.clone()]) });
+    let result = setup.client.surrender(&host, &SurrenderReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::Guest);
+    // the guest never proved, so the turn isn't recorded and the host's proof is only kept in the final state
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.game_state.turn, 1);
+    assert_eq!(snapshot.history_turns_opt.unwrap().turns.len(), 0);
+    assert_eq!(snapshot.game_state.moves.get_unchecked(UserIndex::Host.u32()).move_proofs, Vec::from_array(&setup.env, [host_move]));
+    let history = snapshot.history_opt.unwrap();
+    assert_eq!(history.final_game_state.get_unchecked(0), snapshot.game_state);
+}
+#[test]
+fn test_surrender_wrong_phase() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let params = create_test_lobby_parameters(&setup.env);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    let result = setup.client.try_surrender(&host, &SurrenderReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    setup.client.surrender(&host, &SurrenderReq { lobby_id });
+    let result = setup.client.try_surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    let result = setup.client.try_surrender(&guest, &SurrenderReq { lobby_id: 999 });
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+// endregion
+// region draw offer tests
+#[test]
+fn test_accept_draw() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
+    let outsider = setup.generate_address();
+    let result = setup.client.offer_draw(&host, &DrawReq { lobby_id });
+    assert_eq!(result.draw_offer, Subphase::Host);
+    assert_eq!(result.phase, Phase::MoveCommit);
+    let result = setup.client.try_offer_draw(&host, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::AlreadyExists);
+    let result = setup.client.try_offer_draw(&guest, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::AlreadyExists);
+    // only the opponent of the offering player can accept
+    let result = setup.client.try_accept_draw(&host, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+    let result = setup.client.try_accept_draw(&outsider, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+    let result = setup.client.accept_draw(&guest, &DrawReq { lobby_id });
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::DrawAgreed);
+    assert_eq!(result.end_player, Subphase::None);
+    assert_eq!(result.draw_offer, Subphase::None);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let history = snapshot.history_opt.unwrap();
+    assert_eq!(history.final_lobby_info.get_unchecked(0), result);
+    assert_eq!(history.final_game_state.get_unchecked(0), snapshot.game_state);
+    assert_eq!(setup.client.get_user(&host).draws, 1);
+    assert_eq!(setup.client.get_user(&guest).draws, 1);
+    let result = setup.client.try_offer_draw(&host, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    let result = setup.client.try_accept_draw(&guest, &DrawReq { lobby_id: 999 });
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+#[test]
+fn test_decline_and_expire_draw_offer() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
+    setup.client.offer_draw(&host, &DrawReq { lobby_id });
+    let result = setup.client.try_decline_draw(&host, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+    let result = setup.client.decline_draw(&guest, &DrawReq { lobby_id });
+    assert_eq!(result.draw_offer, Subphase::None);
+    assert_eq!(result.phase, Phase::MoveCommit);
+    let result = setup.client.try_decline_draw(&guest, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+    // the opponent moving does not withdraw the offer
+    setup.client.offer_draw(&host, &DrawReq { lobby_id });
+    setup.client.commit_move(&guest, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [Bytes::from_array(&setup.env, &[3u8; 16])]) });
+    assert_eq!(extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info.draw_offer, Subphase::Host);
+    // the offering player's next commit does
+    let result = setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [Bytes::from_array(&setup.env, &[4u8; 16])]) });
+    assert_eq!(result.draw_offer, Subphase::None);
+    let result = setup.client.try_accept_draw(&guest, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+}
+#[test]
+fn test_draw_offer_cleared_when_game_moves_on() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: create_test_lobby_parameters(&setup.env) });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    // an offer made during setup lapses once both setups are in
+    setup.client.offer_draw(&host, &DrawReq { lobby_id });
+    for address in [&host, &guest] {
+        setup.client.commit_setup(address, &CommitSetupReq {
+            lobby_id,
+            rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+            zz_hidden_ranks: Vec::new(&setup.env),
+        });
+    }
+    let lobby_info = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info;
+    assert_eq!(lobby_info.phase, Phase::MoveCommit);
+    assert_eq!(lobby_info.draw_offer, Subphase::None);
+    // an open offer ends with the game, and a finished game can't be declined into
+    setup.client.offer_draw(&host, &DrawReq { lobby_id });
+    let result = setup.client.surrender(&host, &SurrenderReq { lobby_id });
+    assert_eq!(result.draw_offer, Subphase::None);
+    let last_edited_ledger_seq = result.last_edited_ledger_seq;
+    setup.env.ledger().with_mut(|l| l.sequence_number += 10);
+    let result = setup.client.try_decline_draw(&guest, &DrawReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    assert_eq!(extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info.last_edited_ledger_seq, last_edited_ledger_seq);
+}
+// endregion
+// region invite tests
+#[test]
+fn test_invite_only_join() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let invited = setup.generate_address();
+    let stranger = setup.generate_address();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.invited_guests = Vec::from_array(&setup.env, [invited.clone()]);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    let result = setup.client.try_join_lobby(&stranger, &JoinLobbyReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+    setup.client.join_lobby(&invited, &JoinLobbyReq { lobby_id });
+    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.phase, Phase::SetupCommit);
+}
+#[test]
+fn test_invite_list_validation() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.invited_guests = Vec::from_array(&setup.env, [host.clone()]);
+    let result = setup.client.try_make_lobby(&host, &MakeLobbyReq { parameters: params.clone() });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    params.invited_guests = Vec::new(&setup.env);
+    for _ in 0..=MAX_INVITED_GUESTS {
+        params.invited_guests.push_back(setup.generate_address());
+    }
+    let result = setup.client.try_make_lobby(&host, &MakeLobbyReq { parameters: params });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+}
+#[test]
+fn test_decline_invite_closes_lobby() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let invited = setup.generate_address();
+    let other_invited = setup.generate_address();
+    let mut params = create_full_stratego_board_parameters(&setup.env);
+    params.invited_guests = Vec::from_array(&setup.env, [invited.clone(), other_invited.clone()]);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    let stranger = setup.generate_address();
+    let result = setup.client.try_decline_invite(&stranger, &DeclineInviteReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+    setup.client.decline_invite(&invited, &DeclineInviteReq { lobby_id });
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.phase, Phase::Aborted);
+    assert_eq!(snapshot.lobby_info.end_reason, EndReason::InviteDeclined);
+    assert!(snapshot.lobby_info.host_address.is_empty());
+    // the host is free again and wasn't charged for the abort
+    let host_user = setup.client.get_user(&host);
+    assert_eq!(host_user.current_lobby, 0);
+    assert_eq!(host_user.aborts, 0);
+    let result = setup.client.try_join_lobby(&other_invited, &JoinLobbyReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::LobbyNotJoinable);
+    let result = setup.client.try_decline_invite(&other_invited, &DeclineInviteReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    let result = setup.client.try_decline_invite(&invited, &DeclineInviteReq { lobby_id: 999 });
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+// endregion
+// region open lobby index tests
+#[test]
+fn test_list_open_lobbies() {
+    let setup = TestSetup::new();
+    let params = create_test_lobby_parameters(&setup.env);
+    let host_a = setup.generate_address();
+    let lobby_a = setup.client.make_lobby(&host_a, &MakeLobbyReq { parameters: params.clone() });
+    let lobby_b = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params.clone() });
+    let host_c = setup.generate_address();
+    let lobby_c = setup.client.make_lobby(&host_c, &MakeLobbyReq { parameters: params.clone() });
+    let mut invite_params = params.clone();
+    invite_params.invited_guests = Vec::from_array(&setup.env, [setup.generate_address()]);
+    setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: invite_params });
+    // joined and abandoned lobbies drop out, invite-only lobbies are never listed
+    setup.client.join_lobby(&setup.generate_address(), &JoinLobbyReq { lobby_id: lobby_b });
+    setup.client.leave_lobby(&host_c);
+    let open_lobbies = setup.client.list_open_lobbies(&0, &10);
+    assert_eq!(open_lobbies.len(), 1);
+    let open_lobby = open_lobbies.get_unchecked(0);
+    assert_eq!(open_lobby.lobby_info.index, lobby_a);
+    assert_eq!(open_lobby.lobby_info.host_address.get_unchecked(0), host_a);
+    assert_eq!(open_lobby.board_name, params.board.name);
+    assert_eq!(open_lobby.board_size, params.board.size);
+    assert_eq!(open_lobby.security_mode, params.security_mode);
+    // page through with the last returned id as the cursor
+    let lobby_e = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params.clone() });
+    let lobby_f = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params });
+    let page = setup.client.list_open_lobbies(&0, &1);
+    assert_eq!(page.len(), 1);
+    assert_eq!(page.get_unchecked(0).lobby_info.index, lobby_a);
+    let page = setup.client.list_open_lobbies(&lobby_a, &1);
+    assert_eq!(page.get_unchecked(0).lobby_info.index, lobby_e);
+    let page = setup.client.list_open_lobbies(&lobby_e, &10);
+    assert_eq!(page.len(), 1);
+    assert_eq!(page.get_unchecked(0).lobby_info.index, lobby_f);
+    assert_eq!(setup.client.list_open_lobbies(&lobby_f, &10).len(), 0);
+}
+#[test]
+fn test_open_lobby_index_is_bounded() {
+    let setup = TestSetup::new();
+    let params = create_test_lobby_parameters(&setup.env);
+    let oldest_lobby_id = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params.clone() });
+    for _ in 0..MAX_OPEN_LOBBIES {
+        setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params.clone() });
+    }
+    let open_lobbies = setup.client.list_open_lobbies(&0, &(MAX_OPEN_LOBBIES * 2));
+    assert_eq!(open_lobbies.len(), MAX_OPEN_LOBBIES);
+    assert_eq!(open_lobbies.get_unchecked(0).lobby_info.index, oldest_lobby_id + 1);
+    // evicted lobbies can still be joined by id
+    setup.client.join_lobby(&setup.generate_address(), &JoinLobbyReq { lobby_id: oldest_lobby_id });
+}
+#[test]
+fn test_allocated_ids_skip_pre_upgrade_lobbies() {
+    let setup = TestSetup::new();
+    let params = create_test_lobby_parameters(&setup.env);
+    let lobby_id = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params.clone() });
+    // lobbies that clients created under their own ids before the counter existed
+    let old_lobby_info = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info;
+    setup.env.as_contract(&setup.contract_id, || {
+        setup.env.storage().temporary().set(&DataKey::LobbyInfo(lobby_id + 1), &old_lobby_info);
+        setup.env.storage().persistent().set(&DataKey::HistoryTurns(lobby_id + 2), &HistoryTurns { turns: Vec::new(&setup.env) });
+    });
+    let next_lobby_id = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: params });
+    assert_eq!(next_lobby_id, lobby_id + 3);
+    let kept_lobby_info: LobbyInfo = setup.env.as_contract(&setup.contract_id, || {
+        setup.env.storage().temporary().get(&DataKey::LobbyInfo(lobby_id + 1)).unwrap()
+    });
+    assert_eq!(kept_lobby_info, old_lobby_info);
+}
+// endregion
+// region rematch tests
+#[test]
+fn test_rematch_swaps_sides() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    let offered = setup.client.offer_rematch(&guest, &RematchReq { lobby_id });
+    assert_eq!(offered.rematch_offer, Subphase::Guest);
+    // the offering player can't accept their own offer
+    let result = setup.client.try_accept_rematch(&guest, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+    let rematch_id = setup.client.accept_rematch(&host, &RematchReq { lobby_id });
+    assert_ne!(rematch_id, lobby_id);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, rematch_id);
+    assert_eq!(snapshot.lobby_info.phase, Phase::SetupCommit);
+    assert_eq!(snapshot.lobby_info.subphase, Subphase::Both);
+    assert_eq!(snapshot.lobby_info.host_address.get_unchecked(0), guest);
+    assert_eq!(snapshot.lobby_info.guest_address.get_unchecked(0), host);
+    assert_eq!(snapshot.lobby_info.previous_lobby, lobby_id);
+    assert_eq!(snapshot.game_state.turn, 1);
+    let old_snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_parameters, old_snapshot.lobby_parameters);
+    assert_eq!(snapshot.game_state.pawns.len(), old_snapshot.game_state.pawns.len());
+    let history = snapshot.history_opt.unwrap();
+    assert!(history.final_lobby_info.is_empty());
+    assert_eq!(snapshot.history_turns_opt.unwrap().turns.len(), 0);
+    assert_eq!(old_snapshot.lobby_info.rematch_offer, Subphase::None);
+    setup.verify_user_lobby(&host, rematch_id);
+    setup.verify_user_lobby(&guest, rematch_id);
+    // the old lobby can't be rematched twice
+    let result = setup.client.try_offer_rematch(&host, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+}
+#[test]
+fn test_rematch_rejections() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    let result = setup.client.try_offer_rematch(&host, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    setup.client.surrender(&host, &SurrenderReq { lobby_id });
+    let stranger = setup.generate_address();
+    let result = setup.client.try_offer_rematch(&stranger, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+    let result = setup.client.try_accept_rematch(&guest, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+    setup.client.offer_rematch(&host, &RematchReq { lobby_id });
+    let result = setup.client.try_offer_rematch(&guest, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::AlreadyExists);
+    // leaving withdraws the offer
+    setup.client.leave_lobby(&guest);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.rematch_offer, Subphase::None);
+    let result = setup.client.try_offer_rematch(&host, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::LobbyNotJoinable);
+    let result = setup.client.try_offer_rematch(&host, &RematchReq { lobby_id: 999 });
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+#[test]
+fn test_rematch_rejected_while_in_another_game() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    setup.client.offer_rematch(&guest, &RematchReq { lobby_id });
+    // the offering player moves on to a new lobby before the offer is accepted
+    let other_lobby_id = setup.client.make_lobby(&guest, &MakeLobbyReq { parameters: create_test_lobby_parameters(&setup.env) });
+    let result = setup.client.try_accept_rematch(&host, &RematchReq { lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+    setup.verify_user_lobby(&guest, other_lobby_id);
+    setup.verify_user_lobby(&host, lobby_id);
+}
+// endregion
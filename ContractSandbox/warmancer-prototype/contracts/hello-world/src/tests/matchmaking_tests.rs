@@ -0,0 +1,126 @@
+#![cfg(test)]
+#![allow(unused_variables)]
+extern crate std;
+use super::super::*;
+use super::super::test_utils::*;
+use super::test_utils::*;
+use soroban_sdk::testutils::Ledger as _;
+
+fn set_rating(setup: &TestSetup, address: &Address, rating: u32) {
+    setup.env.as_contract(&setup.contract_id, || {
+        let mut user = Contract::default_user();
+        user.rating = rating;
+        setup.env.storage().persistent().set(&DataKey::User(address.clone()), &user);
+    });
+}
+
+// region matchmaking tests
+#[test]
+fn test_enqueue_pairs_compatible_players() {
+    let setup = TestSetup::new();
+    let first = setup.generate_address();
+    let second = setup.generate_address();
+    let req = EnqueueReq { parameters: create_full_stratego_board_parameters(&setup.env), rating_window: 100 };
+    assert_eq!(setup.client.enqueue(&first, &req), 0);
+    let lobby_id = setup.client.enqueue(&second, &req);
+    assert_ne!(lobby_id, 0);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.phase, Phase::SetupCommit);
+    assert_eq!(snapshot.lobby_info.subphase, Subphase::Both);
+    assert_eq!(snapshot.lobby_info.host_address.get(0).unwrap(), first);
+    assert_eq!(snapshot.lobby_info.guest_address.get(0).unwrap(), second);
+    assert_eq!(snapshot.lobby_parameters, req.parameters);
+    assert_eq!(setup.client.get_user(&first).current_lobby, lobby_id);
+    assert_eq!(setup.client.get_user(&second).current_lobby, lobby_id);
+    // both entries were consumed by the match
+    assert_eq!(setup.client.try_dequeue(&first).unwrap_err().unwrap(), Error::NotFound);
+    assert_eq!(setup.client.try_dequeue(&second).unwrap_err().unwrap(), Error::NotFound);
+    // the matched lobby plays like any other
+    setup.client.leave_lobby(&second);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.phase, Phase::Finished);
+    assert_eq!(snapshot.lobby_info.subphase, Subphase::Host);
+}
+#[test]
+fn test_enqueue_requires_matching_parameters_and_ratings() {
+    let setup = TestSetup::new();
+    let first = setup.generate_address();
+    let second = setup.generate_address();
+    let third = setup.generate_address();
+    let full_req = EnqueueReq { parameters: create_full_stratego_board_parameters(&setup.env), rating_window: 100 };
+    let dev_req = EnqueueReq { parameters: create_test_lobby_parameters(&setup.env), rating_window: 100 };
+    assert_eq!(setup.client.enqueue(&first, &full_req), 0);
+    assert_eq!(setup.client.enqueue(&second, &dev_req), 0);
+    // third is rated too far above first for either window
+    set_rating(&setup, &third, DEFAULT_RATING + 150);
+    assert_eq!(setup.client.enqueue(&third, &full_req), 0);
+    setup.client.dequeue(&third);
+    // a wide window on one side is not enough, both players must accept the difference
+    let wide_req = EnqueueReq { parameters: full_req.parameters.clone(), rating_window: 1000 };
+    assert_eq!(setup.client.enqueue(&third, &wide_req), 0);
+    setup.client.dequeue(&third);
+    set_rating(&setup, &third, DEFAULT_RATING + 50);
+    let lobby_id = setup.client.enqueue(&third, &full_req);
+    assert_ne!(lobby_id, 0);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.host_address.get(0).unwrap(), first);
+    // second is still waiting
+    setup.client.dequeue(&second);
+}
+#[test]
+fn test_dequeue() {
+    let setup = TestSetup::new();
+    let first = setup.generate_address();
+    let second = setup.generate_address();
+    let req = EnqueueReq { parameters: create_full_stratego_board_parameters(&setup.env), rating_window: 100 };
+    assert_eq!(setup.client.enqueue(&first, &req), 0);
+    assert_eq!(setup.client.try_enqueue(&first, &req).unwrap_err().unwrap(), Error::AlreadyExists);
+    setup.client.dequeue(&first);
+    assert_eq!(setup.client.try_dequeue(&first).unwrap_err().unwrap(), Error::NotFound);
+    assert_eq!(setup.client.enqueue(&second, &req), 0);
+}
+#[test]
+fn test_queue_entry_expires() {
+    let setup = TestSetup::new();
+    let first = setup.generate_address();
+    let second = setup.generate_address();
+    let req = EnqueueReq { parameters: create_full_stratego_board_parameters(&setup.env), rating_window: 100 };
+    assert_eq!(setup.client.enqueue(&first, &req), 0);
+    setup.env.ledger().with_mut(|l| l.sequence_number += QUEUE_ENTRY_TTL + 1);
+    assert_eq!(setup.client.enqueue(&second, &req), 0);
+    assert_eq!(setup.client.try_dequeue(&first).unwrap_err().unwrap(), Error::NotFound);
+    // the expired player can queue again and gets matched
+    let lobby_id = setup.client.enqueue(&first, &req);
+    assert_ne!(lobby_id, 0);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.lobby_info.host_address.get(0).unwrap(), second);
+}
+#[test]
+fn test_enqueue_rejections() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    let req = EnqueueReq { parameters: create_full_stratego_board_parameters(&setup.env), rating_window: 100 };
+    assert_eq!(setup.client.try_enqueue(&host, &req).unwrap_err().unwrap(), Error::Unauthorized);
+    let player = setup.generate_address();
+    let mut staked_req = req.clone();
+    staked_req.parameters.stake_amount = 10;
+    staked_req.parameters.stake_token = Vec::from_array(&setup.env, [setup.generate_address()]);
+    assert_eq!(setup.client.try_enqueue(&player, &staked_req).unwrap_err().unwrap(), Error::InvalidArgs);
+    let invalid_req = EnqueueReq { parameters: create_invalid_board_parameters(&setup.env), rating_window: 100 };
+    assert_eq!(setup.client.try_enqueue(&player, &invalid_req).unwrap_err().unwrap(), Error::InvalidArgs);
+}
+#[test]
+fn test_matched_lobby_shares_id_counter() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    let first = setup.generate_address();
+    let second = setup.generate_address();
+    let req = EnqueueReq { parameters: create_full_stratego_board_parameters(&setup.env), rating_window: 100 };
+    setup.client.enqueue(&first, &req);
+    assert_eq!(setup.client.enqueue(&second, &req), lobby_id + 1);
+    let next_lobby_id = setup.client.make_lobby(&setup.generate_address(), &MakeLobbyReq { parameters: req.parameters.clone() });
+    assert_eq!(next_lobby_id, lobby_id + 2);
+    // matched lobbies never sit in the open lobby index
+    assert_eq!(setup.client.list_open_lobbies(&0, &10).len(), 1);
+}
+// endregion
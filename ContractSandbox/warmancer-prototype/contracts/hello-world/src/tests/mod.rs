@@ -7,4 +7,9 @@ pub mod setup_tests;
 pub mod integration_tests;
 pub mod validation_tests;
 pub mod unit_tests;
-pub mod bot_tests;
\ No newline at end of file
+pub mod bot_tests;
+pub mod stake_tests;
+pub mod user_tests;
+pub mod matchmaking_tests;
+pub mod series_tests;
+pub mod tournament_tests;
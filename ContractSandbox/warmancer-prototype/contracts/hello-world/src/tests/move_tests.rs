@@ -10,9 +10,7 @@ use super::test_utils::*;
 #[test]
 fn test_commit_move_success_both_players() {
     let setup = TestSetup::new();
-    let lobby_id = 1u32;
-
-    let (host_address, guest_address) = create_and_advance_to_move_commit(&setup, lobby_id);
+    let (lobby_id, host_address, guest_address) = create_and_advance_to_move_commit(&setup);
 
     // Create test move hashes for both players
     let host_pawn_id = Contract::encode_pawn_id( Pos { x: 0, y: 0 }, UserIndex::Host as u32);
@@ -62,7 +60,7 @@ fn test_commit_move_validation_errors() {
     };
 
     // Create a lobby and advance to move commit phase for further tests
-    let (lobby_id, host_address, _guest_address, _host_ranks, _guest_ranks, _host_merkle_proofs, _guest_merkle_proofs) = setup_lobby_for_commit_move(&setup, 100);
+    let (lobby_id, host_address, _guest_address, _host_ranks, _guest_ranks, _host_merkle_proofs, _guest_merkle_proofs) = setup_lobby_for_commit_move(&setup);
 
     // Test: Not in lobby
     let outsider_address = setup.generate_address();
@@ -74,7 +72,7 @@ fn test_commit_move_validation_errors() {
     //assert!(result.is_err(), "Should fail: not in lobby");
 
     // Test: Wrong phase (create a new lobby in wrong phase)
-    let (wrong_phase_lobby_id, wrong_phase_host, _) = setup_lobby_for_commit_setup(&setup, 200);
+    let (wrong_phase_lobby_id, wrong_phase_host, _) = setup_lobby_for_commit_setup(&setup);
 
     let commit_req = CommitMoveReq {
         lobby_id: wrong_phase_lobby_id,
@@ -104,7 +102,7 @@ fn test_commit_move_validation_errors() {
     assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
 
     // Test: After game finished
-    let (finished_lobby_id, finished_host, finished_guest, _, _, _, _) = setup_lobby_for_commit_move(&setup, 300);
+    let (finished_lobby_id, finished_host, finished_guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
     setup.client.leave_lobby(&finished_host); // This should finish the game
 
     let commit_req = CommitMoveReq {
@@ -116,17 +114,50 @@ fn test_commit_move_validation_errors() {
     assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
 }
 
+#[test]
+fn test_prove_move_end_reasons() {
+    let setup = TestSetup::new();
+    // a proof that does not match the committed hash aborts with MoveHashMismatch
+    let (lobby_id, host, guest, host_ranks, _, _, _) = setup_lobby_for_commit_move(&setup);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Host, &host_ranks, 111).unwrap();
+    let wrong_hash = create_test_move_hash(&setup.env, host_move.pawn_id, host_move.start_pos, host_move.target_pos, 112);
+    setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [wrong_hash]) });
+    setup.client.commit_move(&guest, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [Bytes::from_array(&setup.env, &[4u8; 16])]) });
+    let result = setup.client.prove_move(&host, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [host_move]) });
+    assert_eq!(result.phase, Phase::Aborted);
+    assert_eq!(result.subphase, Subphase::Guest);
+    assert_eq!(result.end_reason, EndReason::MoveHashMismatch);
+    assert_eq!(result.end_player, Subphase::Host);
+    // a correctly hashed move onto an ally aborts with IllegalMove
+    let (lobby_id, host, guest, _, _, _, _) = setup_lobby_for_commit_move(&setup);
+    let illegal_move = HiddenMove {
+        pawn_id: Contract::encode_pawn_id(Pos { x: 0, y: 0 }, UserIndex::Host as u32),
+        salt: 113,
+        start_pos: Pos { x: 0, y: 0 },
+        target_pos: Pos { x: 0, y: 1 },
+    };
+    let illegal_hash = create_test_move_hash(&setup.env, illegal_move.pawn_id, illegal_move.start_pos, illegal_move.target_pos, illegal_move.salt);
+    setup.client.commit_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [illegal_hash]) });
+    setup.client.commit_move(&guest, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [Bytes::from_array(&setup.env, &[4u8; 16])]) });
+    let result = setup.client.prove_move(&host, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [illegal_move]) });
+    assert_eq!(result.phase, Phase::Aborted);
+    assert_eq!(result.end_reason, EndReason::IllegalMove);
+    assert_eq!(result.end_player, Subphase::Host);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.history_opt.unwrap().final_lobby_info.get_unchecked(0).end_reason, EndReason::IllegalMove);
+}
+
 // endregion
 // region security_mode=false tests
 #[test]
 fn test_no_security_mode_basic_functionality() {
     let setup = TestSetup::new();
-    let lobby_id = 1u32;
     let host = setup.generate_address();
     let guest = setup.generate_address();
     let mut params = create_test_lobby_parameters(&setup.env);
     params.security_mode = false;
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     // Use the same setup logic as security mode tests
     let (host_setup, host_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
@@ -165,12 +196,12 @@ fn test_no_security_mode_basic_functionality() {
     let host_move_hash = {
         let serialized = host_move.clone().to_xdr(&setup.env);
         let full_hash = setup.env.crypto().sha256(&serialized).to_bytes().to_array();
-        HiddenMoveHash::from_array(&setup.env, &full_hash[0..16].try_into().unwrap())
+        HiddenMoveHash::from_slice(&setup.env, &full_hash[0..16])
     };
     let guest_move_hash = {
         let serialized = guest_move.clone().to_xdr(&setup.env);
         let full_hash = setup.env.crypto().sha256(&serialized).to_bytes().to_array();
-        HiddenMoveHash::from_array(&setup.env, &full_hash[0..16].try_into().unwrap())
+        HiddenMoveHash::from_slice(&setup.env, &full_hash[0..16])
     };
     // Check if host move succeeds
     setup.client.commit_move_and_prove_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_move_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [host_move]) });
@@ -197,12 +228,11 @@ fn test_no_security_mode_basic_functionality() {
 #[test]
 fn test_no_security_mode_forbidden_functions() {
     let setup = TestSetup::new();
-    let lobby_id = 1u32;
     let host = setup.generate_address();
     let guest = setup.generate_address();
     let mut params = create_test_lobby_parameters(&setup.env);
     params.security_mode = false;
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     // Use the same setup logic as security mode tests
     let (host_setup, host_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
@@ -224,7 +254,7 @@ fn test_no_security_mode_forbidden_functions() {
         zz_hidden_ranks: guest_hidden_ranks,
     });
     // Test that security-mode-only functions are rejected
-    let fake_move_hash = BytesN::from_array(&setup.env, &[1u8; 16]);
+    let fake_move_hash = Bytes::from_array(&setup.env, &[1u8; 16]);
     let result = setup.client.try_commit_move(&host, &CommitMoveReq {
         lobby_id,
         move_hashes: Vec::from_array(&setup.env, [fake_move_hash]),
@@ -244,4 +274,243 @@ fn test_no_security_mode_forbidden_functions() {
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().unwrap(), Error::WrongSecurityMode);
 }
-// endregion
\ No newline at end of file
+// endregion
+// region movement profile tests
+fn start_insecure_game(setup: &TestSetup, params: LobbyParameters) -> (u32, Address, Address, Vec<HiddenRank>, Vec<HiddenRank>) {
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let (host_setup, host_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
+        create_setup_commits_from_game_state(&setup.env, lobby_id, &UserIndex::Host)
+    });
+    let (guest_setup, guest_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
+        create_setup_commits_from_game_state(&setup.env, lobby_id, &UserIndex::Guest)
+    });
+    let (host_root, _host_proofs) = get_merkel(&setup.env, &host_setup, &host_hidden_ranks);
+    let (guest_root, _guest_proofs) = get_merkel(&setup.env, &guest_setup, &guest_hidden_ranks);
+    setup.client.commit_setup(&host, &CommitSetupReq { lobby_id, rank_commitment_root: host_root, zz_hidden_ranks: host_hidden_ranks.clone() });
+    setup.client.commit_setup(&guest, &CommitSetupReq { lobby_id, rank_commitment_root: guest_root, zz_hidden_ranks: guest_hidden_ranks.clone() });
+    (lobby_id, host, guest, host_hidden_ranks, guest_hidden_ranks)
+}
+fn find_move(setup: &TestSetup, lobby_id: u32, ranks: &Vec<HiddenRank>, dx: i32, dy: i32) -> (HiddenMove, Rank) {
+    // the first movable pawn in ranks whose target tile is passable and empty
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let mut passable_map: Map<Pos, bool> = Map::new(&setup.env);
+    for packed_tile in snapshot.lobby_parameters.board.tiles.iter() {
+        let tile = Contract::unpack_tile(packed_tile);
+        passable_map.set(tile.pos, tile.passable);
+    }
+    let mut occupied: Map<Pos, bool> = Map::new(&setup.env);
+    for (_, (_, pawn)) in snapshot.pawns_map.iter() {
+        if pawn.alive { occupied.set(pawn.pos, true); }
+    }
+    for hidden_rank in ranks.iter() {
+        if hidden_rank.rank == 0 || hidden_rank.rank == 11 { continue; }
+        let (_, pawn) = snapshot.pawns_map.get_unchecked(hidden_rank.pawn_id);
+        let target_pos = Pos { x: pawn.pos.x + dx, y: pawn.pos.y + dy };
+        if passable_map.get(target_pos).unwrap_or(false) && !occupied.contains_key(target_pos) {
+            return (HiddenMove { pawn_id: pawn.pawn_id, salt: 42, start_pos: pawn.pos, target_pos }, hidden_rank.rank)
+        }
+    }
+    panic!("no pawn can move by ({}, {})", dx, dy)
+}
+fn submit_move(setup: &TestSetup, lobby_id: u32, address: &Address, hidden_move: HiddenMove) -> LobbyInfo {
+    let move_hash = create_test_move_hash(&setup.env, hidden_move.pawn_id, hidden_move.start_pos, hidden_move.target_pos, hidden_move.salt);
+    setup.client.commit_move_and_prove_move(address, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [move_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [hidden_move]) });
+    extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info
+}
+#[test]
+fn test_movement_profiles_in_prove_move() {
+    let setup = TestSetup::new();
+    let mut diagonal_params = create_test_lobby_parameters(&setup.env);
+    diagonal_params.security_mode = false;
+    for rank in 0..13u32 {
+        let mut profile = Contract::default_movement_profile(rank);
+        profile.diagonal = true;
+        diagonal_params.movement_profiles.push_back(profile);
+    }
+    // a diagonal step is illegal by default
+    let mut default_params = diagonal_params.clone();
+    default_params.movement_profiles = Vec::new(&setup.env);
+    let (lobby_id, host, _, host_ranks, _) = start_insecure_game(&setup, default_params);
+    let (host_move, _) = find_move(&setup, lobby_id, &host_ranks, 1, 1);
+    let lobby_info = submit_move(&setup, lobby_id, &host, host_move);
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.end_reason, EndReason::IllegalMove);
+    assert_eq!(lobby_info.end_player, Subphase::Host);
+    // and legal when the rank's profile allows it
+    let (lobby_id, host, _, host_ranks, _) = start_insecure_game(&setup, diagonal_params.clone());
+    let (host_move, _) = find_move(&setup, lobby_id, &host_ranks, 1, 1);
+    let lobby_info = submit_move(&setup, lobby_id, &host, host_move);
+    assert_eq!(lobby_info.phase, Phase::MoveCommit);
+    assert_eq!(lobby_info.end_reason, EndReason::None);
+    // a rank that may not move aborts even with a one tile step
+    let (lobby_id, host, _, host_ranks, _) = start_insecure_game(&setup, diagonal_params.clone());
+    let (_, rank) = find_move(&setup, lobby_id, &host_ranks, 0, 1);
+    let mut frozen_params = diagonal_params;
+    frozen_params.movement_profiles.set(rank, MovementProfile { can_move: false, diagonal: false, jump: false, max_range: 0 });
+    let (lobby_id, host, _, host_ranks, _) = start_insecure_game(&setup, frozen_params);
+    let (host_move, _) = find_move(&setup, lobby_id, &host_ranks, 0, 1);
+    let lobby_info = submit_move(&setup, lobby_id, &host, host_move);
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.end_reason, EndReason::IllegalMove);
+}
+// endregion
+// region turn limit tests
+fn play_insecure_turn(setup: &TestSetup, lobby_id: u32, host: &Address, guest: &Address, host_ranks: &Vec<HiddenRank>, guest_ranks: &Vec<HiddenRank>, salt: u64) -> LobbyInfo {
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Host, host_ranks, salt).unwrap();
+    let guest_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Guest, guest_ranks, salt + 1).unwrap();
+    let host_hash = create_test_move_hash(&setup.env, host_move.pawn_id, host_move.start_pos, host_move.target_pos, host_move.salt);
+    let guest_hash = create_test_move_hash(&setup.env, guest_move.pawn_id, guest_move.start_pos, guest_move.target_pos, guest_move.salt);
+    setup.client.commit_move_and_prove_move(host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [host_move]) });
+    setup.client.commit_move_and_prove_move(guest, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [guest_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [guest_move]) })
+}
+#[test]
+fn test_max_turns_ends_in_draw() {
+    let setup = TestSetup::new();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.security_mode = false;
+    params.max_turns = 2;
+    let (lobby_id, host, guest, host_ranks, guest_ranks) = start_insecure_game(&setup, params);
+    let result = play_insecure_turn(&setup, lobby_id, &host, &guest, &host_ranks, &guest_ranks, 100);
+    assert_eq!(result.phase, Phase::MoveCommit);
+    let result = play_insecure_turn(&setup, lobby_id, &host, &guest, &host_ranks, &guest_ranks, 200);
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::TurnLimit);
+    assert_eq!(result.end_player, Subphase::None);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.game_state.turn, 3);
+    assert_eq!(snapshot.history_opt.unwrap().final_lobby_info.get_unchecked(0), result);
+    assert_eq!(snapshot.history_turns_opt.unwrap().turns.len(), 3);
+}
+#[test]
+fn test_max_turns_without_capture() {
+    let setup = TestSetup::new();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.security_mode = false;
+    params.max_turns_without_capture = 1;
+    params.turn_limit_adjudication = Adjudication::Material;
+    let (lobby_id, host, guest, host_ranks, guest_ranks) = start_insecure_game(&setup, params);
+    // the opening moves can't reach each other, so nobody captures and material is even
+    let result = play_insecure_turn(&setup, lobby_id, &host, &guest, &host_ranks, &guest_ranks, 100);
+    assert_eq!(result.phase, Phase::Finished);
+    assert_eq!(result.subphase, Subphase::None);
+    assert_eq!(result.end_reason, EndReason::NoCaptureLimit);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.game_state.turns_without_capture, 1);
+}
+// endregion
+// region repetition tests
+fn shuttle_host_pawn(setup: &TestSetup, max_repeated_moves: u32) -> LobbyInfo {
+    // the host moves one pawn forward, back and forward again while the guest keeps advancing
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.security_mode = false;
+    params.max_repeated_moves = max_repeated_moves;
+    let (lobby_id, host, guest, host_ranks, guest_ranks) = start_insecure_game(setup, params);
+    let (forward, _) = find_move(setup, lobby_id, &host_ranks, 0, 1);
+    let back = HiddenMove { pawn_id: forward.pawn_id, salt: 43, start_pos: forward.target_pos, target_pos: forward.start_pos };
+    // the repeat needs a fresh salt since a committed hash can't be reused
+    let again = HiddenMove { salt: 44, ..forward.clone() };
+    let mut lobby_info = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info;
+    for host_move in [forward, back, again] {
+        lobby_info = submit_move(setup, lobby_id, &host, host_move);
+        if lobby_info.phase != Phase::MoveCommit { break; }
+        let (guest_move, _) = find_move(setup, lobby_id, &guest_ranks, 0, -1);
+        lobby_info = submit_move(setup, lobby_id, &guest, guest_move);
+        assert_eq!(lobby_info.phase, Phase::MoveCommit);
+    }
+    lobby_info
+}
+#[test]
+fn test_repeated_moves_abort() {
+    let setup = TestSetup::new();
+    let lobby_info = shuttle_host_pawn(&setup, 2);
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.subphase, Subphase::Guest);
+    assert_eq!(lobby_info.end_reason, EndReason::RepeatedMove);
+    assert_eq!(lobby_info.end_player, Subphase::Host);
+    // the same sequence is fine within the limit or with no limit
+    let lobby_info = shuttle_host_pawn(&setup, 3);
+    assert_eq!(lobby_info.phase, Phase::MoveCommit);
+    let lobby_info = shuttle_host_pawn(&setup, 0);
+    assert_eq!(lobby_info.phase, Phase::MoveCommit);
+}
+fn recorded_turns(setup: &TestSetup, lobby_id: u32) -> u32 {
+    setup.env.as_contract(&setup.contract_id, || {
+        let history_turns: Option<HistoryTurns> = setup.env.storage().persistent().get(&DataKey::HistoryTurns(lobby_id));
+        history_turns.map_or(0, |history_turns| history_turns.turns.len())
+    })
+}
+#[test]
+fn test_half_proved_turn_not_recorded() {
+    let setup = TestSetup::new();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.security_mode = false;
+    for end_with_draw in [false, true] {
+        let (lobby_id, host, guest, host_ranks, guest_ranks) = start_insecure_game(&setup, params.clone());
+        let lobby_info = play_insecure_turn(&setup, lobby_id, &host, &guest, &host_ranks, &guest_ranks, 100);
+        assert_eq!(lobby_info.phase, Phase::MoveCommit);
+        let full_turns = recorded_turns(&setup, lobby_id);
+        // only the host gets their second move in before the game ends
+        let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+        let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &snapshot.lobby_parameters, &UserIndex::Host, &host_ranks, 200).unwrap();
+        let lobby_info = submit_move(&setup, lobby_id, &host, host_move.clone());
+        assert_eq!(lobby_info.phase, Phase::MoveCommit);
+        if end_with_draw {
+            setup.client.offer_draw(&host, &DrawReq { lobby_id });
+            setup.client.accept_draw(&guest, &DrawReq { lobby_id });
+        } else {
+            setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+        }
+        // the history stops at the last full turn, the host's lone proof is only kept in the final state
+        assert_eq!(recorded_turns(&setup, lobby_id), full_turns);
+        let game_state = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id).game_state;
+        assert_eq!(game_state.turn, 2);
+        assert_eq!(game_state.moves.get_unchecked(UserIndex::Host.u32()).move_proofs, Vec::from_array(&setup.env, [host_move]));
+        assert!(game_state.moves.get_unchecked(UserIndex::Guest.u32()).move_proofs.is_empty());
+    }
+}
+#[test]
+fn test_unbound_move_hash_replay_rejected() {
+    let setup = TestSetup::new();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.security_mode = false;
+    let (lobby_id, host, guest, host_ranks, guest_ranks) = start_insecure_game(&setup, params);
+    let (host_move, _) = find_move(&setup, lobby_id, &host_ranks, 0, 1);
+    let host_hash = create_test_move_hash(&setup.env, host_move.pawn_id, host_move.start_pos, host_move.target_pos, host_move.salt);
+    submit_move(&setup, lobby_id, &host, host_move.clone());
+    let (guest_move, _) = find_move(&setup, lobby_id, &guest_ranks, 0, -1);
+    submit_move(&setup, lobby_id, &guest, guest_move);
+    // legacy hashes say nothing about their turn, so reusing one is refused when it's committed
+    let result = setup.client.try_commit_move_and_prove_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [host_move]) });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+}
+#[test]
+fn test_move_hash_replay_rejected() {
+    let setup = TestSetup::new();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.security_mode = false;
+    params.hash_scheme = HashScheme::Bound;
+    let (lobby_id, host, guest, host_ranks, guest_ranks) = start_insecure_game(&setup, params);
+    let (host_move, _) = find_move(&setup, lobby_id, &host_ranks, 0, 1);
+    let host_hash = Contract::move_commitment(&setup.env, HashScheme::Bound, lobby_id, 1, UserIndex::Host, &host_move);
+    let commit_and_prove = |address: &Address, move_hash: &Bytes, hidden_move: &HiddenMove| {
+        setup.client.try_commit_move_and_prove_move(address, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [move_hash.clone()]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [hidden_move.clone()]) })
+    };
+    commit_and_prove(&host, &host_hash, &host_move).unwrap().unwrap();
+    // the guest can't echo the host's commitment
+    let (guest_move, _) = find_move(&setup, lobby_id, &guest_ranks, 0, -1);
+    assert_eq!(commit_and_prove(&guest, &host_hash, &guest_move).unwrap_err().unwrap(), Error::InvalidArgs);
+    let guest_hash = Contract::move_commitment(&setup.env, HashScheme::Bound, lobby_id, 1, UserIndex::Guest, &guest_move);
+    let lobby_info = commit_and_prove(&guest, &guest_hash, &guest_move).unwrap().unwrap();
+    assert_eq!(lobby_info.phase, Phase::MoveCommit);
+    // a hash from an earlier turn no longer opens to the same move
+    let lobby_info = commit_and_prove(&host, &host_hash, &host_move).unwrap().unwrap();
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.end_reason, EndReason::MoveHashMismatch);
+    assert_eq!(lobby_info.end_player, Subphase::Host);
+}
+// endregion
@@ -0,0 +1,159 @@
+#![cfg(test)]
+#![allow(unused_variables)]
+extern crate std;
+use super::super::*;
+use super::super::test_utils::*;
+use super::test_utils::*;
+use soroban_sdk::testutils::Ledger as _;
+
+fn setup_series(setup: &TestSetup, target_wins: u32) -> (LobbyId, Address, Address) {
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let mut params = create_full_stratego_board_parameters(&setup.env);
+    params.series_target_wins = target_wins;
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    (lobby_id, host, guest)
+}
+
+fn draw_game(setup: &TestSetup, lobby_id: LobbyId) {
+    setup.env.as_contract(&setup.contract_id, || {
+        let mut lobby_info: LobbyInfo = setup.env.storage().temporary().get(&DataKey::LobbyInfo(lobby_id)).unwrap();
+        Contract::end_game(&mut lobby_info, Phase::Finished, Subphase::None, EndReason::Blocked, Subphase::None);
+        setup.env.storage().temporary().set(&DataKey::LobbyInfo(lobby_id), &lobby_info);
+        Contract::finalize_game(&setup.env, lobby_id, &mut lobby_info);
+    });
+}
+
+// region series tests
+#[test]
+fn test_best_of_three_series() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_series(&setup, 2);
+    let series = setup.client.get_series(&lobby_id);
+    assert_eq!(series.players, Vec::from_array(&setup.env, [host.clone(), guest.clone()]));
+    assert_eq!(series.lobbies, Vec::from_array(&setup.env, [lobby_id]));
+    assert!(!series.finished);
+    // game one goes to the host and the next game starts with sides swapped
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    let series = setup.client.get_series(&lobby_id);
+    assert_eq!(series.scores, Vec::from_array(&setup.env, [1u32, 0u32]));
+    assert_eq!(series.lobbies.len(), 2);
+    let second_lobby_id = series.lobbies.get_unchecked(1);
+    let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, second_lobby_id);
+    assert_eq!(snapshot.lobby_info.phase, Phase::SetupCommit);
+    assert_eq!(snapshot.lobby_info.host_address.get_unchecked(0), guest);
+    assert_eq!(snapshot.lobby_info.guest_address.get_unchecked(0), host);
+    assert_eq!(snapshot.lobby_info.series, lobby_id);
+    assert_eq!(snapshot.lobby_info.previous_lobby, lobby_id);
+    setup.verify_user_lobby(&host, second_lobby_id);
+    setup.verify_user_lobby(&guest, second_lobby_id);
+    // individual games aren't rated
+    assert_eq!(setup.client.get_user(&host).rating, DEFAULT_RATING);
+    let first_snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(first_snapshot.history_opt.unwrap().final_lobby_info.len(), 1);
+    // game two also goes to the original host which ends the series
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id: second_lobby_id });
+    let series = setup.client.get_series(&lobby_id);
+    assert!(series.finished);
+    assert_eq!(series.scores, Vec::from_array(&setup.env, [2u32, 0u32]));
+    assert_eq!(series.winner, Vec::from_array(&setup.env, [host.clone()]));
+    assert_eq!(series.lobbies.len(), 2);
+    let host_user = setup.client.get_user(&host);
+    let guest_user = setup.client.get_user(&guest);
+    assert_eq!(host_user.rating, DEFAULT_RATING + 16);
+    assert_eq!(guest_user.rating, DEFAULT_RATING - 16);
+    assert_eq!((host_user.wins, host_user.games_completed), (1, 1));
+    assert_eq!((guest_user.losses, guest_user.games_completed), (1, 1));
+    setup.verify_user_lobby(&host, second_lobby_id);
+}
+#[test]
+fn test_leaving_forfeits_series() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_series(&setup, 3);
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    let second_lobby_id = setup.client.get_series(&lobby_id).lobbies.get_unchecked(1);
+    // the original host leaves while leading
+    setup.client.leave_lobby(&host);
+    let series = setup.client.get_series(&lobby_id);
+    assert!(series.finished);
+    assert_eq!(series.scores, Vec::from_array(&setup.env, [1u32, 1u32]));
+    assert_eq!(series.winner, Vec::from_array(&setup.env, [guest.clone()]));
+    assert_eq!(setup.client.get_user(&guest).wins, 1);
+    assert_eq!(setup.client.get_user(&host).current_lobby, 0);
+}
+#[test]
+fn test_series_forfeited_by_player_in_another_game() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_series(&setup, 2);
+    // the host opens another lobby while the first game is still going, then wins it
+    let other_lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: create_test_lobby_parameters(&setup.env) });
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    let series = setup.client.get_series(&lobby_id);
+    assert!(series.finished);
+    assert_eq!(series.lobbies.len(), 1);
+    assert_eq!(series.winner, Vec::from_array(&setup.env, [guest.clone()]));
+    setup.verify_user_lobby(&host, other_lobby_id);
+}
+#[test]
+fn test_setup_timeout_counts_against_staller() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_series(&setup, 2);
+    // the guest never commits a setup and the host claims the timeout
+    setup.client.commit_setup(&host, &CommitSetupReq {
+        lobby_id,
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+        zz_hidden_ranks: Vec::new(&setup.env),
+    });
+    setup.env.ledger().with_mut(|l| l.sequence_number += 101);
+    let lobby_info = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
+    assert_eq!(lobby_info.phase, Phase::Aborted);
+    assert_eq!(lobby_info.end_player, Subphase::Guest);
+    let series = setup.client.get_series(&lobby_id);
+    assert_eq!(series.scores, Vec::from_array(&setup.env, [1u32, 0u32]));
+    assert_eq!(series.draws, 0);
+    assert_eq!(series.lobbies.len(), 2);
+    // a second stall hands the host the series
+    let second_lobby_id = series.lobbies.get_unchecked(1);
+    setup.client.commit_setup(&host, &CommitSetupReq {
+        lobby_id: second_lobby_id,
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+        zz_hidden_ranks: Vec::new(&setup.env),
+    });
+    setup.env.ledger().with_mut(|l| l.sequence_number += 101);
+    setup.client.redeem_win(&host, &RedeemWinReq { lobby_id: second_lobby_id });
+    let series = setup.client.get_series(&lobby_id);
+    assert!(series.finished);
+    assert_eq!(series.scores, Vec::from_array(&setup.env, [2u32, 0u32]));
+    assert_eq!(series.winner, Vec::from_array(&setup.env, [host.clone()]));
+    assert_eq!(setup.client.get_user(&host).wins, 1);
+}
+#[test]
+fn test_drawn_series_stops_at_game_limit() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_series(&setup, 1);
+    for _ in 0..3 {
+        let current_lobby_id = setup.client.get_user(&host).current_lobby;
+        draw_game(&setup, current_lobby_id);
+    }
+    let series = setup.client.get_series(&lobby_id);
+    assert!(series.finished);
+    assert_eq!(series.draws, 3);
+    assert_eq!(series.lobbies.len(), 3);
+    assert!(series.winner.is_empty());
+    let host_user = setup.client.get_user(&host);
+    assert_eq!(host_user.draws, 1);
+    assert_eq!(host_user.rating, DEFAULT_RATING);
+}
+#[test]
+fn test_series_validation() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let mut params = create_full_stratego_board_parameters(&setup.env);
+    params.series_target_wins = MAX_SERIES_TARGET_WINS + 1;
+    let result = setup.client.try_make_lobby(&host, &MakeLobbyReq { parameters: params });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    let result = setup.client.try_get_series(&1);
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+// endregion
@@ -10,15 +10,14 @@ fn test_commit_setup_success() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
     assert_eq!(snapshot.phase, Phase::SetupCommit);
     assert_eq!(snapshot.subphase, Subphase::Both);
-    let host_root = BytesN::from_array(&setup.env, &[1u8; 16]);
-    let guest_root = BytesN::from_array(&setup.env, &[2u8; 16]);
+    let host_root = Bytes::from_array(&setup.env, &[1u8; 16]);
+    let guest_root = Bytes::from_array(&setup.env, &[2u8; 16]);
     setup.client.commit_setup(&host, &CommitSetupReq {
         lobby_id,
         rank_commitment_root: host_root,
@@ -41,11 +40,10 @@ fn test_commit_setup_wrong_subphase() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
-    let host_root = BytesN::from_array(&setup.env, &[1u8; 16]);
+    let host_root = Bytes::from_array(&setup.env, &[1u8; 16]);
     setup.client.commit_setup(&host, &CommitSetupReq {
         lobby_id,
         rank_commitment_root: host_root.clone(),
@@ -66,11 +64,10 @@ fn test_setup_guest_commits_first() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
-    let guest_root = BytesN::from_array(&setup.env, &[2u8; 16]);
+    let guest_root = Bytes::from_array(&setup.env, &[2u8; 16]);
     setup.client.commit_setup(&guest, &CommitSetupReq {
         lobby_id,
         rank_commitment_root: guest_root,
@@ -79,7 +76,7 @@ fn test_setup_guest_commits_first() {
     let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
     assert_eq!(snapshot.subphase, Subphase::Host);
     assert_eq!(snapshot.phase, Phase::SetupCommit);
-    let host_root = BytesN::from_array(&setup.env, &[1u8; 16]);
+    let host_root = Bytes::from_array(&setup.env, &[1u8; 16]);
     setup.client.commit_setup(&host, &CommitSetupReq {
         lobby_id,
         rank_commitment_root: host_root,
@@ -94,11 +91,10 @@ fn test_setup_leave_during_commit() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let params = create_test_lobby_parameters(&setup.env);
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
-    let host_root = BytesN::from_array(&setup.env, &[1u8; 16]);
+    let host_root = Bytes::from_array(&setup.env, &[1u8; 16]);
     setup.client.commit_setup(&host, &CommitSetupReq {
         lobby_id,
         rank_commitment_root: host_root,
@@ -118,10 +114,9 @@ fn test_setup_no_security_mode_success() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let mut params = create_test_lobby_parameters(&setup.env);
     params.security_mode = false;
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     // Use the same setup logic as security mode tests
     let (host_setup, host_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
@@ -153,17 +148,142 @@ fn test_setup_no_security_mode_missing_ranks() {
     let setup = TestSetup::new();
     let host = setup.generate_address();
     let guest = setup.generate_address();
-    let lobby_id = 1u32;
     let mut params = create_test_lobby_parameters(&setup.env);
     params.security_mode = false;
-    setup.client.make_lobby(&host, &MakeLobbyReq { lobby_id, parameters: params });
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
     setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
     // Try to commit setup without providing hidden ranks (should fail)
     let result = setup.client.try_commit_setup(&host, &CommitSetupReq {
         lobby_id,
-        rank_commitment_root: BytesN::from_array(&setup.env, &[1u8; 16]),
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
         zz_hidden_ranks: Vec::new(&setup.env),
     });
     assert!(result.is_err());
 }
+// endregion
+// region host_team tests
+fn create_asymmetric_board_parameters(env: &Env, host_team: u32) -> LobbyParameters {
+    // red setup is a row along the bottom, blue setup is a column on the right edge
+    let mut tiles = Vec::new(env);
+    for y in 0..3 {
+        for x in 0..4 {
+            let setup = if y == 0 && x < 2 { 0 } else if x == 3 && y > 0 { 1 } else { 2 };
+            tiles.push_back(pack_tile(&Tile { pos: Pos { x, y }, passable: true, setup, setup_zone: 1 }));
+        }
+    }
+    let mut params = create_test_lobby_parameters(env);
+    params.board = Board {
+        hex: false,
+        name: String::from_str(env, "Asymmetric"),
+        size: Pos { x: 4, y: 3 },
+        tiles,
+    };
+    params.host_team = host_team;
+    params.max_ranks = Vec::from_array(env, [1u32, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+    params.security_mode = false;
+    params
+}
+#[test]
+fn test_host_team_chooses_setup_side() {
+    for host_team in [0u32, 1u32] {
+        let setup = TestSetup::new();
+        let host = setup.generate_address();
+        let guest = setup.generate_address();
+        let params = create_asymmetric_board_parameters(&setup.env, host_team);
+        let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params.clone() });
+        setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+        // every pawn belongs to whoever plays its tile's setup side
+        let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+        let mut setup_map: Map<Pos, u32> = Map::new(&setup.env);
+        for packed_tile in params.board.tiles.iter() {
+            let tile = Contract::unpack_tile(packed_tile);
+            setup_map.set(tile.pos, tile.setup);
+        }
+        let mut host_pawns = 0;
+        for (pawn_id, (_, pawn)) in snapshot.pawns_map.iter() {
+            let (setup_pos, owner) = Contract::decode_pawn_id(pawn_id);
+            assert_eq!(setup_pos, pawn.pos);
+            assert_eq!(setup_map.get_unchecked(pawn.pos) == host_team, owner == UserIndex::Host);
+            if owner == UserIndex::Host { host_pawns += 1; }
+        }
+        assert_eq!(host_pawns, 2);
+        // revealed rank counts are checked per owner, so each side fits max_ranks
+        let (host_setup, host_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
+            create_setup_commits_from_game_state(&setup.env, lobby_id, &UserIndex::Host)
+        });
+        let (guest_setup, guest_hidden_ranks) = setup.env.as_contract(&setup.contract_id, || {
+            create_setup_commits_from_game_state(&setup.env, lobby_id, &UserIndex::Guest)
+        });
+        let (host_root, _host_proofs) = get_merkel(&setup.env, &host_setup, &host_hidden_ranks);
+        let (guest_root, _guest_proofs) = get_merkel(&setup.env, &guest_setup, &guest_hidden_ranks);
+        setup.client.commit_setup(&host, &CommitSetupReq { lobby_id, rank_commitment_root: host_root, zz_hidden_ranks: host_hidden_ranks.clone() });
+        setup.client.commit_setup(&guest, &CommitSetupReq { lobby_id, rank_commitment_root: guest_root, zz_hidden_ranks: guest_hidden_ranks.clone() });
+        let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+        assert_eq!(snapshot.lobby_info.phase, Phase::MoveCommit);
+        let host_counts = Contract::get_revealed_rank_counts(&setup.env, UserIndex::Host, &snapshot.game_state);
+        let guest_counts = Contract::get_revealed_rank_counts(&setup.env, UserIndex::Guest, &snapshot.game_state);
+        assert_eq!((host_counts[0], host_counts[4]), (1, 1));
+        assert_eq!((guest_counts[0], guest_counts[4]), (1, 1));
+        // losing the guest throne hands the win to the host on either side
+        let mut game_state = snapshot.game_state.clone();
+        for (index, packed_pawn) in snapshot.game_state.pawns.iter().enumerate() {
+            let mut pawn = Contract::unpack_pawn(&setup.env, packed_pawn);
+            if Contract::decode_pawn_id(pawn.pawn_id).1 == UserIndex::Guest && pawn.rank.get_unchecked(0) == 0 {
+                pawn.alive = false;
+                game_state.pawns.set(index as u32, Contract::pack_pawn(pawn));
+            }
+        }
+        assert_eq!(Contract::check_game_over(&setup.env, &game_state, &params), (Subphase::Host, EndReason::ThroneCaptured));
+        // generated moves are legal for both players on either side
+        let host_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &params, &UserIndex::Host, &host_hidden_ranks, 11).unwrap();
+        let guest_move = generate_valid_move_req(&setup.env, &snapshot.pawns_map, &params, &UserIndex::Guest, &guest_hidden_ranks, 12).unwrap();
+        assert_eq!(Contract::decode_pawn_id(host_move.pawn_id).1, UserIndex::Host);
+        assert_eq!(Contract::decode_pawn_id(guest_move.pawn_id).1, UserIndex::Guest);
+        let host_hash = create_test_move_hash(&setup.env, host_move.pawn_id, host_move.start_pos, host_move.target_pos, host_move.salt);
+        let guest_hash = create_test_move_hash(&setup.env, guest_move.pawn_id, guest_move.start_pos, guest_move.target_pos, guest_move.salt);
+        setup.client.commit_move_and_prove_move(&host, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [host_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [host_move]) });
+        setup.client.commit_move_and_prove_move(&guest, &CommitMoveReq { lobby_id, move_hashes: Vec::from_array(&setup.env, [guest_hash]) }, &ProveMoveReq { lobby_id, move_proofs: Vec::from_array(&setup.env, [guest_move]) });
+        let snapshot = extract_full_snapshot(&setup.env, &setup.contract_id, lobby_id);
+        assert_ne!(snapshot.lobby_info.phase, Phase::Aborted);
+        assert_eq!(snapshot.game_state.turn, 2);
+    }
+}
+// endregion
+// region hash_scheme tests
+#[test]
+fn test_tagged32_rejects_short_commitments() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.hash_scheme = HashScheme::Tagged32;
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let result = setup.client.try_commit_setup(&host, &CommitSetupReq {
+        lobby_id,
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+        zz_hidden_ranks: Vec::new(&setup.env),
+    });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    for address in [&host, &guest] {
+        setup.client.commit_setup(address, &CommitSetupReq {
+            lobby_id,
+            rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 32]),
+            zz_hidden_ranks: Vec::new(&setup.env),
+        });
+    }
+    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.phase, Phase::MoveCommit);
+    let result = setup.client.try_commit_move(&host, &CommitMoveReq {
+        lobby_id,
+        move_hashes: Vec::from_array(&setup.env, [Bytes::from_array(&setup.env, &[3u8; 16])]),
+    });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    setup.client.commit_move(&host, &CommitMoveReq {
+        lobby_id,
+        move_hashes: Vec::from_array(&setup.env, [Bytes::from_array(&setup.env, &[3u8; 32])]),
+    });
+    let snapshot = extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    assert_eq!(snapshot.subphase, Subphase::Guest);
+}
 // endregion
\ No newline at end of file
@@ -0,0 +1,185 @@
+#![cfg(test)]
+#![allow(unused_variables)]
+extern crate std;
+use super::super::*;
+use super::super::test_utils::*;
+use super::test_utils::*;
+use soroban_sdk::testutils::Ledger as _;
+use soroban_sdk::token::{StellarAssetClient, TokenClient};
+
+const STARTING_BALANCE: i128 = 1000;
+const STAKE: i128 = 100;
+
+fn create_stake_token(setup: &TestSetup, players: &[&Address]) -> Address {
+    let token_admin = setup.generate_address();
+    let token_address = setup.env.register_stellar_asset_contract_v2(token_admin).address();
+    let asset_client = StellarAssetClient::new(&setup.env, &token_address);
+    for player in players {
+        asset_client.mint(player, &STARTING_BALANCE);
+    }
+    token_address
+}
+
+fn create_staked_lobby_parameters(setup: &TestSetup, token_address: &Address) -> LobbyParameters {
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.stake_amount = STAKE;
+    params.stake_token = Vec::from_array(&setup.env, [token_address.clone()]);
+    params
+}
+
+// region stake tests
+#[test]
+fn test_stake_escrow_and_winner_payout() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host, &guest]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let params = create_staked_lobby_parameters(&setup, &token_address);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    assert_eq!(token.balance(&host), STARTING_BALANCE - STAKE);
+    assert_eq!(token.balance(&setup.contract_id), STAKE);
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    assert_eq!(token.balance(&guest), STARTING_BALANCE - STAKE);
+    assert_eq!(token.balance(&setup.contract_id), STAKE * 2);
+    setup.client.leave_lobby(&guest);
+    assert_eq!(token.balance(&host), STARTING_BALANCE + STAKE);
+    assert_eq!(token.balance(&guest), STARTING_BALANCE - STAKE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+    // leaving an already finished lobby must not pay out again
+    setup.client.leave_lobby(&host);
+    assert_eq!(token.balance(&host), STARTING_BALANCE + STAKE);
+}
+#[test]
+fn test_stake_refund_on_declined_invite() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let invited = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host, &invited]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let mut params = create_staked_lobby_parameters(&setup, &token_address);
+    params.invited_guests = Vec::from_array(&setup.env, [invited.clone()]);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.decline_invite(&invited, &DeclineInviteReq { lobby_id });
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+    assert_eq!(token.balance(&invited), STARTING_BALANCE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+}
+#[test]
+fn test_rematch_stakes() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host, &guest]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let params = create_staked_lobby_parameters(&setup, &token_address);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    assert_eq!(token.balance(&host), STARTING_BALANCE + STAKE);
+    // both stakes are escrowed again for the rematch
+    setup.client.offer_rematch(&host, &RematchReq { lobby_id });
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+    let rematch_id = setup.client.accept_rematch(&guest, &RematchReq { lobby_id });
+    assert_eq!(token.balance(&guest), STARTING_BALANCE - STAKE * 2);
+    assert_eq!(token.balance(&setup.contract_id), STAKE * 2);
+    setup.client.surrender(&host, &SurrenderReq { lobby_id: rematch_id });
+    assert_eq!(token.balance(&guest), STARTING_BALANCE);
+    // an offer that is withdrawn by leaving is refunded
+    setup.client.offer_rematch(&host, &RematchReq { lobby_id: rematch_id });
+    assert_eq!(token.balance(&host), STARTING_BALANCE - STAKE);
+    setup.client.leave_lobby(&host);
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+}
+#[test]
+fn test_series_stake_settled_once() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host, &guest]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let mut params = create_staked_lobby_parameters(&setup, &token_address);
+    params.series_target_wins = 2;
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    setup.client.surrender(&host, &SurrenderReq { lobby_id });
+    // the pot stays in escrow until the series is decided
+    assert_eq!(token.balance(&setup.contract_id), STAKE * 2);
+    let second_lobby_id = setup.client.get_series(&lobby_id).lobbies.get_unchecked(1);
+    setup.client.surrender(&host, &SurrenderReq { lobby_id: second_lobby_id });
+    assert_eq!(token.balance(&guest), STARTING_BALANCE + STAKE);
+    assert_eq!(token.balance(&host), STARTING_BALANCE - STAKE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+}
+#[test]
+fn test_stake_refund_on_lobby_abort() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let params = create_staked_lobby_parameters(&setup, &token_address);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.leave_lobby(&host);
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+}
+#[test]
+fn test_stake_refund_on_setup_timeout() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host, &guest]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let params = create_staked_lobby_parameters(&setup, &token_address);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    setup.client.commit_setup(&host, &CommitSetupReq {
+        lobby_id,
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+        zz_hidden_ranks: Vec::new(&setup.env),
+    });
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = snapshot.lobby_info.last_edited_ledger_seq + 100);
+    let result = setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
+    assert_eq!(result.phase, Phase::Aborted);
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+    assert_eq!(token.balance(&guest), STARTING_BALANCE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+}
+#[test]
+fn test_stake_split_on_draw() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host, &guest]);
+    let token = TokenClient::new(&setup.env, &token_address);
+    let params = create_staked_lobby_parameters(&setup, &token_address);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params.clone() });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    let mut lobby_info = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id).lobby_info;
+    Contract::end_game(&mut lobby_info, Phase::Finished, Subphase::None, EndReason::ThroneCaptured, Subphase::None);
+    setup.env.as_contract(&setup.contract_id, || {
+        Contract::settle_stakes(&setup.env, &lobby_info, &params);
+    });
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+    assert_eq!(token.balance(&guest), STARTING_BALANCE);
+    assert_eq!(token.balance(&setup.contract_id), 0);
+}
+#[test]
+fn test_stake_parameter_validation() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let token_address = create_stake_token(&setup, &[&host]);
+    let mut params = create_test_lobby_parameters(&setup.env);
+    params.stake_amount = STAKE;
+    let result = setup.client.try_make_lobby(&host, &MakeLobbyReq { parameters: params });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    let mut params = create_staked_lobby_parameters(&setup, &token_address);
+    params.stake_amount = 0;
+    let result = setup.client.try_make_lobby(&host, &MakeLobbyReq { parameters: params });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    let token = TokenClient::new(&setup.env, &token_address);
+    assert_eq!(token.balance(&host), STARTING_BALANCE);
+}
+// endregion
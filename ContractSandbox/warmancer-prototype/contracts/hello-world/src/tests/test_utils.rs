@@ -25,14 +25,15 @@ impl TestSetup {
             client,
         }
     }
-    pub fn make_lobby(&self, address: &Address, req: &MakeLobbyReq, label: &str) -> () {
+    pub fn make_lobby(&self, address: &Address, req: &MakeLobbyReq, label: &str) -> LobbyId {
         let fn_name = ">make_lobby()";
         let pre_label = &[label, fn_name, "-pre"].concat();
         let post_label = &[label, fn_name, "-post"].concat();
         std::println!("{} = A lobby will be made, security_mode {} host team {}", pre_label, req.parameters.security_mode, req.parameters.host_team);
-        self.client.make_lobby(address, req);
-        let post_snapshot = extract_lobby_snapshot(&self.env, &self.contract_id, req.lobby_id);
-        post_snapshot.print_snapshot_info(req.lobby_id, post_label);
+        let lobby_id = self.client.make_lobby(address, req);
+        let post_snapshot = extract_lobby_snapshot(&self.env, &self.contract_id, lobby_id);
+        post_snapshot.print_snapshot_info(lobby_id, post_label);
+        lobby_id
     }
     pub fn join_lobby(&self, address: &Address, req: &JoinLobbyReq, label: &str) -> () {
         let fn_name = ">join_lobby()";
@@ -257,12 +258,12 @@ impl Tree {
     }
 }
 
-pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tree) {
+pub fn build_merkle_tree(env: &Env, leaves: Vec<MerkleHash>) -> (MerkleHash, Tree) {
     std::println!("=== build_merkle_tree START ===");
     std::println!("Number of leaves: {}", leaves.len());
     
     for (i, leaf) in leaves.iter().enumerate() {
-        std::println!("Leaf {}: {:?}", i, leaf.to_array());
+        std::println!("Leaf {}: {:?}", i, leaf);
     }
     
     if leaves.is_empty() {
@@ -271,7 +272,7 @@ pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tre
             leaves: Vec::new(env),
             levels: Vec::from_array(env, [Vec::from_array(env, [zero_root.clone()])]),
         };
-        std::println!("Empty tree, returning zero root: {:?}", zero_root.to_array());
+        std::println!("Empty tree, returning zero root: {:?}", zero_root);
         return (zero_root, tree);
     }
     
@@ -306,19 +307,16 @@ pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tre
             let left = current_nodes.get(i).unwrap();
             let right = current_nodes.get(i + 1).unwrap();
             
-            std::println!("  Pair {}: left={:?}, right={:?}", i/2, left.to_array(), right.to_array());
+            std::println!("  Pair {}: left={:?}, right={:?}", i/2, left, right);
             
-            let mut combined_bytes = [0u8; 32];
-            combined_bytes[0..16].copy_from_slice(&left.to_array());
-            combined_bytes[16..32].copy_from_slice(&right.to_array());
+            let mut combined_bytes = left.clone();
+            combined_bytes.append(&right);
             
             std::println!("  Combined bytes: {:?}", combined_bytes);
             
-            let parent_full = env.crypto().sha256(&Bytes::from_array(env, &combined_bytes));
-            let parent_bytes = parent_full.to_array();
-            let parent_hash = MerkleHash::from_array(env, &parent_bytes[0..16].try_into().unwrap());
+            let parent_hash = Contract::commitment_hash(env, HashScheme::Legacy, HASH_TAG_NODE, &combined_bytes);
             
-            std::println!("  Parent hash: {:?}", parent_hash.to_array());
+            std::println!("  Parent hash: {:?}", parent_hash);
             
             next_level.push_back(parent_hash);
             i += 2;
@@ -331,7 +329,7 @@ pub fn build_merkle_tree(env: &Env, leaves: Vec<BytesN<16>>) -> (BytesN<16>, Tre
     
     let root = levels.get(levels.len() as u32 - 1).unwrap().get(0).unwrap();
     
-    std::println!("Final root: {:?}", root.to_array());
+    std::println!("Final root: {:?}", root);
     std::println!("Total levels: {}", levels.len());
     
     let tree = Tree {
@@ -351,20 +349,18 @@ pub fn create_test_move_hash(env: &Env, pawn_id: PawnId, start_pos: Pos, target_
         salt,
     };
     let serialized = move_proof.to_xdr(env);
-    let full_hash = env.crypto().sha256(&serialized).to_bytes().to_array();
-    HiddenMoveHash::from_array(env, &full_hash[0..16].try_into().unwrap())
+    Contract::commitment_hash(env, HashScheme::Legacy, HASH_TAG_MOVE, &serialized)
 }
 
-pub fn create_and_advance_to_move_commit(setup: &TestSetup, lobby_id: u32) -> (Address, Address) {
+pub fn create_and_advance_to_move_commit(setup: &TestSetup) -> (u32, Address, Address) {
     let host_address = setup.generate_address();
     let guest_address = setup.generate_address();
 
     let lobby_parameters = create_test_lobby_parameters(&setup.env);
     let make_req = MakeLobbyReq {
-        lobby_id,
         parameters: lobby_parameters,
     };
-    setup.client.make_lobby(&host_address, &make_req);
+    let lobby_id = setup.client.make_lobby(&host_address, &make_req);
 
     let join_req = JoinLobbyReq { lobby_id };
     setup.client.join_lobby(&guest_address, &join_req);
@@ -403,18 +399,17 @@ pub fn create_and_advance_to_move_commit(setup: &TestSetup, lobby_id: u32) -> (A
         assert_eq!(lobby_info.subphase, Subphase::Both);
     });
 
-    (host_address, guest_address)
+    (lobby_id, host_address, guest_address)
 }
 
-pub fn setup_lobby_for_commit_setup(setup: &TestSetup, lobby_id: u32) -> (u32, Address, Address) {
+pub fn setup_lobby_for_commit_setup(setup: &TestSetup) -> (u32, Address, Address) {
     let lobby_parameters = create_full_stratego_board_parameters(&setup.env);
 
     let host_address = setup.generate_address();
     let make_req = MakeLobbyReq {
-        lobby_id,
         parameters: lobby_parameters,
     };
-    setup.client.make_lobby(&host_address, &make_req);
+    let lobby_id = setup.client.make_lobby(&host_address, &make_req);
 
     let guest_address = setup.generate_address();
     let join_req = JoinLobbyReq { lobby_id };
@@ -423,8 +418,8 @@ pub fn setup_lobby_for_commit_setup(setup: &TestSetup, lobby_id: u32) -> (u32, A
     (lobby_id, host_address, guest_address)
 }
 
-pub fn setup_lobby_for_commit_move(setup: &TestSetup, lobby_id: u32) -> (u32, Address, Address, Vec<HiddenRank>, Vec<HiddenRank>, Vec<MerkleProof>, Vec<MerkleProof>) {
-    let (lobby_id, host_address, guest_address) = setup_lobby_for_commit_setup(setup, lobby_id);
+pub fn setup_lobby_for_commit_move(setup: &TestSetup) -> (u32, Address, Address, Vec<HiddenRank>, Vec<HiddenRank>, Vec<MerkleProof>, Vec<MerkleProof>) {
+    let (lobby_id, host_address, guest_address) = setup_lobby_for_commit_setup(setup);
 
     let (host_ranks, guest_ranks, host_merkle_proofs, guest_merkle_proofs) = advance_through_complete_setup_phase(setup, lobby_id, &host_address, &guest_address);
 
@@ -0,0 +1,232 @@
+#![cfg(test)]
+#![allow(unused_variables)]
+extern crate std;
+use super::super::*;
+use super::super::test_utils::*;
+use super::test_utils::*;
+use soroban_sdk::testutils::Ledger as _;
+
+fn set_rating(setup: &TestSetup, address: &Address, rating: u32) {
+    setup.env.as_contract(&setup.contract_id, || {
+        let mut user = Contract::default_user();
+        user.rating = rating;
+        setup.env.storage().persistent().set(&DataKey::User(address.clone()), &user);
+    });
+}
+
+// returns the players ordered by seed, seed 1 first
+fn setup_tournament(setup: &TestSetup, player_count: u32, max_players: u32) -> (TournamentId, Address, std::vec::Vec<Address>) {
+    let organizer = setup.generate_address();
+    let tournament_id = setup.client.make_tournament(&organizer, &MakeTournamentReq {
+        max_players,
+        parameters: create_test_lobby_parameters(&setup.env),
+    });
+    let mut players = std::vec::Vec::new();
+    for seed in 0..player_count {
+        let player = setup.generate_address();
+        set_rating(setup, &player, 2000 - seed * 10);
+        players.push(player);
+    }
+    // register in reverse so seeding can't just follow registration order
+    for player in players.iter().rev() {
+        setup.client.join_tournament(player, &TournamentReq { tournament_id });
+    }
+    (tournament_id, organizer, players)
+}
+
+fn match_players(setup: &TestSetup, lobby_id: LobbyId) -> (Address, Address) {
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    (snapshot.lobby_info.host_address.get_unchecked(0), snapshot.lobby_info.guest_address.get_unchecked(0))
+}
+
+// the guest of every match still being played in the round surrenders
+fn play_round_hosts_win(setup: &TestSetup, tournament_id: TournamentId) {
+    let tournament = setup.client.get_tournament(&tournament_id);
+    for lobby_id in tournament.matches.iter() {
+        if lobby_id == 0 || extract_phase_snapshot(&setup.env, &setup.contract_id, lobby_id).phase == Phase::Finished {
+            continue;
+        }
+        let (host, guest) = match_players(setup, lobby_id);
+        setup.client.surrender(&guest, &SurrenderReq { lobby_id });
+    }
+}
+
+// region tournament tests
+#[test]
+fn test_tournament_seeded_bracket() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, 8, 8);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.round, 1);
+    assert_eq!(tournament.players.get_unchecked(0), players[0]);
+    assert_eq!(tournament.matches.len(), 4);
+    assert_eq!(tournament.pending_matches, 4);
+    // 1v8 4v5 2v7 3v6
+    for (pair_index, (host_seed, guest_seed)) in [(1, 8), (4, 5), (2, 7), (3, 6)].iter().enumerate() {
+        let lobby_id = tournament.matches.get_unchecked(pair_index as u32);
+        let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+        assert_eq!(snapshot.lobby_info.phase, Phase::SetupCommit);
+        assert_eq!(snapshot.lobby_info.tournament, tournament_id);
+        assert_eq!(snapshot.lobby_info.host_address.get_unchecked(0), players[host_seed - 1]);
+        assert_eq!(snapshot.lobby_info.guest_address.get_unchecked(0), players[guest_seed - 1]);
+        setup.verify_user_lobby(&players[host_seed - 1], lobby_id);
+    }
+    let result = setup.client.try_join_tournament(&setup.generate_address(), &TournamentReq { tournament_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongPhase);
+    // the round only advances once every match is over
+    let first_lobby_id = tournament.matches.get_unchecked(0);
+    setup.client.surrender(&players[7], &SurrenderReq { lobby_id: first_lobby_id });
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.round, 1);
+    assert_eq!(tournament.results.get_unchecked(0), Vec::from_array(&setup.env, [players[0].clone()]));
+    play_round_hosts_win(&setup, tournament_id);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.round, 2);
+    assert_eq!(tournament.matches.len(), 2);
+    assert_eq!(match_players(&setup, tournament.matches.get_unchecked(0)), (players[0].clone(), players[3].clone()));
+    assert_eq!(match_players(&setup, tournament.matches.get_unchecked(1)), (players[1].clone(), players[2].clone()));
+    play_round_hosts_win(&setup, tournament_id);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.round, 3);
+    assert_eq!(match_players(&setup, tournament.matches.get_unchecked(0)), (players[0].clone(), players[1].clone()));
+    play_round_hosts_win(&setup, tournament_id);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert!(tournament.finished);
+    assert_eq!(tournament.winner, Vec::from_array(&setup.env, [players[0].clone()]));
+}
+#[test]
+fn test_tournament_byes() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, 5, 8);
+    let result = setup.client.try_close_tournament(&players[0], &TournamentReq { tournament_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::Unauthorized);
+    setup.client.close_tournament(&organizer, &TournamentReq { tournament_id });
+    // only 4v5 is played, the top three seeds get byes
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.pending_matches, 1);
+    assert_eq!(tournament.matches, Vec::from_array(&setup.env, [0u32, tournament.matches.get_unchecked(1), 0u32, 0u32]));
+    assert_eq!(match_players(&setup, tournament.matches.get_unchecked(1)), (players[3].clone(), players[4].clone()));
+    // the lower seed wins this time
+    setup.client.surrender(&players[3], &SurrenderReq { lobby_id: tournament.matches.get_unchecked(1) });
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.round, 2);
+    assert_eq!(match_players(&setup, tournament.matches.get_unchecked(0)), (players[0].clone(), players[4].clone()));
+    assert_eq!(match_players(&setup, tournament.matches.get_unchecked(1)), (players[1].clone(), players[2].clone()));
+}
+#[test]
+fn test_tournament_timeouts_and_double_aborts() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, 4, 4);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    let timeout_lobby_id = tournament.matches.get_unchecked(0);
+    let stalled_lobby_id = tournament.matches.get_unchecked(1);
+    // seed 4 never commits a setup and seed 1 claims the timeout
+    setup.client.commit_setup(&players[0], &CommitSetupReq {
+        lobby_id: timeout_lobby_id,
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+        zz_hidden_ranks: Vec::new(&setup.env),
+    });
+    let result = setup.client.try_reap_stale_lobby(&ReapStaleLobbyReq { lobby_id: stalled_lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    setup.env.ledger().with_mut(|l| l.sequence_number += 101);
+    setup.client.extend_lobby_ttl(&organizer, &timeout_lobby_id);
+    setup.client.extend_lobby_ttl(&organizer, &stalled_lobby_id);
+    let result = setup.client.try_reap_stale_lobby(&ReapStaleLobbyReq { lobby_id: timeout_lobby_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::WrongSubphase);
+    setup.client.redeem_win(&players[0], &RedeemWinReq { lobby_id: timeout_lobby_id });
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert_eq!(tournament.results.get_unchecked(0), Vec::from_array(&setup.env, [players[0].clone()]));
+    // neither seed 2 nor seed 3 commits, so both are out and seed 1 wins the final by bye
+    let stalled = setup.client.reap_stale_lobby(&ReapStaleLobbyReq { lobby_id: stalled_lobby_id });
+    assert_eq!(stalled.phase, Phase::Aborted);
+    assert_eq!(stalled.end_player, Subphase::None);
+    // and both are released from the dead lobby
+    assert_eq!(setup.client.get_user(&players[1]).current_lobby, 0);
+    assert_eq!(setup.client.get_user(&players[2]).current_lobby, 0);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert!(tournament.finished);
+    assert_eq!(tournament.round, 2);
+    assert_eq!(tournament.winner, Vec::from_array(&setup.env, [players[0].clone()]));
+}
+#[test]
+fn test_tournament_draw_goes_to_higher_seed() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, 2, 2);
+    let lobby_id = setup.client.get_tournament(&tournament_id).matches.get_unchecked(0);
+    setup.env.as_contract(&setup.contract_id, || {
+        let mut lobby_info: LobbyInfo = setup.env.storage().temporary().get(&DataKey::LobbyInfo(lobby_id)).unwrap();
+        Contract::end_game(&mut lobby_info, Phase::Finished, Subphase::None, EndReason::Blocked, Subphase::None);
+        setup.env.storage().temporary().set(&DataKey::LobbyInfo(lobby_id), &lobby_info);
+        Contract::finalize_game(&setup.env, lobby_id, &mut lobby_info);
+    });
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert!(tournament.finished);
+    assert_eq!(tournament.winner, Vec::from_array(&setup.env, [players[0].clone()]));
+}
+#[test]
+fn test_tournament_player_in_another_game_forfeits() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, 3, 4);
+    // seeds 2 and 3 are sitting in open lobbies of their own when the bracket is paired
+    let busy_lobby_id = setup.client.make_lobby(&players[1], &MakeLobbyReq { parameters: create_test_lobby_parameters(&setup.env) });
+    setup.client.make_lobby(&players[2], &MakeLobbyReq { parameters: create_test_lobby_parameters(&setup.env) });
+    setup.client.close_tournament(&organizer, &TournamentReq { tournament_id });
+    // both of them forfeit, so seed 1 wins without a game being started
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert!(tournament.finished);
+    assert_eq!(tournament.winner, Vec::from_array(&setup.env, [players[0].clone()]));
+    setup.verify_user_lobby(&players[1], busy_lobby_id);
+}
+#[test]
+fn test_tournament_match_left_mid_game() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, 4, 4);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    let (first_match, second_match) = (tournament.matches.get_unchecked(0), tournament.matches.get_unchecked(1));
+    let (_, first_guest) = match_players(&setup, first_match);
+    setup.client.surrender(&first_guest, &SurrenderReq { lobby_id: first_match });
+    // leaving the last match of the round must not count the winner as busy in the game that just ended
+    let (second_host, second_guest) = match_players(&setup, second_match);
+    setup.client.leave_lobby(&second_guest);
+    let tournament = setup.client.get_tournament(&tournament_id);
+    assert!(!tournament.finished);
+    assert_eq!(tournament.matches.len(), 1);
+    let (final_host, final_guest) = match_players(&setup, tournament.matches.get_unchecked(0));
+    assert!(final_host == second_host || final_guest == second_host);
+    play_round_hosts_win(&setup, tournament_id);
+    assert!(setup.client.get_tournament(&tournament_id).finished);
+}
+#[test]
+fn test_full_tournament() {
+    let setup = TestSetup::new();
+    let (tournament_id, organizer, players) = setup_tournament(&setup, MAX_TOURNAMENT_PLAYERS, MAX_TOURNAMENT_PLAYERS);
+    let mut rounds = 0;
+    while !setup.client.get_tournament(&tournament_id).finished {
+        play_round_hosts_win(&setup, tournament_id);
+        rounds += 1;
+    }
+    assert_eq!(rounds, 4);
+    assert_eq!(setup.client.get_tournament(&tournament_id).winner, Vec::from_array(&setup.env, [players[0].clone()]));
+}
+#[test]
+fn test_tournament_validation() {
+    let setup = TestSetup::new();
+    let organizer = setup.generate_address();
+    let params = create_test_lobby_parameters(&setup.env);
+    let result = setup.client.try_make_tournament(&organizer, &MakeTournamentReq { max_players: MAX_TOURNAMENT_PLAYERS + 1, parameters: params.clone() });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    let mut series_params = params.clone();
+    series_params.series_target_wins = 2;
+    let result = setup.client.try_make_tournament(&organizer, &MakeTournamentReq { max_players: 4, parameters: series_params });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    let tournament_id = setup.client.make_tournament(&organizer, &MakeTournamentReq { max_players: 4, parameters: params });
+    let player = setup.generate_address();
+    setup.client.join_tournament(&player, &TournamentReq { tournament_id });
+    let result = setup.client.try_join_tournament(&player, &TournamentReq { tournament_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::AlreadyExists);
+    let result = setup.client.try_close_tournament(&organizer, &TournamentReq { tournament_id });
+    assert_eq!(result.unwrap_err().unwrap(), Error::InvalidArgs);
+    let result = setup.client.try_get_tournament(&99);
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+// endregion
@@ -90,11 +90,29 @@ mod unit_tests {
                 tiles: packed_tiles,
             },
             board_hash: BytesN::from_array(env, &[1u8; 16]),
+            clock_budget: 0,
+            clock_increment: 0,
+            combat_outcomes: Vec::new(env),
             dev_mode: true,
+            disconnect_window: 0,
+            dispute_window: 0,
+            hash_scheme: HashScheme::Legacy,
             host_team: 0,
+            invited_guests: Vec::new(env),
             max_ranks: Vec::from_array(env, [1u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32, 0u32]),
+            max_repeated_moves: 0,
+            max_turns: 0,
+            max_turns_without_capture: 0,
+            movement_profiles: Vec::new(env),
+            mutual_block_adjudication: Adjudication::Draw,
             must_fill_all_tiles: false,
+            reap_grace_period: 0,
             security_mode: true,
+            series_target_wins: 0,
+            setup_reveal: false,
+            stake_amount: 0,
+            stake_token: Vec::new(env),
+            turn_limit_adjudication: Adjudication::Draw,
         }
     }
     #[test]
@@ -182,11 +200,37 @@ mod unit_tests {
         params.board.tiles = tiles;
         params.board.size = Pos { x: 4, y: 1 };
         assert!(!Contract::validate_parameters(&env, &params));
+        let mut params = create_baseline_valid_params(&env);
+        for attacker_rank in 0..13u32 {
+            for defender_rank in 0..13u32 {
+                params.combat_outcomes.push_back(Contract::default_combat_outcome(attacker_rank, defender_rank));
+            }
+        }
+        assert!(Contract::validate_parameters(&env, &params));
+        params.combat_outcomes.pop_back();
+        assert!(!Contract::validate_parameters(&env, &params));
+        // mirrored pairs may favor the attacker but can't bounce one way and trade the other
+        params.combat_outcomes.push_back(Contract::default_combat_outcome(12, 12));
+        params.combat_outcomes.set(4 * 13 + 6, CombatOutcome::Neither);
+        assert!(Contract::validate_parameters(&env, &params));
+        params.combat_outcomes.set(6 * 13 + 4, CombatOutcome::Both);
+        assert!(!Contract::validate_parameters(&env, &params));
+        let mut params = create_baseline_valid_params(&env);
+        for rank in 0..13u32 {
+            params.movement_profiles.push_back(Contract::default_movement_profile(rank));
+        }
+        assert!(Contract::validate_parameters(&env, &params));
+        params.movement_profiles.set(2, MovementProfile { can_move: true, diagonal: false, jump: false, max_range: 17 });
+        assert!(!Contract::validate_parameters(&env, &params));
+        params.movement_profiles.set(2, MovementProfile { can_move: true, diagonal: false, jump: false, max_range: 0 });
+        assert!(!Contract::validate_parameters(&env, &params));
+        params.movement_profiles.pop_back();
+        assert!(!Contract::validate_parameters(&env, &params));
     }
     // endregion
-    // region verify_merkle_proof tests
+    // region get_merkle_root tests
     #[test]
-    fn test_verify_merkle_proof_single_and_multiple() {
+    fn test_get_merkle_root_single_and_multiple() {
         let env = Env::default();
         let hidden_ranks = Vec::from_array(&env, [
             HiddenRank { pawn_id: 1, rank: 5, salt: 100 },
@@ -198,7 +242,7 @@ mod unit_tests {
         for hidden_rank in hidden_ranks.iter() {
             let serialized = hidden_rank.clone().to_xdr(&env);
             let full_hash = env.crypto().sha256(&serialized).to_bytes().to_array();
-            let rank_hash = HiddenRankHash::from_array(&env, &full_hash[0..16].try_into().unwrap());
+            let rank_hash = HiddenRankHash::from_slice(&env, &full_hash[0..16]);
             rank_hashes.push_back(rank_hash);
         }
         let (root, tree) = crate::test_utils::build_merkle_tree(&env, rank_hashes.clone());
@@ -206,27 +250,192 @@ mod unit_tests {
             let proof = tree.generate_proof(&env, i as u32);
             let serialized = hidden_rank.clone().to_xdr(&env);
             let full_hash = env.crypto().sha256(&serialized).to_bytes().to_array();
-            let calculated_hash = HiddenRankHash::from_array(&env, &full_hash[0..16].try_into().unwrap());
+            let calculated_hash = HiddenRankHash::from_slice(&env, &full_hash[0..16]);
             assert_eq!(calculated_hash, expected_hash);
-            let is_valid = Contract::verify_merkle_proof(&env, &calculated_hash, &proof, &root);
-            assert!(is_valid);
+            assert_eq!(Contract::get_merkle_root(&env, HashScheme::Legacy, &calculated_hash, &proof), root);
         }
         let hidden_rank = HiddenRank { pawn_id: 100, rank: 5, salt: 1234 };
         let hidden_rank2 = HiddenRank{ pawn_id: 101, rank: 7, salt: 1234 };
         let serialized = hidden_rank.clone().to_xdr(&env);
         let full_hash = env.crypto().sha256(&serialized).to_bytes().to_array();
-        let rank_hash = HiddenRankHash::from_array(&env, &full_hash[0..16].try_into().unwrap());
+        let rank_hash = HiddenRankHash::from_slice(&env, &full_hash[0..16]);
         let serialized2 = hidden_rank2.clone().to_xdr(&env);
         let full_hash2 = env.crypto().sha256(&serialized2).to_bytes().to_array();
-        let rank_hash2 = HiddenRankHash::from_array(&env, &full_hash2[0..16].try_into().unwrap());
+        let rank_hash2 = HiddenRankHash::from_slice(&env, &full_hash2[0..16]);
         let leaves = Vec::from_array(&env, [rank_hash.clone(), rank_hash2.clone()]);
         let (root, tree) = crate::test_utils::build_merkle_tree(&env, leaves);
         let proof = tree.generate_proof(&env, 0);
         let proof2 = tree.generate_proof(&env, 1);
-        let is_valid = Contract::verify_merkle_proof(&env, &rank_hash, &proof, &root);
-        assert!(is_valid);
-        let is_valid2 = Contract::verify_merkle_proof(&env, &rank_hash2, &proof2, &root);
-        assert!(is_valid2);
+        assert_eq!(Contract::get_merkle_root(&env, HashScheme::Legacy, &rank_hash, &proof), root);
+        assert_eq!(Contract::get_merkle_root(&env, HashScheme::Legacy, &rank_hash2, &proof2), root);
+    }
+    fn hash_hidden_rank(env: &Env, hidden_rank: &HiddenRank) -> HiddenRankHash {
+        Contract::commitment_hash(env, HashScheme::Tagged, HASH_TAG_LEAF, &hidden_rank.clone().to_xdr(env))
+    }
+    #[test]
+    fn test_validate_rank_proofs_binds_leaf_slots() {
+        let env = Env::default();
+        // the host owns three pawns, so the committed tree has three leaves padded to depth 2
+        let mut game_state = create_test_game_state(&env, true, true);
+        let extra_pos = Pos { x: 2, y: 0 };
+        game_state.pawns.push_back(Contract::pack_pawn(create_collision_pawn(&env, Contract::encode_pawn_id(extra_pos, 0), extra_pos, 4)));
+        let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+        let rank_slots = Contract::get_rank_slots(&env, &pawns_map, UserIndex::Host);
+        assert_eq!(rank_slots.len(), 3);
+        assert_eq!(Contract::get_merkle_depth(rank_slots.len()), 2);
+        let mut hidden_ranks = Vec::new(&env);
+        let mut leaves = Vec::new(&env);
+        for (pawn_id, slot) in rank_slots.iter() {
+            assert_eq!(slot, hidden_ranks.len());
+            let hidden_rank = HiddenRank { pawn_id, rank: 4, salt: 100 + slot as u64 };
+            leaves.push_back(hash_hidden_rank(&env, &hidden_rank));
+            hidden_ranks.push_back(hidden_rank);
+        }
+        let (tree_root, tree) = crate::test_utils::build_merkle_tree_with_scheme(&env, leaves.clone(), HashScheme::Tagged);
+        let root = Contract::rank_commitment(&env, HashScheme::Tagged, &tree_root, 3);
+        let proofs = Vec::from_array(&env, [tree.generate_proof(&env, 0), tree.generate_proof(&env, 1), tree.generate_proof(&env, 2)]);
+        assert!(Contract::validate_rank_proofs(&env, HashScheme::Tagged, &hidden_ranks, &proofs, &rank_slots, &root));
+        // a bare tree root without the leaf count is not a valid commitment
+        assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &hidden_ranks, &proofs, &rank_slots, &tree_root));
+        // proofs are positional: swapping two of them fails even though both leaves are in the tree
+        let swapped = Vec::from_array(&env, [proofs.get_unchecked(1), proofs.get_unchecked(0), proofs.get_unchecked(2)]);
+        assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &hidden_ranks, &swapped, &rank_slots, &root));
+        // an opponent's pawn has no slot in this tree
+        let guest_rank = Vec::from_array(&env, [HiddenRank { pawn_id: Contract::encode_pawn_id(Pos { x: 0, y: 1 }, 1), rank: 0, salt: 100 }]);
+        assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &guest_rank, &Vec::from_array(&env, [proofs.get_unchecked(0)]), &rank_slots, &root));
+        // a spare fourth leaf holding a different rank for the first pawn can't be revealed
+        let mut cheat_leaves = leaves.clone();
+        let alternate_rank = HiddenRank { pawn_id: hidden_ranks.get_unchecked(0).pawn_id, rank: 10, salt: 999 };
+        cheat_leaves.push_back(hash_hidden_rank(&env, &alternate_rank));
+        let (cheat_tree_root, cheat_tree) = crate::test_utils::build_merkle_tree_with_scheme(&env, cheat_leaves, HashScheme::Tagged);
+        for leaf_count in [3u32, 4u32] {
+            let cheat_root = Contract::rank_commitment(&env, HashScheme::Tagged, &cheat_tree_root, leaf_count);
+            let at_spare_leaf = Vec::from_array(&env, [cheat_tree.generate_proof(&env, 3)]);
+            assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &Vec::from_array(&env, [alternate_rank.clone()]), &at_spare_leaf, &rank_slots, &cheat_root));
+            let mut relabeled = cheat_tree.generate_proof(&env, 3);
+            relabeled.leaf_index = 0;
+            assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &Vec::from_array(&env, [alternate_rank.clone()]), &Vec::from_array(&env, [relabeled]), &rank_slots, &cheat_root));
+        }
+        // a deeper tree can't be passed off at the committed depth
+        let mut deep_leaves = leaves.clone();
+        for _ in 0..3 {
+            deep_leaves.push_back(hash_hidden_rank(&env, &alternate_rank));
+        }
+        let (deep_tree_root, deep_tree) = crate::test_utils::build_merkle_tree_with_scheme(&env, deep_leaves, HashScheme::Tagged);
+        let deep_root = Contract::rank_commitment(&env, HashScheme::Tagged, &deep_tree_root, 3);
+        let deep_proof = Vec::from_array(&env, [deep_tree.generate_proof(&env, 0)]);
+        assert_eq!(deep_proof.get_unchecked(0).siblings.len(), 3);
+        assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &Vec::from_array(&env, [hidden_ranks.get_unchecked(0)]), &deep_proof, &rank_slots, &deep_root));
+    }
+    #[test]
+    fn test_legacy_matches_pre_tagging_root() {
+        let env = Env::default();
+        // leaves and nodes hashed the way clients committed before tagging, with fixed 16 byte halves
+        let truncate = |bytes: &Bytes| -> BytesN<16> {
+            let full_hash = env.crypto().sha256(bytes).to_bytes().to_array();
+            BytesN::from_array(&env, &full_hash[0..16].try_into().unwrap())
+        };
+        let hidden_ranks = Vec::from_array(&env, [
+            HiddenRank { pawn_id: 1, rank: 0, salt: 11 },
+            HiddenRank { pawn_id: 2, rank: 4, salt: 22 },
+            HiddenRank { pawn_id: 3, rank: 9, salt: 33 },
+            HiddenRank { pawn_id: 4, rank: 11, salt: 44 },
+        ]);
+        let leaves: std::vec::Vec<BytesN<16>> = hidden_ranks.iter().map(|hidden_rank| truncate(&hidden_rank.to_xdr(&env))).collect();
+        let node = |left: &BytesN<16>, right: &BytesN<16>| -> BytesN<16> {
+            let mut combined_bytes = [0u8; 32];
+            combined_bytes[0..16].copy_from_slice(&left.to_array());
+            combined_bytes[16..32].copy_from_slice(&right.to_array());
+            truncate(&Bytes::from_array(&env, &combined_bytes))
+        };
+        let (left, right) = (node(&leaves[0], &leaves[1]), node(&leaves[2], &leaves[3]));
+        let baseline_root: MerkleHash = node(&left, &right).into();
+        let mut proofs = Vec::new(&env);
+        let mut rank_slots = Map::new(&env);
+        for (i, hidden_rank) in hidden_ranks.iter().enumerate() {
+            let sibling: Bytes = leaves[i ^ 1].clone().into();
+            let uncle: Bytes = if i < 2 { right.clone().into() } else { left.clone().into() };
+            proofs.push_back(MerkleProof { leaf_index: i as u32, siblings: Vec::from_array(&env, [sibling, uncle]) });
+            rank_slots.set(hidden_rank.pawn_id, i as u32);
+        }
+        assert_eq!(Contract::rank_commitment(&env, HashScheme::Legacy, &baseline_root, 4), baseline_root);
+        assert!(Contract::validate_rank_proofs(&env, HashScheme::Legacy, &hidden_ranks, &proofs, &rank_slots, &baseline_root));
+        // and move hashes are still the truncated sha256 of the move
+        let hidden_move = HiddenMove { pawn_id: 1, salt: 55, start_pos: Pos { x: 0, y: 3 }, target_pos: Pos { x: 0, y: 4 } };
+        let baseline_move_hash: Bytes = truncate(&hidden_move.clone().to_xdr(&env)).into();
+        assert_eq!(Contract::move_commitment(&env, HashScheme::Legacy, 1, 2, UserIndex::Host, &hidden_move), baseline_move_hash);
+    }
+    #[test]
+    fn test_commitment_hash_schemes() {
+        let env = Env::default();
+        let hidden_rank = HiddenRank { pawn_id: 100, rank: 5, salt: 1234 };
+        let data = hidden_rank.to_xdr(&env);
+        // legacy is the old truncated sha256, so clients that hash the old way still commit correctly
+        let full_hash = env.crypto().sha256(&data).to_bytes().to_array();
+        let legacy = Contract::commitment_hash(&env, HashScheme::Legacy, HASH_TAG_LEAF, &data);
+        assert_eq!(legacy, Bytes::from_slice(&env, &full_hash[0..16]));
+        assert_eq!(Contract::commitment_hash(&env, HashScheme::Legacy, HASH_TAG_NODE, &data), legacy);
+        // tagged schemes separate leaves, nodes, moves and roots over the same bytes
+        for hash_scheme in [HashScheme::Tagged, HashScheme::Tagged32, HashScheme::Bound, HashScheme::Bound32] {
+            let leaf = Contract::commitment_hash(&env, hash_scheme, HASH_TAG_LEAF, &data);
+            let node = Contract::commitment_hash(&env, hash_scheme, HASH_TAG_NODE, &data);
+            let hidden_move = Contract::commitment_hash(&env, hash_scheme, HASH_TAG_MOVE, &data);
+            let root = Contract::commitment_hash(&env, hash_scheme, HASH_TAG_ROOT, &data);
+            assert_eq!(leaf.len(), Contract::hash_length(hash_scheme));
+            assert_ne!(leaf, legacy);
+            assert_ne!(leaf, node);
+            assert_ne!(leaf, hidden_move);
+            assert_ne!(node, hidden_move);
+            assert_ne!(root, node);
+        }
+        // the scheme byte is part of the preimage, so Tagged32 is not Tagged with more bytes
+        let tagged = Contract::commitment_hash(&env, HashScheme::Tagged, HASH_TAG_LEAF, &data);
+        let tagged32 = Contract::commitment_hash(&env, HashScheme::Tagged32, HASH_TAG_LEAF, &data);
+        assert_eq!(tagged32.len(), 32);
+        assert_ne!(tagged32.slice(0..16), tagged);
+    }
+    #[test]
+    fn test_move_commitment_binding() {
+        let env = Env::default();
+        let hidden_move = HiddenMove { pawn_id: 100, salt: 1234, start_pos: Pos { x: 0, y: 3 }, target_pos: Pos { x: 0, y: 4 } };
+        // unbound schemes ignore the context so their commitments are unchanged
+        for hash_scheme in [HashScheme::Legacy, HashScheme::Tagged, HashScheme::Tagged32] {
+            let unbound = Contract::move_commitment(&env, hash_scheme, 1, 2, UserIndex::Host, &hidden_move);
+            assert_eq!(unbound, Contract::commitment_hash(&env, hash_scheme, HASH_TAG_MOVE, &hidden_move.clone().to_xdr(&env)));
+            assert_eq!(Contract::move_commitment(&env, hash_scheme, 3, 4, UserIndex::Guest, &hidden_move), unbound);
+        }
+        for hash_scheme in [HashScheme::Bound, HashScheme::Bound32] {
+            let bound = Contract::move_commitment(&env, hash_scheme, 1, 2, UserIndex::Host, &hidden_move);
+            assert_eq!(bound.len(), Contract::hash_length(hash_scheme));
+            assert_ne!(bound, Contract::move_commitment(&env, hash_scheme, 2, 2, UserIndex::Host, &hidden_move));
+            assert_ne!(bound, Contract::move_commitment(&env, hash_scheme, 1, 3, UserIndex::Host, &hidden_move));
+            assert_ne!(bound, Contract::move_commitment(&env, hash_scheme, 1, 2, UserIndex::Guest, &hidden_move));
+        }
+    }
+    #[test]
+    fn test_validate_rank_proofs_tagged32() {
+        let env = Env::default();
+        let game_state = create_test_game_state(&env, true, true);
+        let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+        let rank_slots = Contract::get_rank_slots(&env, &pawns_map, UserIndex::Host);
+        let mut hidden_ranks = Vec::new(&env);
+        let mut leaves = Vec::new(&env);
+        for (pawn_id, slot) in rank_slots.iter() {
+            let hidden_rank = HiddenRank { pawn_id, rank: 4, salt: 100 + slot as u64 };
+            leaves.push_back(Contract::commitment_hash(&env, HashScheme::Tagged32, HASH_TAG_LEAF, &hidden_rank.clone().to_xdr(&env)));
+            hidden_ranks.push_back(hidden_rank);
+        }
+        let (tree_root, tree) = crate::test_utils::build_merkle_tree_with_scheme(&env, leaves.clone(), HashScheme::Tagged32);
+        assert_eq!(tree_root.len(), 32);
+        let root = Contract::rank_commitment(&env, HashScheme::Tagged32, &tree_root, leaves.len());
+        let mut proofs = Vec::new(&env);
+        for i in 0..leaves.len() {
+            proofs.push_back(tree.generate_proof(&env, i));
+        }
+        assert!(Contract::validate_rank_proofs(&env, HashScheme::Tagged32, &hidden_ranks, &proofs, &rank_slots, &root));
+        // the same tree can't be checked under another scheme
+        assert!(!Contract::validate_rank_proofs(&env, HashScheme::Tagged, &hidden_ranks, &proofs, &rank_slots, &root));
+        assert!(!Contract::validate_rank_proofs(&env, HashScheme::Legacy, &hidden_ranks, &proofs, &rank_slots, &root));
     }
     // endregion
 // region encode_pawn_id/decode_pawn_id tests
@@ -415,7 +624,7 @@ fn test_resolve_collision_all_scenarios() {
         rank: Vec::from_array(&env, [4u32]),
         zz_revealed: false,
     };
-    Contract::resolve_collision(&mut higher_rank, &mut lower_rank);
+    Contract::resolve_collision(&Vec::new(&env), &mut higher_rank, &mut lower_rank);
     assert!(higher_rank.alive);
     assert!(!lower_rank.alive);
     let mut pawn_a = PawnState {
@@ -436,7 +645,7 @@ fn test_resolve_collision_all_scenarios() {
         rank: Vec::from_array(&env, [5u32]),
         zz_revealed: false,
     };
-    Contract::resolve_collision(&mut pawn_a, &mut pawn_b);
+    Contract::resolve_collision(&Vec::new(&env), &mut pawn_a, &mut pawn_b);
     assert!(!pawn_a.alive);
     assert!(!pawn_b.alive);
     let mut assassin = PawnState {
@@ -457,7 +666,7 @@ fn test_resolve_collision_all_scenarios() {
         rank: Vec::from_array(&env, [10u32]),
         zz_revealed: false,
     };
-    Contract::resolve_collision(&mut assassin, &mut warlord);
+    Contract::resolve_collision(&Vec::new(&env), &mut assassin, &mut warlord);
     assert!(assassin.alive);
     assert!(!warlord.alive);
     let mut assassin2 = PawnState {
@@ -478,7 +687,7 @@ fn test_resolve_collision_all_scenarios() {
         rank: Vec::from_array(&env, [10u32]),
         zz_revealed: false,
     };
-    Contract::resolve_collision(&mut warlord2, &mut assassin2);
+    Contract::resolve_collision(&Vec::new(&env), &mut warlord2, &mut assassin2);
     assert!(assassin2.alive);
     assert!(!warlord2.alive);
     let mut seer = PawnState {
@@ -499,7 +708,7 @@ fn test_resolve_collision_all_scenarios() {
         rank: Vec::from_array(&env, [11u32]),
         zz_revealed: false,
     };
-    Contract::resolve_collision(&mut seer, &mut trap);
+    Contract::resolve_collision(&Vec::new(&env), &mut seer, &mut trap);
     assert!(seer.alive);
     assert!(!trap.alive);
     let mut seer2 = PawnState {
@@ -520,10 +729,261 @@ fn test_resolve_collision_all_scenarios() {
         rank: Vec::from_array(&env, [11u32]),
         zz_revealed: false,
     };
-    Contract::resolve_collision(&mut trap2, &mut seer2);
+    Contract::resolve_collision(&Vec::new(&env), &mut trap2, &mut seer2);
     assert!(seer2.alive);
     assert!(!trap2.alive);
 }
+fn create_collision_pawn(env: &Env, pawn_id: PawnId, pos: Pos, rank: Rank) -> PawnState {
+    PawnState {
+        pawn_id,
+        alive: true,
+        moved: false,
+        moved_scout: false,
+        pos,
+        rank: Vec::from_array(env, [rank]),
+        zz_revealed: false,
+    }
+}
+fn create_default_combat_outcomes(env: &Env) -> Vec<CombatOutcome> {
+    let mut combat_outcomes = Vec::new(env);
+    for attacker_rank in 0..13u32 {
+        for defender_rank in 0..13u32 {
+            combat_outcomes.push_back(Contract::default_combat_outcome(attacker_rank, defender_rank));
+        }
+    }
+    combat_outcomes
+}
+#[test]
+fn test_default_combat_outcomes_match_classic_rules() {
+    let env = Env::default();
+    let explicit_table = create_default_combat_outcomes(&env);
+    for a_rank in 0..12u32 {
+        for b_rank in 0..12u32 {
+            // the rules resolve_collision hardcoded before combat tables existed
+            let (a_dies, b_dies) = if a_rank == 11 && b_rank == 3 { (true, false) }
+                else if b_rank == 11 && a_rank == 3 { (false, true) }
+                else if a_rank == 10 && b_rank == 1 { (true, false) }
+                else if b_rank == 10 && a_rank == 1 { (false, true) }
+                else if a_rank < b_rank { (true, false) }
+                else if b_rank < a_rank { (false, true) }
+                else { (true, true) };
+            for combat_outcomes in [Vec::new(&env), explicit_table.clone()] {
+                let mut a_pawn = create_collision_pawn(&env, 1, Pos { x: 0, y: 0 }, a_rank);
+                let mut b_pawn = create_collision_pawn(&env, 2, Pos { x: 0, y: 0 }, b_rank);
+                Contract::resolve_collision(&combat_outcomes, &mut a_pawn, &mut b_pawn);
+                assert_eq!(a_pawn.alive, !a_dies, "attacker rank {} vs defender rank {}", a_rank, b_rank);
+                assert_eq!(b_pawn.alive, !b_dies, "attacker rank {} vs defender rank {}", a_rank, b_rank);
+                assert!(a_pawn.zz_revealed && b_pawn.zz_revealed);
+            }
+        }
+    }
+}
+#[test]
+fn test_resolve_collision_custom_combat_outcomes() {
+    let env = Env::default();
+    // attacker wins ties
+    let mut combat_outcomes = create_default_combat_outcomes(&env);
+    for rank in 1..11u32 {
+        combat_outcomes.set(rank * 13 + rank, CombatOutcome::Defender);
+    }
+    let mut attacker = create_collision_pawn(&env, 1, Pos { x: 0, y: 0 }, 5);
+    let mut defender = create_collision_pawn(&env, 2, Pos { x: 0, y: 0 }, 5);
+    Contract::resolve_collision(&combat_outcomes, &mut attacker, &mut defender);
+    assert!(attacker.alive);
+    assert!(!defender.alive);
+    // one-shot traps die after killing
+    let mut combat_outcomes = create_default_combat_outcomes(&env);
+    for rank in 1..11u32 {
+        if rank != 3 {
+            combat_outcomes.set(rank * 13 + 11, CombatOutcome::Both);
+        }
+    }
+    let mut attacker = create_collision_pawn(&env, 1, Pos { x: 0, y: 0 }, 9);
+    let mut trap = create_collision_pawn(&env, 2, Pos { x: 0, y: 0 }, 11);
+    Contract::resolve_collision(&combat_outcomes, &mut attacker, &mut trap);
+    assert!(!attacker.alive);
+    assert!(!trap.alive);
+    let mut seer = create_collision_pawn(&env, 1, Pos { x: 0, y: 0 }, 3);
+    let mut trap = create_collision_pawn(&env, 2, Pos { x: 0, y: 0 }, 11);
+    Contract::resolve_collision(&combat_outcomes, &mut seer, &mut trap);
+    assert!(seer.alive);
+    assert!(!trap.alive);
+    // assassin only beats the warlord when attacking
+    let mut combat_outcomes = create_default_combat_outcomes(&env);
+    combat_outcomes.set(10 * 13 + 1, CombatOutcome::Defender);
+    let mut assassin = create_collision_pawn(&env, 1, Pos { x: 0, y: 0 }, 1);
+    let mut warlord = create_collision_pawn(&env, 2, Pos { x: 0, y: 0 }, 10);
+    Contract::resolve_collision(&combat_outcomes, &mut assassin, &mut warlord);
+    assert!(assassin.alive);
+    assert!(!warlord.alive);
+    let mut assassin = create_collision_pawn(&env, 1, Pos { x: 0, y: 0 }, 1);
+    let mut warlord = create_collision_pawn(&env, 2, Pos { x: 0, y: 0 }, 10);
+    Contract::resolve_collision(&combat_outcomes, &mut warlord, &mut assassin);
+    assert!(!assassin.alive);
+    assert!(warlord.alive);
+}
+#[test]
+fn test_complete_move_resolution_uses_attack_direction() {
+    let env = Env::default();
+    // assassin only beats the warlord when attacking
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.combat_outcomes = create_default_combat_outcomes(&env);
+    lobby_params.combat_outcomes.set(10 * 13 + 1, CombatOutcome::Defender);
+    let host_pos = Pos { x: 0, y: 0 };
+    let guest_pos = Pos { x: 0, y: 1 };
+    let host_id = Contract::encode_pawn_id(host_pos, 0);
+    let guest_id = Contract::encode_pawn_id(guest_pos, 1);
+    let resolve = |host_moves: bool, guest_moves: bool| -> (PawnState, PawnState) {
+        let mut game_state = GameState {
+            moves: Contract::create_empty_moves(&env),
+            pawns: Vec::from_array(&env, [
+                Contract::pack_pawn(create_collision_pawn(&env, host_id, host_pos, 1)),
+                Contract::pack_pawn(create_collision_pawn(&env, guest_id, guest_pos, 10)),
+            ]),
+            rank_roots: Vec::new(&env),
+            turn: 1,
+            turns_without_capture: 0,
+        };
+        if host_moves {
+            let mut host_move = game_state.moves.get_unchecked(0);
+            host_move.move_proofs.push_back(HiddenMove { pawn_id: host_id, salt: 1, start_pos: host_pos, target_pos: guest_pos });
+            game_state.moves.set(0, host_move);
+        }
+        if guest_moves {
+            let mut guest_move = game_state.moves.get_unchecked(1);
+            guest_move.move_proofs.push_back(HiddenMove { pawn_id: guest_id, salt: 2, start_pos: guest_pos, target_pos: host_pos });
+            game_state.moves.set(1, guest_move);
+        }
+        let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+        Contract::complete_move_resolution(&env, &mut game_state, None, &pawns_map, &lobby_params);
+        (Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(0)), Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(1)))
+    };
+    // host assassin attacks a stationary warlord
+    let (assassin, warlord) = resolve(true, false);
+    assert!(assassin.alive);
+    assert!(!warlord.alive);
+    // guest warlord attacks a stationary assassin
+    let (assassin, warlord) = resolve(false, true);
+    assert!(!assassin.alive);
+    assert!(warlord.alive);
+    // both attack, so each attack applies
+    let (assassin, warlord) = resolve(true, true);
+    assert!(!assassin.alive);
+    assert!(!warlord.alive);
+}
+fn create_neither_game_state(env: &Env, pawns: &[(Pos, u32, Rank)], moves: &[(usize, Pos)]) -> GameState {
+    // pawns are (pos, user index, rank), moves are (pawn index, target)
+    let mut game_state = GameState {
+        moves: Contract::create_empty_moves(env),
+        pawns: Vec::new(env),
+        rank_roots: Vec::new(env),
+        turn: 1,
+        turns_without_capture: 0,
+    };
+    for (pos, user_index, rank) in pawns.iter() {
+        game_state.pawns.push_back(Contract::pack_pawn(create_collision_pawn(env, Contract::encode_pawn_id(*pos, *user_index), *pos, *rank)));
+    }
+    for (pawn_index, target_pos) in moves.iter() {
+        let (pos, user_index, _) = pawns[*pawn_index];
+        let mut user_move = game_state.moves.get_unchecked(user_index);
+        user_move.move_proofs.push_back(HiddenMove { pawn_id: Contract::encode_pawn_id(pos, user_index), salt: *pawn_index as u64, start_pos: pos, target_pos: *target_pos });
+        game_state.moves.set(user_index, user_move);
+    }
+    game_state
+}
+#[test]
+fn test_neither_outcome_bounces_attackers() {
+    let env = Env::default();
+    // nobody can hurt anybody
+    let mut lobby_params = create_baseline_valid_params(&env);
+    for _ in 0..13 * 13 {
+        lobby_params.combat_outcomes.push_back(CombatOutcome::Neither);
+    }
+    let positions = |game_state: &mut GameState, lobby_params: &LobbyParameters| -> std::vec::Vec<(Pos, bool)> {
+        let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+        Contract::complete_move_resolution(&env, game_state, None, &pawns_map, lobby_params);
+        game_state.pawns.iter().map(|packed| { let pawn = Contract::unpack_pawn(&env, packed); (pawn.pos, pawn.alive) }).collect()
+    };
+    let (a, b, c, d) = (Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 }, Pos { x: 2, y: 0 }, Pos { x: 0, y: 1 });
+    // attacking a stationary pawn
+    let mut game_state = create_neither_game_state(&env, &[(a, 0, 5), (b, 1, 5)], &[(0, b)]);
+    assert_eq!(positions(&mut game_state, &lobby_params), std::vec![(a, true), (b, true)]);
+    assert_eq!(game_state.turns_without_capture, 1);
+    // both stepping onto the same tile
+    let mut game_state = create_neither_game_state(&env, &[(a, 0, 5), (c, 1, 5)], &[(0, b), (1, b)]);
+    assert_eq!(positions(&mut game_state, &lobby_params), std::vec![(a, true), (c, true)]);
+    // swapping tiles
+    let mut game_state = create_neither_game_state(&env, &[(a, 0, 5), (b, 1, 5)], &[(0, b), (1, a)]);
+    assert_eq!(positions(&mut game_state, &lobby_params), std::vec![(a, true), (b, true)]);
+    // a pawn that stepped into the tile the attacker left gets pushed back as well
+    let mut game_state = create_neither_game_state(&env, &[(a, 0, 5), (b, 1, 5), (d, 1, 5)], &[(0, b), (2, a)]);
+    assert_eq!(positions(&mut game_state, &lobby_params), std::vec![(a, true), (b, true), (d, true)]);
+    // the survivor of a lethal collision keeps the tile
+    let mut game_state = create_neither_game_state(&env, &[(a, 0, 5), (b, 1, 4)], &[(0, b)]);
+    lobby_params.combat_outcomes = Vec::new(&env);
+    assert_eq!(positions(&mut game_state, &lobby_params), std::vec![(b, true), (b, false)]);
+}
+#[test]
+fn test_needed_rank_proofs_follow_combat_outcomes() {
+    let env = Env::default();
+    let (a, b) = (Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 });
+    let mut game_state = create_neither_game_state(&env, &[(a, 0, 5), (b, 1, 5)], &[(0, b)]);
+    // the defender's rank is still hidden
+    let mut defender = Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(1));
+    defender.rank = Vec::new(&env);
+    game_state.pawns.set(1, Contract::pack_pawn(defender.clone()));
+    let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+    let collisions = Contract::compute_collisions(&env, &game_state, &pawns_map);
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.max_ranks = Vec::from_array(&env, [1u32; 13]);
+    let (h_needed, g_needed) = Contract::derive_needed_rank_proofs(&env, &game_state, &collisions, &pawns_map, &lobby_params);
+    assert!(h_needed.is_empty());
+    assert_eq!(g_needed, Vec::from_array(&env, [defender.pawn_id]));
+    // a rank 5 attacker bounces off everything, so the defender survives whatever it is and keeps its rank hidden
+    lobby_params.combat_outcomes = create_default_combat_outcomes(&env);
+    for defender_rank in 0..13u32 {
+        lobby_params.combat_outcomes.set(5 * 13 + defender_rank, CombatOutcome::Neither);
+    }
+    let (h_needed, g_needed) = Contract::derive_needed_rank_proofs(&env, &game_state, &collisions, &pawns_map, &lobby_params);
+    assert!(h_needed.is_empty() && g_needed.is_empty());
+    Contract::complete_move_resolution(&env, &mut game_state, Some(collisions.clone()), &pawns_map, &lobby_params);
+    let attacker = Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(0));
+    let defender = Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(1));
+    assert!(attacker.alive && defender.alive);
+    assert_eq!((attacker.pos, defender.pos), (a, b));
+    assert!(defender.rank.is_empty() && !defender.zz_revealed);
+    // one rank the defender could have kills it, so it has to prove
+    lobby_params.combat_outcomes.set(5 * 13 + 4, CombatOutcome::Defender);
+    let (_, g_needed) = Contract::derive_needed_rank_proofs(&env, &game_state, &collisions, &pawns_map, &lobby_params);
+    assert_eq!(g_needed.len(), 1);
+}
+#[test]
+fn test_needed_rank_proofs_for_hidden_moves() {
+    let env = Env::default();
+    let (a, b, d) = (Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 }, Pos { x: 1, y: 1 });
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.max_ranks = Vec::from_array(&env, [1u32; 13]);
+    lobby_params.movement_profiles = create_movement_profiles(&env, 3, MovementProfile { can_move: true, diagonal: true, jump: false, max_range: 1 });
+    let needed = |moves: &[(usize, Pos)]| -> Vec<PawnId> {
+        let mut game_state = create_neither_game_state(&env, &[(a, 0, 3)], moves);
+        let mut pawn = Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(0));
+        pawn.rank = Vec::new(&env);
+        game_state.pawns.set(0, Contract::pack_pawn(pawn));
+        let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+        Contract::derive_needed_rank_proofs(&env, &game_state, &Vec::new(&env), &pawns_map, &lobby_params).0
+    };
+    // any movable rank could have stepped forward
+    assert!(needed(&[(0, b)]).is_empty());
+    // only the diagonal rank could have stepped diagonally
+    let pawn_id = Contract::encode_pawn_id(a, 0);
+    assert_eq!(needed(&[(0, d)]), Vec::from_array(&env, [pawn_id]));
+    // and the proven rank is held to its own profile
+    let hidden_move = HiddenMove { pawn_id, salt: 0, start_pos: a, target_pos: d };
+    let (passable_map, pos_to_pawn_id) = Contract::movement_maps(&env, &lobby_params, &Map::new(&env));
+    let target_counts = Map::new(&env);
+    assert!(Contract::is_move_legal(&lobby_params, &Contract::movement_profile(&lobby_params, 3), &hidden_move, &passable_map, &pos_to_pawn_id, &target_counts));
+    assert!(!Contract::is_move_legal(&lobby_params, &Contract::movement_profile(&lobby_params, 4), &hidden_move, &passable_map, &pos_to_pawn_id, &target_counts));
+}
 // endregion
 // region is_scout_move tests
 #[test]
@@ -609,6 +1069,7 @@ fn create_test_game_state(env: &Env, host_flag_alive: bool, guest_flag_alive: bo
         pawns,
         rank_roots: Vec::new(env),
         turn: 1,
+        turns_without_capture: 0,
     }
 }
 #[test]
@@ -617,19 +1078,19 @@ fn test_check_game_over_all_conditions() {
     let game_state = create_test_game_state(&env, true, true);
     let lobby_params = create_baseline_valid_params(&env);
     let result = Contract::check_game_over(&env, &game_state, &lobby_params);
-    assert_eq!(result, Subphase::Both);
+    assert_eq!(result, (Subphase::Both, EndReason::None));
     let game_state = create_test_game_state(&env, true, false);
     let lobby_params = create_baseline_valid_params(&env);
     let result = Contract::check_game_over(&env, &game_state, &lobby_params);
-    assert_eq!(result, Subphase::Host);
+    assert_eq!(result, (Subphase::Host, EndReason::ThroneCaptured));
     let game_state = create_test_game_state(&env, false, true);
     let lobby_params = create_baseline_valid_params(&env);
     let result = Contract::check_game_over(&env, &game_state, &lobby_params);
-    assert_eq!(result, Subphase::Guest);
+    assert_eq!(result, (Subphase::Guest, EndReason::ThroneCaptured));
     let game_state = create_test_game_state(&env, false, false);
     let lobby_params = create_baseline_valid_params(&env);
     let result = Contract::check_game_over(&env, &game_state, &lobby_params);
-    assert_eq!(result, Subphase::None);
+    assert_eq!(result, (Subphase::None, EndReason::ThroneCaptured));
     let mut pawns = Vec::new(&env);
     let other_pawn = PawnState {
         pawn_id: Contract::encode_pawn_id(Pos { x: 1, y: 0 }, 0),
@@ -646,10 +1107,192 @@ fn test_check_game_over_all_conditions() {
         pawns,
         rank_roots: Vec::new(&env),
         turn: 1,
+        turns_without_capture: 0,
     };
     let lobby_params = create_baseline_valid_params(&env);
     let result = Contract::check_game_over(&env, &game_state, &lobby_params);
-    assert_eq!(result, Subphase::Both);
+    assert_eq!(result, (Subphase::Both, EndReason::None));
+}
+fn create_cornered_game_state(env: &Env) -> GameState {
+    // host warrior in the corner is boxed in by its own traps, the guest warrior is free
+    let pawns = Vec::from_array(env, [
+        Contract::pack_pawn(create_collision_pawn(env, Contract::encode_pawn_id(Pos { x: 0, y: 0 }, 0), Pos { x: 0, y: 0 }, 5)),
+        Contract::pack_pawn(create_collision_pawn(env, Contract::encode_pawn_id(Pos { x: 1, y: 0 }, 0), Pos { x: 1, y: 0 }, 11)),
+        Contract::pack_pawn(create_collision_pawn(env, Contract::encode_pawn_id(Pos { x: 0, y: 1 }, 0), Pos { x: 0, y: 1 }, 11)),
+        Contract::pack_pawn(create_collision_pawn(env, Contract::encode_pawn_id(Pos { x: 2, y: 1 }, 1), Pos { x: 2, y: 1 }, 5)),
+    ]);
+    GameState {
+        moves: Contract::create_empty_moves(env),
+        pawns,
+        rank_roots: Vec::new(env),
+        turn: 1,
+        turns_without_capture: 0,
+    }
+}
+fn create_movement_profiles(env: &Env, rank: Rank, profile: MovementProfile) -> Vec<MovementProfile> {
+    let mut movement_profiles = Vec::new(env);
+    for r in 0..13u32 {
+        movement_profiles.push_back(if r == rank { profile } else { Contract::default_movement_profile(r) });
+    }
+    movement_profiles
+}
+#[test]
+fn test_check_turn_limit() {
+    let env = Env::default();
+    // host has the throne and a warrior left, the guest only the throne
+    let mut game_state = create_test_game_state(&env, true, true);
+    let mut lobby_params = create_baseline_valid_params(&env);
+    assert_eq!(Contract::check_turn_limit(&env, &game_state, &lobby_params), (Subphase::Both, EndReason::None));
+    lobby_params.max_turns = 2;
+    assert_eq!(Contract::check_turn_limit(&env, &game_state, &lobby_params), (Subphase::Both, EndReason::None));
+    game_state.turn = 2;
+    assert_eq!(Contract::check_turn_limit(&env, &game_state, &lobby_params), (Subphase::None, EndReason::TurnLimit));
+    lobby_params.turn_limit_adjudication = Adjudication::Material;
+    assert_eq!(Contract::check_turn_limit(&env, &game_state, &lobby_params), (Subphase::Host, EndReason::TurnLimit));
+    lobby_params.max_turns = 0;
+    lobby_params.max_turns_without_capture = 3;
+    game_state.turns_without_capture = 2;
+    assert_eq!(Contract::check_turn_limit(&env, &game_state, &lobby_params), (Subphase::Both, EndReason::None));
+    game_state.turns_without_capture = 3;
+    assert_eq!(Contract::check_turn_limit(&env, &game_state, &lobby_params), (Subphase::Host, EndReason::NoCaptureLimit));
+}
+fn create_mutual_block_game_state(env: &Env) -> (GameState, LobbyParameters) {
+    // both warriors sit diagonally apart, walled in by impassable tiles, so neither side can move
+    let mut game_state = create_test_game_state(env, true, true);
+    let guest_pos = Pos { x: 2, y: 1 };
+    game_state.pawns.push_back(Contract::pack_pawn(create_collision_pawn(env, Contract::encode_pawn_id(guest_pos, 1), guest_pos, 5)));
+    let mut lobby_params = create_baseline_valid_params(env);
+    lobby_params.board.tiles = Vec::from_array(env, [
+        crate::test_utils::pack_tile(&Tile { pos: Pos { x: 0, y: 0 }, passable: true, setup: 0, setup_zone: 1 }),
+        crate::test_utils::pack_tile(&Tile { pos: Pos { x: 1, y: 0 }, passable: true, setup: 0, setup_zone: 1 }),
+        crate::test_utils::pack_tile(&Tile { pos: Pos { x: 2, y: 0 }, passable: false, setup: 2, setup_zone: 1 }),
+        crate::test_utils::pack_tile(&Tile { pos: Pos { x: 0, y: 1 }, passable: true, setup: 1, setup_zone: 1 }),
+        crate::test_utils::pack_tile(&Tile { pos: Pos { x: 1, y: 1 }, passable: false, setup: 2, setup_zone: 1 }),
+        crate::test_utils::pack_tile(&Tile { pos: Pos { x: 2, y: 1 }, passable: true, setup: 1, setup_zone: 1 }),
+    ]);
+    (game_state, lobby_params)
+}
+#[test]
+fn test_check_game_over_mutual_block() {
+    let env = Env::default();
+    let (mut game_state, mut lobby_params) = create_mutual_block_game_state(&env);
+    // even material and no losses settle to a draw under every policy
+    for adjudication in [Adjudication::Draw, Adjudication::Material, Adjudication::DeadMovables] {
+        lobby_params.mutual_block_adjudication = adjudication;
+        assert_eq!(Contract::check_game_over(&env, &game_state, &lobby_params), (Subphase::None, EndReason::MutualBlock));
+    }
+    // the guest has lost a warrior but kept an extra trap off the board
+    let dead_pos = Pos { x: 3, y: 0 };
+    let mut dead_pawn = create_collision_pawn(&env, Contract::encode_pawn_id(dead_pos, 1), dead_pos, 4);
+    dead_pawn.alive = false;
+    game_state.pawns.push_back(Contract::pack_pawn(dead_pawn));
+    let trap_pos = Pos { x: 3, y: 1 };
+    game_state.pawns.push_back(Contract::pack_pawn(create_collision_pawn(&env, Contract::encode_pawn_id(trap_pos, 1), trap_pos, 11)));
+    lobby_params.mutual_block_adjudication = Adjudication::Draw;
+    assert_eq!(Contract::check_game_over(&env, &game_state, &lobby_params), (Subphase::None, EndReason::MutualBlock));
+    lobby_params.mutual_block_adjudication = Adjudication::Material;
+    assert_eq!(Contract::check_game_over(&env, &game_state, &lobby_params), (Subphase::Guest, EndReason::MutualBlock));
+    lobby_params.mutual_block_adjudication = Adjudication::DeadMovables;
+    assert_eq!(Contract::check_game_over(&env, &game_state, &lobby_params), (Subphase::Host, EndReason::MutualBlock));
+}
+#[test]
+fn test_check_game_over_mutual_block_hidden() {
+    let env = Env::default();
+    let (_, mut lobby_params) = create_mutual_block_game_state(&env);
+    lobby_params.max_ranks = Vec::from_array(&env, [1u32; 13]);
+    lobby_params.mutual_block_adjudication = Adjudication::Draw;
+    // the host fills the open corner and the guest is walled in, nobody has revealed anything
+    let hidden_state = |pawns: &[(Pos, u32, Rank)]| -> GameState {
+        let mut game_state = create_neither_game_state(&env, pawns, &[]);
+        for i in 0..game_state.pawns.len() {
+            let mut pawn = Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(i));
+            pawn.rank = Vec::new(&env);
+            game_state.pawns.set(i, Contract::pack_pawn(pawn));
+        }
+        game_state
+    };
+    let (a, b, c, d) = (Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 }, Pos { x: 0, y: 1 }, Pos { x: 2, y: 1 });
+    let game_state = hidden_state(&[(a, 0, 0), (b, 0, 5), (c, 0, 5), (d, 1, 5)]);
+    assert_eq!(Contract::check_game_over(&env, &game_state, &lobby_params), (Subphase::None, EndReason::MutualBlock));
+    // with a free tile the host's hidden pawns can move and only the guest is stuck
+    let game_state = hidden_state(&[(a, 0, 0), (b, 0, 5), (d, 1, 5)]);
+    assert_eq!(Contract::check_game_over(&env, &game_state, &lobby_params), (Subphase::Host, EndReason::Blocked));
+}
+#[test]
+fn test_turns_without_capture_counter() {
+    let env = Env::default();
+    let host_pos = Pos { x: 0, y: 0 };
+    let guest_pos = Pos { x: 0, y: 1 };
+    let host_id = Contract::encode_pawn_id(host_pos, 0);
+    let guest_id = Contract::encode_pawn_id(guest_pos, 1);
+    let mut game_state = GameState {
+        moves: Contract::create_empty_moves(&env),
+        pawns: Vec::from_array(&env, [
+            Contract::pack_pawn(create_collision_pawn(&env, host_id, host_pos, 5)),
+            Contract::pack_pawn(create_collision_pawn(&env, guest_id, guest_pos, 4)),
+        ]),
+        rank_roots: Vec::new(&env),
+        turn: 1,
+        turns_without_capture: 4,
+    };
+    let lobby_params = create_baseline_valid_params(&env);
+    // a turn with no collision counts up
+    let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+    Contract::complete_move_resolution(&env, &mut game_state, None, &pawns_map, &lobby_params);
+    assert_eq!(game_state.turns_without_capture, 5);
+    // a collision that kills resets it
+    let mut host_move = game_state.moves.get_unchecked(0);
+    host_move.move_proofs.push_back(HiddenMove { pawn_id: host_id, salt: 1, start_pos: host_pos, target_pos: guest_pos });
+    game_state.moves.set(0, host_move);
+    let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+    Contract::complete_move_resolution(&env, &mut game_state, None, &pawns_map, &lobby_params);
+    assert_eq!(game_state.turns_without_capture, 0);
+    assert!(!Contract::unpack_pawn(&env, game_state.pawns.get_unchecked(1)).alive);
+}
+#[test]
+fn test_check_game_over_movement_profiles() {
+    let env = Env::default();
+    let game_state = create_cornered_game_state(&env);
+    let lobby_params = create_baseline_valid_params(&env);
+    let result = Contract::check_game_over(&env, &game_state, &lobby_params);
+    assert_eq!(result, (Subphase::Guest, EndReason::Blocked));
+    // a diagonal step escapes the corner
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.movement_profiles = create_movement_profiles(&env, 5, MovementProfile { can_move: true, diagonal: true, jump: false, max_range: 1 });
+    let result = Contract::check_game_over(&env, &game_state, &lobby_params);
+    assert_eq!(result, (Subphase::Both, EndReason::None));
+    // so does jumping over the trap
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.movement_profiles = create_movement_profiles(&env, 5, MovementProfile { can_move: true, diagonal: false, jump: true, max_range: 2 });
+    let result = Contract::check_game_over(&env, &game_state, &lobby_params);
+    assert_eq!(result, (Subphase::Both, EndReason::None));
+    // but not without the range to land past it
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.movement_profiles = create_movement_profiles(&env, 5, MovementProfile { can_move: true, diagonal: false, jump: true, max_range: 1 });
+    let result = Contract::check_game_over(&env, &game_state, &lobby_params);
+    assert_eq!(result, (Subphase::Guest, EndReason::Blocked));
+}
+#[test]
+fn test_generate_valid_move_req_movement_profiles() {
+    let env = Env::default();
+    let game_state = create_cornered_game_state(&env);
+    let pawns_map = Contract::create_pawns_map(&env, &game_state.pawns);
+    let mut host_ranks = Vec::new(&env);
+    for (pawn_id, (_, pawn)) in pawns_map.iter() {
+        if Contract::decode_pawn_id(pawn_id).1 == UserIndex::Host {
+            host_ranks.push_back(HiddenRank { pawn_id, rank: pawn.rank.get_unchecked(0), salt: 0 });
+        }
+    }
+    let lobby_params = create_baseline_valid_params(&env);
+    assert!(crate::test_utils::generate_valid_move_req(&env, &pawns_map, &lobby_params, &UserIndex::Host, &host_ranks, 7).is_none());
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.movement_profiles = create_movement_profiles(&env, 5, MovementProfile { can_move: true, diagonal: true, jump: false, max_range: 1 });
+    let diagonal_move = crate::test_utils::generate_valid_move_req(&env, &pawns_map, &lobby_params, &UserIndex::Host, &host_ranks, 7).unwrap();
+    assert_eq!(diagonal_move.target_pos, Pos { x: 1, y: 1 });
+    let mut lobby_params = create_baseline_valid_params(&env);
+    lobby_params.movement_profiles = create_movement_profiles(&env, 5, MovementProfile { can_move: true, diagonal: false, jump: true, max_range: 2 });
+    let jump_move = crate::test_utils::generate_valid_move_req(&env, &pawns_map, &lobby_params, &UserIndex::Host, &host_ranks, 7).unwrap();
+    assert_eq!(jump_move.target_pos, Pos { x: 2, y: 0 });
 }
 // endregion
 }
\ No newline at end of file
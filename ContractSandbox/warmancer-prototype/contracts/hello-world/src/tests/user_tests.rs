@@ -0,0 +1,108 @@
+#![cfg(test)]
+#![allow(unused_variables)]
+extern crate std;
+use super::super::*;
+use super::super::test_utils::*;
+use super::test_utils::*;
+use soroban_sdk::testutils::Ledger as _;
+
+// region user record tests
+#[test]
+fn test_finished_game_updates_ratings() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    setup.client.leave_lobby(&guest);
+    let host_user = setup.client.get_user(&host);
+    let guest_user = setup.client.get_user(&guest);
+    assert_eq!(host_user.rating, DEFAULT_RATING + 16);
+    assert_eq!(guest_user.rating, DEFAULT_RATING - 16);
+    assert_eq!((host_user.wins, host_user.losses, host_user.draws), (1, 0, 0));
+    assert_eq!((guest_user.wins, guest_user.losses, guest_user.draws), (0, 1, 0));
+    assert_eq!(host_user.games_completed, 1);
+    assert_eq!(guest_user.games_completed, 1);
+    assert_eq!(guest_user.current_lobby, 0);
+    assert_eq!(host_user.current_lobby, lobby_id);
+}
+#[test]
+fn test_dev_mode_game_is_unrated() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    let guest = setup.generate_address();
+    let params = create_test_lobby_parameters(&setup.env);
+    assert!(params.dev_mode);
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: params });
+    setup.client.join_lobby(&guest, &JoinLobbyReq { lobby_id });
+    setup.client.surrender(&host, &SurrenderReq { lobby_id });
+    let host_user = setup.client.get_user(&host);
+    assert_eq!(host_user.rating, DEFAULT_RATING);
+    assert_eq!(host_user.losses, 0);
+    assert_eq!(host_user.games_completed, 0);
+}
+#[test]
+fn test_draw_and_abort_counts() {
+    let setup = TestSetup::new();
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    let mut lobby_info = snapshot.lobby_info;
+    Contract::end_game(&mut lobby_info, Phase::Finished, Subphase::None, EndReason::ThroneCaptured, Subphase::None);
+    setup.env.as_contract(&setup.contract_id, || {
+        Contract::record_game_result(&setup.env, &lobby_info, &snapshot.lobby_parameters);
+    });
+    let host_user = setup.client.get_user(&host);
+    assert_eq!(host_user.draws, 1);
+    assert_eq!(host_user.rating, DEFAULT_RATING);
+    assert_eq!(setup.client.get_user(&guest).draws, 1);
+    // aborts are unrated and only counted against the player responsible
+    let (lobby_id, host, guest) = setup_lobby_for_commit_setup(&setup);
+    setup.client.commit_setup(&host, &CommitSetupReq {
+        lobby_id,
+        rank_commitment_root: Bytes::from_array(&setup.env, &[1u8; 16]),
+        zz_hidden_ranks: Vec::new(&setup.env),
+    });
+    let snapshot = extract_lobby_snapshot(&setup.env, &setup.contract_id, lobby_id);
+    setup.env.ledger().with_mut(|l| l.sequence_number = snapshot.lobby_info.last_edited_ledger_seq + 100);
+    setup.client.redeem_win(&host, &RedeemWinReq { lobby_id });
+    let host_user = setup.client.get_user(&host);
+    let guest_user = setup.client.get_user(&guest);
+    assert_eq!(host_user.aborts, 0);
+    assert_eq!(guest_user.aborts, 1);
+    assert_eq!(guest_user.rating, DEFAULT_RATING);
+    assert_eq!(guest_user.games_completed, 0);
+}
+#[test]
+fn test_get_user_not_found() {
+    let setup = TestSetup::new();
+    let stranger = setup.generate_address();
+    let result = setup.client.try_get_user(&stranger);
+    assert_eq!(result.unwrap_err().unwrap(), Error::NotFound);
+}
+#[test]
+fn test_next_rating() {
+    assert_eq!(Contract::next_rating(1200, 1200, 1000), 1216);
+    assert_eq!(Contract::next_rating(1200, 1200, 0), 1184);
+    assert_eq!(Contract::next_rating(1200, 1200, 500), 1200);
+    // favourites gain less for a win and lose more for a loss
+    assert_eq!(Contract::next_rating(1400, 1200, 1000), 1407);
+    assert_eq!(Contract::next_rating(1200, 1400, 1000), 1224);
+    assert_eq!(Contract::next_rating(2400, 1200, 0), 2369);
+    assert_eq!(Contract::next_rating(10, 1200, 0), 10);
+    assert_eq!(Contract::next_rating(0, 0, 0), 0);
+}
+#[test]
+fn test_pre_upgrade_user_is_upgraded_on_read() {
+    let setup = TestSetup::new();
+    let host = setup.generate_address();
+    setup.env.as_contract(&setup.contract_id, || {
+        setup.env.storage().persistent().set(&DataKey::User(host.clone()), &UserV0 { current_lobby: 0, games_completed: 3 });
+    });
+    let user = setup.client.get_user(&host);
+    assert_eq!(user.games_completed, 3);
+    assert_eq!(user.rating, DEFAULT_RATING);
+    // the next write stores the current layout
+    let lobby_id = setup.client.make_lobby(&host, &MakeLobbyReq { parameters: create_test_lobby_parameters(&setup.env) });
+    setup.env.as_contract(&setup.contract_id, || {
+        let user: User = setup.env.storage().persistent().get(&DataKey::User(host.clone())).unwrap();
+        assert_eq!((user.current_lobby, user.games_completed), (lobby_id, 3));
+    });
+}
+// endregion
@@ -11,7 +11,6 @@ fn test_move_to_enemy_occupied_tile() {
     let setup = TestSetup::new();
     
     // Create a fresh lobby with hex board
-    let lobby_id = 864275;
     let host_address = <soroban_sdk::Address as soroban_sdk::testutils::Address>::generate(&setup.env);
     let guest_address = <soroban_sdk::Address as soroban_sdk::testutils::Address>::generate(&setup.env);
     
@@ -19,10 +18,7 @@ fn test_move_to_enemy_occupied_tile() {
     let lobby_parameters = crate::test_utils::create_user_board_parameters(&setup.env);
     
     // Make and join lobby
-    setup.client.make_lobby(&host_address, &MakeLobbyReq {
-        lobby_id,
-        parameters: lobby_parameters.clone(),
-    });
+    let lobby_id = setup.client.make_lobby(&host_address, &MakeLobbyReq { parameters: lobby_parameters.clone() });
     setup.client.join_lobby(&guest_address, &JoinLobbyReq { lobby_id });
     
     // Complete setup phase
@@ -92,11 +88,11 @@ fn test_move_to_enemy_occupied_tile() {
     // Commit moves
     let host_move_serialized = host_move.clone().to_xdr(&setup.env);
     let host_move_full_hash = setup.env.crypto().sha256(&host_move_serialized).to_bytes().to_array();
-    let host_move_hash = HiddenMoveHash::from_array(&setup.env, &host_move_full_hash[0..16].try_into().unwrap());
+    let host_move_hash = HiddenMoveHash::from_slice(&setup.env, &host_move_full_hash[0..16]);
     
     let guest_move_serialized = guest_move.clone().to_xdr(&setup.env);
     let guest_move_full_hash = setup.env.crypto().sha256(&guest_move_serialized).to_bytes().to_array();
-    let guest_move_hash = HiddenMoveHash::from_array(&setup.env, &guest_move_full_hash[0..16].try_into().unwrap());
+    let guest_move_hash = HiddenMoveHash::from_slice(&setup.env, &guest_move_full_hash[0..16]);
     
     setup.client.commit_move(&host_address, &CommitMoveReq {
         lobby_id,